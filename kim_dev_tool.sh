@@ -74,6 +74,7 @@ sudo "$script_dir/kim_temp_bin" stream | while IFS= read -r line; do
     charging=$(echo "$line" | jq -r '.charging')
     mem_free_pct=$(echo "$line" | jq -r '.mem_free_pct')
     efficiency_hrs=$(echo "$line" | jq -r '.efficiency_hrs')
+    screen_w=$(echo "$line" | jq -r '.screen_w')
     wakeups_per_sec=$(echo "$line" | jq -r '.wakeups_per_sec')
 
     # RENDER UI
@@ -94,14 +95,16 @@ sudo "$script_dir/kim_temp_bin" stream | while IFS= read -r line; do
     is_charging="no"; [ "$charging" = "true" ] && is_charging="yes"
     
     # Efficiency recalibration
-    if [ $(echo "$real_total_w > 0.5" | bc -l) -eq 1 ]; then
+    if [ "$efficiency_hrs" = "null" ]; then
+        avg_100_hours="0"
+    elif [ $(echo "$real_total_w > 0.5" | bc -l) -eq 1 ]; then
         avg_100_hours=$(echo "$efficiency_hrs * $power_w / $real_total_w" | bc -l)
     else
         avg_100_hours="99.9"
     fi
     
     # Time Remaining
-    if [ $(echo "$real_total_w > 0" | bc -l) -eq 1 ]; then
+    if [ "$battery_pct" != "null" ] && [ $(echo "$real_total_w > 0" | bc -l) -eq 1 ]; then
         time_left_hrs=$(echo "$avg_100_hours * $battery_pct / 100" | bc -l)
         hrs_int=$(echo "$time_left_hrs" | awk '{print int($1)}')
         mins_int=$(echo "($time_left_hrs - $hrs_int) * 60" | bc -l | awk '{print int($1)}')
@@ -110,6 +113,9 @@ sudo "$script_dir/kim_temp_bin" stream | while IFS= read -r line; do
         time_remaining="--:--"
     fi
 
+    if [ "$battery_pct" = "null" ]; then
+        printf "🔋 BATTERY:    none (desktop Mac)\033[K\n"
+    else
     printf "🔋 BATTERY:    %3d%%   " "$battery_pct"
     if [ "$is_charging" = "yes" ]; then
         printf "\033[32m(Charging)\033[0m\033[K\n"
@@ -123,13 +129,16 @@ sudo "$script_dir/kim_temp_bin" stream | while IFS= read -r line; do
     printf "   ├─ Power Draw:  %s W\033[K\n" "$real_total_w"
     printf "   ├─ Time Left:   %s (est)\033[K\n" "$time_remaining"
     printf "   └─ Live @100%%: %.1fh\033[K\n" "$avg_100_hours"
+    fi
     
     echo ""
     
     # Power Breakdown
-    # Screen is diff between Battery Rail (Total) and System Logic (PSTR)
-    screen_mw=$(echo "($bat_power_w - $power_w) * 1000" | bc -l | awk '{print int($1)}')
-    [ $(echo "$screen_mw < 0" | bc -l) -eq 1 ] && screen_mw=0
+    # Screen is diff between Battery Rail (Total) and System Logic (PSTR), computed by kim_temp.
+    # null on Macs without a built-in panel or battery (Mac mini/Studio).
+    if [ "$screen_w" != "null" ]; then
+        screen_mw=$(echo "$screen_w * 1000" | bc -l | awk '{print int($1)}')
+    fi
     
     # Misc is System Logic - Components
     # Note: PSTR includes CPU/GPU/ANE/Memory and Logic Board overhead
@@ -143,7 +152,11 @@ sudo "$script_dir/kim_temp_bin" stream | while IFS= read -r line; do
     printf "   ├─ CPU:     %5d mW\033[K\n" "$cpu_mw"
     printf "   ├─ GPU:     %5d mW\033[K\n" "$gpu_mw"
     printf "   ├─ ANE:     %5d mW\033[K\n" "$ane_mw"
-    printf "   ├─ Screen:  %5d mW   (Est. from rail diff)\033[K\n" "$screen_mw"
+    if [ "$screen_w" != "null" ]; then
+        printf "   ├─ Screen:  %5d mW   (Est. from rail diff)\033[K\n" "$screen_mw"
+    else
+        printf "   ├─ Screen:    n/a     (no built-in display)\033[K\n"
+    fi
     printf "   └─ Misc:    %5d mW   (Memory, WiFi, SSD, Losses)\033[K\n" "$misc_mw"
     
     echo ""
@@ -165,7 +178,7 @@ sudo "$script_dir/kim_temp_bin" stream | while IFS= read -r line; do
     printf "   ├─ GPU:      %6.1f°C\033[K\n" "$gpu_temp"
     printf "   ├─ Memory:   %6.1f°C\033[K\n" "$mem_temp"
     printf "   ├─ SSD:      %6.1f°C\033[K\n" "$ssd_temp"
-    printf "   └─ Battery:  %6.1f°C\033[K\n" "$bat_temp"
+    [ "$bat_temp" != "null" ] && printf "   └─ Battery:  %6.1f°C\033[K\n" "$bat_temp"
     
     echo ""
     
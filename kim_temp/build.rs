@@ -0,0 +1,12 @@
+// Generates the gRPC service from proto/kim_temp.proto for the `grpc` feature (with a vendored
+// protoc, so nothing has to be installed); other builds have nothing to generate.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/kim_temp.proto");
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform"));
+        tonic_prost_build::configure().build_client(false).compile_protos(&["proto/kim_temp.proto"], &["proto"]).expect("cannot compile proto/kim_temp.proto");
+    }
+}
@@ -0,0 +1,53 @@
+// Adaptive stream interval: the fast rate (1 s, or --interval with --adaptive) on AC or under
+// load, backing off to 15 s once the machine has been on battery, idle and calm for a while and
+// to 30 s if it stays that way, so a long logging session barely adds to the drain it measures.
+// Straight back to the fast rate as soon as it's plugged in, loaded, or power or temperature
+// moves. Entering a slow rate needs several calm samples in a row and leaving it needs one clear
+// change, so the rate doesn't flap on noise.
+
+const FAST_MS: u64 = 1000;
+const IDLE_MS: u64 = 15_000;
+const DEEP_IDLE_MS: u64 = 30_000;
+// Consecutive calm samples before IDLE_MS, and before DEEP_IDLE_MS (two more minutes at IDLE_MS)
+const CALM_SAMPLES: u32 = 10;
+const DEEP_CALM_SAMPLES: u32 = CALM_SAMPLES + 8;
+// Above this the machine is under load, however steady
+const LOAD_POWER_W: f64 = 15.0;
+// Change between samples that counts as activity
+const POWER_STEP_W: f64 = 1.5;
+const TEMP_STEP_C: f64 = 1.0;
+// Below these a sample counts as calm
+const CALM_POWER_STEP_W: f64 = 0.5;
+const CALM_TEMP_STEP_C: f64 = 0.5;
+
+pub struct Controller {
+    last: Option<(f64, f64)>,
+    calm: u32,
+    fast_ms: u64,
+}
+
+impl Default for Controller {
+    fn default() -> Controller {
+        Controller::new(FAST_MS)
+    }
+}
+
+impl Controller {
+    pub fn new(fast_ms: u64) -> Controller {
+        Controller { last: None, calm: 0, fast_ms }
+    }
+
+    // Takes this sample's readings and returns the interval until the next sample, plus whether
+    // a slow (idle) rate is in effect.
+    pub fn next(&mut self, on_battery: bool, power_w: f64, temp_c: f64) -> (u64, bool) {
+        let (dp, dt) = self.last.map(|(p, t)| ((power_w - p).abs(), (temp_c - t).abs())).unwrap_or((0.0, 0.0));
+        self.last = Some((power_w, temp_c));
+        if !on_battery || power_w > LOAD_POWER_W || dp > POWER_STEP_W || dt > TEMP_STEP_C {
+            self.calm = 0;
+        } else if dp < CALM_POWER_STEP_W && dt < CALM_TEMP_STEP_C {
+            self.calm = self.calm.saturating_add(1);
+        }
+        let ms = if self.calm >= DEEP_CALM_SAMPLES { DEEP_IDLE_MS } else if self.calm >= CALM_SAMPLES { IDLE_MS } else { return (self.fast_ms, false) };
+        (ms.max(self.fast_ms), true)
+    }
+}
@@ -0,0 +1,196 @@
+// Threshold alerts evaluated against every stream sample, e.g. `cpu_temp > 95 for 30s`.
+// A rule fires once when its condition has held for the whole duration, and resolves once
+// when it stops holding; both transitions become events in the sample and run the rule's action.
+
+#[cfg(feature = "config")]
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+// One entry of `[[alerts]]` in the config.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(deny_unknown_fields))]
+pub struct AlertConfig {
+    pub name: Option<String>,
+    pub when: String,
+    // Shell command run on fire/resolve, with KIM_ALERT_* variables set
+    pub run: Option<String>,
+    // Post a macOS notification when the alert fires
+    #[cfg_attr(feature = "config", serde(default))]
+    pub notify: bool,
+    pub webhook: Option<crate::webhook::Webhook>,
+    pub escalate: Option<crate::escalation::EscalationConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op { Gt, Ge, Lt, Le, Eq, Ne }
+
+impl Op {
+    fn holds(self, a: f64, b: f64) -> bool {
+        match self { Op::Gt => a > b, Op::Ge => a >= b, Op::Lt => a < b, Op::Le => a <= b, Op::Eq => a == b, Op::Ne => a != b }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub metric: String,
+    op: Op,
+    pub threshold: f64,
+    pub hold: Duration,
+    pub run: Option<String>,
+    pub notify: bool,
+    pub webhook: Option<crate::webhook::Webhook>,
+    pub escalation: Option<crate::escalation::Escalation>,
+}
+
+// "30s", "5m", "1h" or plain seconds.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let (num, mult) = match s.chars().last()? {
+        's' => (&s[..s.len() - 1], 1.0),
+        'm' => (&s[..s.len() - 1], 60.0),
+        'h' => (&s[..s.len() - 1], 3600.0),
+        _ => (s, 1.0),
+    };
+    num.parse::<f64>().ok().filter(|v| *v >= 0.0).map(|v| Duration::from_secs_f64(v * mult))
+}
+
+impl Rule {
+    pub fn from_config(cfg: &AlertConfig) -> Result<Rule, String> {
+        let mut rule = Rule::parse(&cfg.when)?;
+        if let Some(name) = &cfg.name { rule.name = name.clone(); }
+        rule.run = cfg.run.clone();
+        rule.notify = cfg.notify;
+        if let Some(hook) = &cfg.webhook { hook.validate()?; }
+        rule.webhook = cfg.webhook.clone();
+        if let Some(esc) = &cfg.escalate {
+            // Escalation always starts with a notification
+            rule.escalation = Some(crate::escalation::Escalation::from_config(esc)?);
+            rule.notify = true;
+        }
+        Ok(rule)
+    }
+
+    // Parses `metric OP number [for DURATION]`; the rule is named after its condition.
+    pub fn parse(when: &str) -> Result<Rule, String> {
+        let err = || format!("invalid alert {:?} (expected e.g. 'cpu_temp > 95 for 30s')", when);
+        let (cond, hold) = match when.split_once(" for ") {
+            Some((c, d)) => (c, parse_duration(d.trim()).ok_or_else(err)?),
+            None => (when, Duration::ZERO),
+        };
+        let (metric, op, rest) = [(">=", Op::Ge), ("<=", Op::Le), ("==", Op::Eq), ("!=", Op::Ne), (">", Op::Gt), ("<", Op::Lt)].iter()
+            .find_map(|(sym, op)| cond.split_once(sym).map(|(m, r)| (m.trim(), *op, r.trim())))
+            .ok_or_else(err)?;
+        if metric.is_empty() || !metric.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') { return Err(err()); }
+        let threshold: f64 = rest.parse().map_err(|_| err())?;
+        Ok(Rule { name: when.to_string(), metric: metric.to_string(), op, threshold, hold, run: None, notify: false, webhook: None, escalation: None })
+    }
+
+    // Whether the condition is true in this sample, regardless of how long it has held.
+    pub fn holds(&self, metrics: &[(&str, Option<f64>)]) -> bool {
+        metrics.iter().find(|(m, _)| *m == self.metric).and_then(|(_, v)| *v).is_some_and(|v| self.op.holds(v, self.threshold))
+    }
+}
+
+struct State {
+    since: Option<Instant>,
+    firing: bool,
+    escalation: crate::escalation::Progress,
+}
+
+pub struct Engine {
+    rules: Vec<(Rule, State)>,
+    // During quiet hours transitions are still recorded and acted on, but post no notification
+    pub quiet: bool,
+}
+
+impl Engine {
+    pub fn new(rules: Vec<Rule>) -> Engine {
+        if rules.iter().any(|r| r.escalation.as_ref().is_some_and(|e| e.stops_processes())) {
+            crate::escalation::install_exit_guard();
+        }
+        Engine { rules: rules.into_iter().map(|r| (r, State { since: None, firing: false, escalation: Default::default() })).collect(), quiet: false }
+    }
+
+    pub fn is_empty(&self) -> bool { self.rules.is_empty() }
+
+    // Whether any rule watches `metric` (so its collector has to run even if the field isn't output).
+    pub fn uses(&self, metric: &str) -> bool { self.rules.iter().any(|(r, _)| r.metric == metric) }
+
+    // Checks every rule against this sample's metrics; returns alert events as JSON objects.
+    // A metric that is missing or null counts as "condition not met".
+    pub fn evaluate(&mut self, metrics: &[(&str, Option<f64>)]) -> Vec<String> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+        for (rule, state) in &mut self.rules {
+            let value = metrics.iter().find(|(m, _)| *m == rule.metric).and_then(|(_, v)| *v);
+            let holds = value.is_some_and(|v| rule.op.holds(v, rule.threshold));
+            if !holds {
+                state.since = None;
+                if state.firing {
+                    state.firing = false;
+                    events.push(transition(rule, "resolved", value, Duration::ZERO, self.quiet));
+                    if let Some(esc) = &rule.escalation { events.extend(esc.resume(&mut state.escalation, &rule.name)); }
+                }
+                continue;
+            }
+            let since = *state.since.get_or_insert(now);
+            if !state.firing && now.duration_since(since) >= rule.hold {
+                state.firing = true;
+                events.push(transition(rule, "firing", value, now.duration_since(since), self.quiet));
+            }
+            // Escalation stages count from when the alert fired, not from when the condition began
+            if let (true, Some(esc)) = (state.firing, &rule.escalation) {
+                let firing_for = now.duration_since(since).saturating_sub(rule.hold);
+                events.extend(esc.step(&mut state.escalation, firing_for, &rule.name));
+            }
+        }
+        events
+    }
+}
+
+fn transition(rule: &Rule, state: &str, value: Option<f64>, held: Duration, quiet: bool) -> String {
+    if let Some(cmd) = &rule.run { run_action(cmd, rule, state, value); }
+    if rule.notify && !quiet && state == "firing" {
+        let message = match value {
+            Some(v) if held.as_secs() > 0 => format!("{} {:.1} for {}s", rule.metric, v, held.as_secs()),
+            Some(v) => format!("{} {:.1} (limit {})", rule.metric, v, rule.threshold),
+            None => rule.metric.clone(),
+        };
+        notify(&rule.name, &message);
+    }
+    let event = format!("{{\"type\":\"alert\",\"name\":{},\"state\":\"{}\",\"metric\":\"{}\",\"value\":{},\"threshold\":{}}}",
+        crate::json_str(&rule.name), state, rule.metric, crate::json_num(value, 2), rule.threshold);
+    if let Some(hook) = &rule.webhook { hook.send(&event); }
+    event
+}
+
+// Runs the action in the background so a slow command never stalls sampling.
+fn run_action(cmd: &str, rule: &Rule, state: &str, value: Option<f64>) {
+    let child = std::process::Command::new("sh").args(["-c", cmd])
+        .env("KIM_ALERT_NAME", &rule.name)
+        .env("KIM_ALERT_STATE", state)
+        .env("KIM_ALERT_METRIC", &rule.metric)
+        .env("KIM_ALERT_VALUE", value.map(|v| format!("{:.2}", v)).unwrap_or_default())
+        .env("KIM_ALERT_THRESHOLD", rule.threshold.to_string())
+        .stdout(std::process::Stdio::null())
+        .spawn();
+    match child {
+        Ok(mut c) => { std::thread::spawn(move || { let _ = c.wait(); }); }
+        Err(e) => crate::diag::warn!("alert action for {} failed: {}", rule.name, e),
+    }
+}
+
+// AppleScript string literal
+fn applescript_str(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Posts a Notification Center banner via osascript, without waiting for it.
+pub fn notify(title: &str, message: &str) {
+    let script = format!("display notification {} with title {}", applescript_str(message), applescript_str(&format!("kim_temp: {}", title)));
+    match std::process::Command::new("osascript").args(["-e", &script]).stdout(std::process::Stdio::null()).spawn() {
+        Ok(mut c) => { std::thread::spawn(move || { let _ = c.wait(); }); }
+        Err(e) => crate::diag::warn!("notification failed: {}", e),
+    }
+}
@@ -0,0 +1,194 @@
+// Battery presence and state. Desktop Macs (mini/Studio/Pro) have no AppleSmartBattery,
+// in which case every battery-derived field is reported as null instead of made-up numbers.
+
+pub struct Battery {
+    pub design_wh: f32,
+}
+
+// Queries AppleSmartBattery once; None when the machine has no battery.
+pub fn detect() -> Option<Battery> {
+    let ioreg_output = crate::subprocess::stdout(std::process::Command::new("ioreg").args(["-r", "-c", "AppleSmartBattery"])).unwrap_or_default();
+    if ioreg_output.trim().is_empty() || ioreg_output.contains("\"BatteryInstalled\" = No") {
+        return None;
+    }
+    let battery_mah: f32 = ioreg_output.lines().find(|l| l.contains("\"DesignCapacity\"")).and_then(|l| l.split('=').nth(1).and_then(|s| s.trim().parse().ok())).unwrap_or(4500.0);
+    Some(Battery { design_wh: battery_mah * 11.4 / 1000.0 })
+}
+
+// Battery percentage and charging state from `pmset -g batt`.
+pub fn pmset_status() -> (i32, bool) {
+    let battery_output = crate::subprocess::stdout(std::process::Command::new("pmset").args(["-g", "batt"])).unwrap_or_default();
+    let battery_pct: i32 = battery_output.split('%').next().and_then(|s| s.split_whitespace().last()).and_then(|s| s.parse().ok()).unwrap_or(0);
+    let charging = battery_output.contains("; charging;") || (battery_output.contains("AC Power") && !battery_output.contains("discharging"));
+    (battery_pct, charging)
+}
+
+// Power adapter as reported by AppleSmartBattery.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adapter {
+    // Negotiated wattage (USB-PD contract), e.g. 96, or less when a dock shares power
+    pub watts: Option<u32>,
+    // Power currently drawn from the adapter
+    pub input_w: Option<f64>,
+    // What the charging path loses converting it (AdapterEfficiencyLoss)
+    pub loss_w: Option<f64>,
+    // Power going into the battery by the gas gauge (voltage × current); None unless it is charging
+    pub charge_w: Option<f64>,
+}
+
+impl Adapter {
+    // `charge_overhead_w`: adapter input not accounted for by the system load or the battery
+    // charge, i.e. what conversion and charging lose on the way in. Only while charging, since
+    // that is when all three terms are measured; comparing it across chargers and cables at a
+    // similar load shows which one wastes more. Can go slightly negative when the three sources
+    // sample at different moments.
+    pub fn overhead_w(&self, load_w: f64) -> Option<f64> {
+        Some(self.input_w? - load_w - self.charge_w?)
+    }
+}
+
+// Reads `Name=value` out of an ioreg inline dictionary like {"Watts"=96,"Description"="pd charger"}.
+fn dict_field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{}\"=", name))? + name.len() + 3;
+    let rest = &line[start..];
+    Some(rest[..rest.find([',', '}']).unwrap_or(rest.len())].trim())
+}
+
+// None when no adapter is connected (or there is no battery to report one).
+pub fn adapter() -> Option<Adapter> {
+    let ioreg_output = crate::subprocess::stdout(std::process::Command::new("ioreg").args(["-r", "-c", "AppleSmartBattery"])).unwrap_or_default();
+    if !ioreg_output.contains("\"ExternalConnected\" = Yes") { return None; }
+    let watts = ioreg_output.lines().find(|l| l.contains("\"AdapterDetails\"")).and_then(|l| dict_field(l, "Watts")).and_then(|v| v.parse().ok());
+    // SystemPowerIn is in mW
+    let telemetry = |name: &str| ioreg_output.lines().find(|l| l.contains("\"PowerTelemetryData\"")).and_then(|l| dict_field(l, name)).and_then(|v| v.parse::<f64>().ok()).map(|mw| mw / 1000.0);
+    let charge_w = gauge_w(&ioreg_output).filter(|w| *w > 0.0);
+    Some(Adapter { watts, input_w: telemetry("SystemPowerIn"), loss_w: telemetry("AdapterEfficiencyLoss"), charge_w })
+}
+
+// ioreg reports negative currents as wrapped unsigned 64-bit numbers.
+fn ioreg_int(output: &str, name: &str) -> Option<i64> {
+    let v = output.lines().find(|l| l.trim_start().starts_with(&format!("\"{}\" =", name)))?.split('=').nth(1)?.trim();
+    v.parse::<i64>().ok().or_else(|| v.parse::<u64>().ok().map(|u| u as i64))
+}
+
+// Battery power by the gas gauge (voltage × current): positive while charging, negative while
+// discharging.
+fn gauge_w(ioreg_output: &str) -> Option<f64> {
+    let mv = ioreg_int(ioreg_output, "Voltage")?;
+    let ma = ioreg_int(ioreg_output, "InstantAmperage").or_else(|| ioreg_int(ioreg_output, "Amperage"))?;
+    Some(mv as f64 * ma as f64 / 1e6)
+}
+
+// Power leaving the battery according to the gas gauge, independent of the SMC rails; None
+// while charging or without a battery.
+pub fn discharge_w() -> Option<f64> {
+    let ioreg_output = crate::subprocess::stdout(std::process::Command::new("ioreg").args(["-r", "-c", "AppleSmartBattery"]))?;
+    gauge_w(&ioreg_output).filter(|w| *w < 0.0).map(|w| -w)
+}
+
+// Observed drain rate: a least-squares slope of battery % over the last few minutes of
+// discharging, instead of design capacity divided by instantaneous power. pmset only reports
+// whole percents, so nothing is estimated until the window spans a couple of minutes and at
+// least one percent has actually been used.
+#[derive(Default)]
+pub struct Drain {
+    points: std::collections::VecDeque<(std::time::Instant, f64)>,
+}
+
+const DRAIN_WINDOW: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+const DRAIN_MIN_SPAN: std::time::Duration = std::time::Duration::from_secs(120);
+
+impl Drain {
+    // Returns (drain_pct_per_hr, est_hours_remaining); history restarts whenever the battery charges.
+    pub fn observe(&mut self, now: std::time::Instant, pct: i32, charging: bool) -> (Option<f64>, Option<f64>) {
+        if charging { self.points.clear(); return (None, None); }
+        self.points.push_back((now, pct as f64));
+        while self.points.front().is_some_and(|(t, _)| now.duration_since(*t) > DRAIN_WINDOW) { self.points.pop_front(); }
+        let (first, _) = self.points[0];
+        let used = self.points.iter().map(|(_, p)| *p).fold(f64::MIN, f64::max) - pct as f64;
+        if now.duration_since(first) < DRAIN_MIN_SPAN || used < 1.0 { return (None, None); }
+        let hours = |t: &std::time::Instant| t.duration_since(first).as_secs_f64() / 3600.0;
+        let n = self.points.len() as f64;
+        let (mx, my) = (self.points.iter().map(|(t, _)| hours(t)).sum::<f64>() / n, self.points.iter().map(|(_, p)| p).sum::<f64>() / n);
+        let sxx: f64 = self.points.iter().map(|(t, _)| (hours(t) - mx).powi(2)).sum();
+        let sxy: f64 = self.points.iter().map(|(t, y)| (hours(t) - mx) * (y - my)).sum();
+        let drain = -sxy / sxx;
+        if !drain.is_finite() || drain <= 0.0 { return (None, None); }
+        (Some(drain), Some(pct as f64 / drain))
+    }
+}
+
+// The moment a charge reaches 100%: armed by charging below 100%, so a stream started on a full
+// battery, or one held at a charge limit, never fires. Once per charge.
+#[derive(Default)]
+pub struct FullCharge {
+    armed: bool,
+}
+
+impl FullCharge {
+    pub fn observe(&mut self, pct: i32, charging: bool) -> bool {
+        if pct < 100 { self.armed |= charging; return false; }
+        std::mem::take(&mut self.armed)
+    }
+}
+
+fn ioreg_str(output: &str, name: &str) -> Option<String> {
+    let v = output.lines().find(|l| l.trim_start().starts_with(&format!("\"{}\" =", name)))?.split_once('=')?.1.trim();
+    Some(v.trim_matches('"').to_string())
+}
+
+fn ioreg_bool(output: &str, name: &str) -> Option<bool> {
+    ioreg_str(output, name).map(|v| v == "Yes")
+}
+
+// `battery-health`: long-term state of the cell from AppleSmartBattery.
+pub struct Health {
+    pub cycle_count: Option<i64>,
+    pub design_cycle_count: Option<i64>,
+    pub design_mah: Option<i64>,
+    // Full-charge capacity now; MaxCapacity is a percentage on Apple Silicon, so the raw key is preferred
+    pub max_mah: Option<i64>,
+    pub serial: Option<String>,
+    pub permanent_failure: bool,
+    pub fully_charged: Option<bool>,
+    pub charging: Option<bool>,
+    pub external_connected: Option<bool>,
+}
+
+impl Health {
+    pub fn health_pct(&self) -> Option<f64> {
+        match (self.max_mah, self.design_mah) { (Some(m), Some(d)) if d > 0 => Some(m as f64 * 100.0 / d as f64), _ => None }
+    }
+
+    // Apple's wording: below 80% of design capacity (or a reported failure) needs service.
+    pub fn condition(&self) -> &'static str {
+        if self.permanent_failure { "Service Recommended (failure reported)" }
+        else if self.health_pct().is_some_and(|h| h < 80.0) { "Service Recommended" }
+        else { "Normal" }
+    }
+
+    pub fn to_json(&self) -> String {
+        use crate::{json_num, json_opt};
+        format!("{{\"cycle_count\":{},\"design_cycle_count\":{},\"design_mah\":{},\"max_mah\":{},\"health_pct\":{},\"condition\":\"{}\",\"flags\":{{\"permanent_failure\":{},\"fully_charged\":{},\"charging\":{},\"external_connected\":{}}},\"serial\":{}}}",
+            json_opt(self.cycle_count), json_opt(self.design_cycle_count), json_opt(self.design_mah), json_opt(self.max_mah), json_num(self.health_pct(), 1),
+            self.condition(), self.permanent_failure, json_opt(self.fully_charged), json_opt(self.charging), json_opt(self.external_connected),
+            self.serial.as_deref().map(crate::json_str).unwrap_or_else(|| "null".to_string()))
+    }
+}
+
+// None on machines without a battery.
+pub fn health() -> Option<Health> {
+    detect()?;
+    let out = crate::subprocess::stdout(std::process::Command::new("ioreg").args(["-r", "-c", "AppleSmartBattery"]))?;
+    Some(Health {
+        cycle_count: ioreg_int(&out, "CycleCount"),
+        design_cycle_count: ioreg_int(&out, "DesignCycleCount9C"),
+        design_mah: ioreg_int(&out, "DesignCapacity"),
+        max_mah: ioreg_int(&out, "AppleRawMaxCapacity").or_else(|| ioreg_int(&out, "NominalChargeCapacity")),
+        serial: ioreg_str(&out, "Serial").filter(|s| !s.is_empty()),
+        permanent_failure: ioreg_int(&out, "PermanentFailureStatus").is_some_and(|v| v != 0),
+        fully_charged: ioreg_bool(&out, "FullyCharged"),
+        charging: ioreg_bool(&out, "IsCharging"),
+        external_connected: ioreg_bool(&out, "ExternalConnected"),
+    })
+}
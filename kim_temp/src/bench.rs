@@ -0,0 +1,97 @@
+// `bench` (feature "soak", like `soak`): what one `stream` sample costs, against the mock machine.
+// The real Sampler, Enricher and Serializer run with every field selected; a warm-up long enough
+// to fill the rolling and drain windows comes first, so the measured samples see the buffers at their
+// steady size. The run fails (exit 1) when the mean CPU time per sample is over the budget
+// (--budget-us, default BUDGET_US) or when a plain sample allocated at all: the loop is meant to
+// reuse its buffers. Not plain are samples that run powermetrics (every 5th; a subprocess can't
+// avoid the heap) and the rare ones with an event, which is built as a String. Allocations are
+// counted by a wrapper around the system allocator, installed only in builds with this feature.
+// The mock stands in for IOKit, so the SMC calls themselves aren't in the figure; `-vv` on a real
+// `stream` logs those per sample.
+
+use crate::config::Units;
+use crate::mock::Mock;
+use crate::pipeline::{EnrichOptions, Enricher, Needs, Sampler, SerializeOptions, Serializer, Timings};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Mean CPU time per sample allowed, in µs; the mock pipeline takes a small fraction of it
+pub const BUDGET_US: f64 = 500.0;
+// Past the longest history a sample keeps (the 15 minute drain window), at one simulated second
+// per sample
+const WARMUP: u64 = 1000;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+pub struct Report {
+    pub samples: u64,
+    pub cpu_us_per_sample: f64,
+    pub budget_us: f64,
+    pub allocs_per_sample: f64,
+    // Allocations in samples without a powermetrics run or an event; the loop's own, which
+    // should be none
+    pub plain_allocs: u64,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.cpu_us_per_sample <= self.budget_us && self.plain_allocs == 0
+    }
+}
+
+pub fn run(samples: u64, seed: u64, budget_us: f64, units: Units) -> Report {
+    let profile = crate::profile::detect();
+    let fields = crate::sample::Fields::parse(None).unwrap_or_else(|e| { eprintln!("{}", e); std::process::exit(2); });
+    let mut mock = Mock::new(seed, false);
+    let mut sampler = Sampler::new(&profile, Some(70.0), true, true);
+    let needs = Needs { temps: true, powermetrics: true, adapter: true, derived: false, dominant: true };
+    let mut enricher = Enricher::new(EnrichOptions {
+        profile: &profile, excluded: &[], derived: &[], units, needs, has_battery: true, backlight: false, quiet_hours: None, adaptive: false, full_charge: false, powermetrics_fn: None,
+        interval_ms: 1000, revalidate_every: Duration::ZERO,
+    }, crate::alerts::Engine::new(Vec::new()), None, Vec::new());
+    let mut serializer = Serializer::new(SerializeOptions {
+        fields: &fields, units, detail: true, host: "bench", model: crate::mock::MODEL, tags: None, corrected: false, backlight: false, rails: None, meter: None,
+    });
+    let mut temps = crate::temps::Temps::default();
+    let timings = Timings::default();
+    let mut meter = crate::qos::SelfMeter::new();
+    let (mut allocs, mut plain_allocs) = (0, 0);
+    for seq in 1..=WARMUP + samples {
+        if seq == WARMUP + 1 { meter.sample(); }
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let s = sampler.sample(&mut mock, seq, &mut temps);
+        let e = enricher.enrich(&mut mock, &s, &temps);
+        std::hint::black_box(serializer.serialize(&s, &mut temps, &enricher.pm, &e, &timings));
+        let plain = !e.pm_fresh && e.events.is_empty();
+        drop(e);
+        if seq <= WARMUP { continue; }
+        let n = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        allocs += n;
+        if plain { plain_allocs += n; }
+    }
+    let cpu_ms = meter.sample().0;
+    let n = samples.max(1) as f64;
+    Report { samples, cpu_us_per_sample: cpu_ms * 1000.0 / n, budget_us, allocs_per_sample: allocs as f64 / n, plain_allocs }
+}
@@ -0,0 +1,130 @@
+// `bugreport`: one command that gathers what an issue report needs, so it can be reproduced
+// without back-and-forth. Everything comes from this binary run as a child, and goes into
+// OUT/kim_temp-bugreport-YYYYMMDD-HHMMSS.tar.gz:
+//   metadata.txt   kim_temp version and features, macOS version, model, CPU, memory
+//   profile.txt    `kim_temp profile` (chip, power keys, roles, calibration)
+//   keys.txt       `kim_temp keys` (every SMC key with its value, grouped)
+//   doctor.txt     `kim_temp doctor` (SMC, sensors, rails, powermetrics and log path checks)
+//   stream.jsonl   `kim_temp stream` for --seconds (default 60), stream.err its stderr
+// Redacted in every file: the home directory, user name and host/computer names. With
+// --hash-processes the stream runs with `--redact-processes hash` and a random salt, so the same
+// process keeps the same name within one report but can't be looked up.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+pub struct Options {
+    pub out: PathBuf,
+    pub seconds: u64,
+    pub hash_processes: bool,
+    pub config: Option<String>,
+    // Passed through to `stream` after the generated arguments
+    pub stream_args: Vec<String>,
+}
+
+// What gets replaced, longest first so a home directory goes before the user name inside it
+struct Redactions(Vec<(String, &'static str)>);
+
+impl Redactions {
+    fn detect() -> Redactions {
+        let scutil = |name: &str| Command::new("scutil").args(["--get", name]).output().ok().filter(|o| o.status.success()).map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+        let mut list: Vec<(String, &'static str)> = Vec::new();
+        if let Some(home) = std::env::var("HOME").ok().filter(|h| h != "/") { list.push((home, "~")); }
+        for host in [crate::profile::sysctl("kern.hostname"), scutil("ComputerName"), scutil("LocalHostName")].into_iter().flatten() {
+            list.push((host.trim_end_matches(".local").to_string(), "<host>"));
+            list.push((host, "<host>"));
+        }
+        if let Ok(user) = std::env::var("USER") { list.push((user, "<user>")); }
+        // Short names would replace pieces of ordinary words
+        list.retain(|(s, _)| s.len() >= 3);
+        list.sort_by_key(|(s, _)| std::cmp::Reverse(s.len()));
+        list.dedup_by(|a, b| a.0 == b.0);
+        Redactions(list)
+    }
+
+    fn apply(&self, text: &str) -> String {
+        self.0.iter().fold(text.to_string(), |text, (secret, with)| text.replace(secret.as_str(), with))
+    }
+}
+
+// A child's stdout, with its stderr appended when it said anything.
+fn capture(program: &str, args: &[&str]) -> String {
+    match Command::new(program).args(args).output() {
+        Ok(o) => {
+            let mut text = String::from_utf8_lossy(&o.stdout).into_owned();
+            let err = String::from_utf8_lossy(&o.stderr);
+            if !err.trim().is_empty() { text.push_str(&format!("\n# stderr\n{}", err)); }
+            if !o.status.success() { text.push_str(&format!("\n# {}\n", o.status)); }
+            text
+        }
+        Err(e) => format!("# cannot run {}: {}\n", program, e),
+    }
+}
+
+fn metadata(exe: &str) -> String {
+    let sysctl = |name| crate::profile::sysctl(name).unwrap_or_else(|| "?".to_string());
+    // The version line comes first in --capabilities-built
+    let mut out = capture(exe, &["--capabilities-built"]);
+    out.push_str(&format!("\n{}", capture("sw_vers", &[])));
+    out.push_str(&format!("\nhw.model: {}\nmachdep.cpu.brand_string: {}\nhw.memsize: {}\nkern.osrelease: {}\n",
+        sysctl("hw.model"), sysctl("machdep.cpu.brand_string"), sysctl("hw.memsize"), sysctl("kern.osrelease")));
+    out
+}
+
+// Runs `stream` for the capture window and returns its stdout and stderr.
+fn stream(exe: &str, opts: &Options) -> Result<(String, String), String> {
+    let mut args: Vec<String> = ["stream", "--interval", "1000", "--no-event-log", "--no-health-log"].iter().map(|s| s.to_string()).collect();
+    if let Some(c) = &opts.config { args.extend(["--config".to_string(), c.clone()]); }
+    if opts.hash_processes { args.extend(["--redact-processes".to_string(), "hash".to_string(), "--redact-salt".to_string(), format!("{:016x}", salt())]); }
+    args.extend(opts.stream_args.iter().cloned());
+    let mut child = Command::new(exe).args(&args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+        .map_err(|e| format!("cannot run stream: {}", e))?;
+    // Drained on threads, so a full pipe can't stall the child
+    let drain = |pipe: Option<Box<dyn Read + Send>>| std::thread::spawn(move || { let mut s = String::new(); if let Some(mut p) = pipe { let _ = p.read_to_string(&mut s); } s });
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    crate::session::sleep(Duration::from_secs(opts.seconds));
+    // SIGINT, as Ctrl-C would, so stream ends its last line and restores what it changed
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    for _ in 0..100 {
+        if child.try_wait().ok().flatten().is_some() { break; }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    if child.try_wait().ok().flatten().is_none() { let _ = child.kill(); let _ = child.wait(); }
+    Ok((stdout.join().unwrap_or_default(), stderr.join().unwrap_or_default()))
+}
+
+fn salt() -> u64 {
+    let mut buf = [0u8; 8];
+    let read = std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut buf)).is_ok();
+    if read { u64::from_le_bytes(buf) } else { std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64 ^ std::process::id() as u64 }
+}
+
+// Returns the path of the archive.
+pub fn run(opts: &Options) -> Result<PathBuf, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("cannot locate this binary: {}", e))?.to_string_lossy().into_owned();
+    let name = format!("kim_temp-bugreport-{}", crate::clock::local(std::time::SystemTime::now()).compact());
+    let dir = std::env::temp_dir().join(&name);
+    let redactions = Redactions::detect();
+    let write = |file: &str, text: &str| crate::package::write(&dir.join(file), &redactions.apply(text));
+
+    eprintln!("bugreport: recording {} s of samples (keep the machine doing whatever shows the problem)", opts.seconds);
+    let (samples, errors) = stream(&exe, opts)?;
+    write("stream.jsonl", &samples)?;
+    write("stream.err", &errors)?;
+    eprintln!("bugreport: reading keys and machine details");
+    let config: Vec<&str> = opts.config.as_deref().map(|c| vec!["--config", c]).unwrap_or_default();
+    write("profile.txt", &capture(&exe, &[&["profile"], config.as_slice()].concat()))?;
+    write("keys.txt", &capture(&exe, &[&["keys"], config.as_slice()].concat()))?;
+    write("doctor.txt", &capture(&exe, &[&["doctor"], config.as_slice()].concat()))?;
+    write("metadata.txt", &metadata(&exe))?;
+
+    std::fs::create_dir_all(&opts.out).map_err(|e| format!("cannot create {}: {}", opts.out.display(), e))?;
+    let archive = opts.out.join(format!("{}.tar.gz", name));
+    let parent = dir.parent().unwrap_or(Path::new("/")).to_string_lossy().into_owned();
+    let result = crate::package::run("tar", &["-czf", &archive.to_string_lossy(), "-C", &parent, &name]);
+    let _ = std::fs::remove_dir_all(&dir);
+    result.map(|_| archive)
+}
@@ -0,0 +1,60 @@
+// `burst --hz 10 --secs 30`: the five power rails only (the cheap part: one driver call each,
+// keys resolved up front) at up to 10 Hz for a short window, for transients the 1 s stream
+// averages away. Samples run on a fixed schedule and are only printed once the window is over,
+// so writing output never delays a read: one JSON line per sample with its offset from the
+// start, then a summary line with min/mean/p95/max per rail. Ctrl-C ends the window early.
+
+use crate::json_num;
+use crate::power::Rails;
+use std::time::{Duration, Instant};
+
+pub const MAX_HZ: f64 = 10.0;
+pub const MAX_SECS: f64 = 600.0;
+
+pub struct Series {
+    hz: f64,
+    names: Vec<&'static str>,
+    // (offset from the start in ms, watts per rail)
+    samples: Vec<(f64, Vec<Option<f64>>)>,
+    // Samples whose read ran past the next one's slot
+    late: u64,
+}
+
+pub fn run(rails: &Rails, hz: f64, secs: f64) -> Series {
+    let period = Duration::from_secs_f64(1.0 / hz);
+    let count = (hz * secs).round().max(1.0) as u32;
+    let mut series = Series { hz, names: Vec::new(), samples: Vec::with_capacity(count as usize), late: 0 };
+    let start = Instant::now();
+    for i in 0..count {
+        if crate::session::interrupted().is_some() { break; }
+        let slot = start + period * i;
+        if let Some(wait) = slot.checked_duration_since(Instant::now()) { std::thread::sleep(wait); }
+        let at = start.elapsed();
+        let read = rails.read();
+        if series.names.is_empty() { series.names = read.iter().map(|(n, _)| *n).collect(); }
+        series.samples.push((at.as_secs_f64() * 1000.0, read.into_iter().map(|(_, w)| w).collect()));
+        if start.elapsed() > period * (i + 1) { series.late += 1; }
+    }
+    series
+}
+
+impl Series {
+    pub fn lines(&self) -> Vec<String> {
+        self.samples.iter().map(|(t, watts)| {
+            let rails: Vec<String> = self.names.iter().zip(watts).map(|(n, w)| format!("\"{}\":{}", n, json_num(*w, 3))).collect();
+            format!("{{\"type\":\"burst\",\"t_ms\":{:.1},{}}}", t, rails.join(","))
+        }).collect()
+    }
+
+    pub fn summary(&self) -> String {
+        let stats: Vec<String> = self.names.iter().enumerate().map(|(i, n)| {
+            let mut v: Vec<f64> = self.samples.iter().filter_map(|(_, w)| w[i]).collect();
+            if v.is_empty() { return format!("\"{}\":null", n); }
+            v.sort_by(f64::total_cmp);
+            let p95 = v[((v.len() as f64 * 0.95).ceil() as usize).clamp(1, v.len()) - 1];
+            format!("\"{}\":{{\"min\":{:.3},\"mean\":{:.3},\"p95\":{:.3},\"max\":{:.3}}}", n, v[0], v.iter().sum::<f64>() / v.len() as f64, p95, v[v.len() - 1])
+        }).collect();
+        let span_ms = self.samples.last().map_or(0.0, |(t, _)| *t);
+        format!("{{\"type\":\"burst_summary\",\"hz\":{},\"samples\":{},\"span_ms\":{:.1},\"late\":{},\"rails\":{{{}}}}}", self.hz, self.samples.len(), span_ms, self.late, stats.join(","))
+    }
+}
@@ -0,0 +1,76 @@
+// `charge-limit set N | clear | status`: caps charging through whichever SMC key this
+// machine's firmware implements, then reads it back to confirm the SMC accepted the value.
+// The previous value goes to the undo journal (`restore-hw-state`).
+//   BCLM  ui8 percent (Intel and early Apple Silicon firmware), any limit 20..100
+//   CHWA  ui8 flag (Apple Silicon, macOS 13+ firmware), a fixed 80% limit only
+//   CH0B  charging inhibit only; holding an arbitrary % would need a process toggling it, so
+//         it is reported but not used for `set`
+
+use crate::smcraw::Raw;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Method { Bclm, Chwa, Inhibit }
+
+impl Method {
+    fn key(self) -> &'static str {
+        match self { Method::Bclm => "BCLM", Method::Chwa => "CHWA", Method::Inhibit => "CH0B" }
+    }
+}
+
+// The first key the firmware knows, in order of preference.
+fn detect(smc: &Raw) -> Option<Method> {
+    [Method::Bclm, Method::Chwa, Method::Inhibit].into_iter().find(|m| smc.info(m.key()).is_ok())
+}
+
+fn write_verified(smc: &Raw, key: &str, value: u8, yes: bool) -> Result<(), String> {
+    let current = smc.read(key)?.1.first().copied().unwrap_or(0);
+    if current == value { return Ok(()); }
+    if !crate::journal::confirm(&format!("Write {} = {} (currently {})", key, value, current), yes) { return Err("cancelled".to_string()); }
+    smc.write(key, &[value])?;
+    match smc.read(key)?.1.first() {
+        Some(v) if *v == value => Ok(()),
+        other => Err(format!("{} read back as {:?} after writing {}; the firmware ignored the change", key, other, value)),
+    }
+}
+
+pub fn status() -> Result<String, String> {
+    let smc = Raw::open()?;
+    let Some(method) = detect(&smc) else { return Ok("no charge-limit key on this machine".to_string()) };
+    let value = smc.read(method.key())?.1.first().copied().unwrap_or(0);
+    Ok(match method {
+        Method::Bclm if value >= 100 => "BCLM: no limit (100%)".to_string(),
+        Method::Bclm => format!("BCLM: charging stops at {}%", value),
+        Method::Chwa if value != 0 => "CHWA: charging stops at 80%".to_string(),
+        Method::Chwa => "CHWA: no limit".to_string(),
+        Method::Inhibit => format!("CH0B: charging {} (inhibit only; no percentage limit on this model)", if value != 0 { "inhibited" } else { "allowed" }),
+    })
+}
+
+pub fn set(percent: u8, yes: bool) -> Result<String, String> {
+    let smc = Raw::open()?;
+    match detect(&smc) {
+        Some(Method::Bclm) => {
+            if !(20..=100).contains(&percent) { return Err(format!("limit {}% out of range (20..100)", percent)); }
+            write_verified(&smc, "BCLM", percent, yes)?;
+            Ok(format!("BCLM set to {}%", percent))
+        }
+        Some(Method::Chwa) if percent == 80 => { write_verified(&smc, "CHWA", 1, yes)?; Ok("CHWA set: charging stops at 80%".to_string()) }
+        Some(Method::Chwa) => Err(format!("this firmware only supports an 80% limit (CHWA), not {}%", percent)),
+        Some(Method::Inhibit) => Err("this model only exposes a charging inhibit (CH0B); a percentage limit needs a process that toggles it".to_string()),
+        None => Err("no charge-limit key on this machine".to_string()),
+    }
+}
+
+pub fn clear(yes: bool) -> Result<String, String> {
+    let smc = Raw::open()?;
+    match detect(&smc) {
+        Some(Method::Bclm) => write_verified(&smc, "BCLM", 100, yes).map(|_| "BCLM limit cleared (100%)".to_string()),
+        Some(Method::Chwa) => write_verified(&smc, "CHWA", 0, yes).map(|_| "CHWA limit cleared".to_string()),
+        Some(Method::Inhibit) => {
+            write_verified(&smc, "CH0B", 0, yes)?;
+            if smc.info("CH0C").is_ok() { write_verified(&smc, "CH0C", 0, yes)?; }
+            Ok("charging inhibit cleared".to_string())
+        }
+        None => Err("no charge-limit key on this machine".to_string()),
+    }
+}
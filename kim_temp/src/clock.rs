@@ -0,0 +1,107 @@
+// Wall-clock formatting without a date crate: local time via localtime_r.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct LocalTime {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millis: u32,
+    // Offset from UTC in seconds
+    pub utc_offset: i64,
+}
+
+#[allow(clippy::unnecessary_cast)]
+pub fn local(t: SystemTime) -> LocalTime {
+    let since = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    LocalTime {
+        year: tm.tm_year as i32 + 1900, month: tm.tm_mon as u32 + 1, day: tm.tm_mday as u32,
+        hour: tm.tm_hour as u32, minute: tm.tm_min as u32, second: tm.tm_sec as u32,
+        millis: since.subsec_millis(), utc_offset: tm.tm_gmtoff as i64,
+    }
+}
+
+// `t` at a fixed UTC offset instead of this machine's zone, e.g. to show a recorded sample as the
+// machine that took it saw it (Howard Hinnant's civil_from_days).
+#[cfg(feature = "record")]
+pub fn at_offset(t: SystemTime, utc_offset: i64) -> LocalTime {
+    let since = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs() as i64 + utc_offset;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let (day, month) = (doy - (153 * mp + 2) / 5 + 1, if mp < 10 { mp + 3 } else { mp - 9 });
+    LocalTime {
+        year: (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32, month: month as u32, day: day as u32,
+        hour: (rem / 3600) as u32, minute: (rem % 3600 / 60) as u32, second: (rem % 60) as u32, millis: since.subsec_millis(), utc_offset,
+    }
+}
+
+// RFC 3339, written straight into the output (the stream loop formats a timestamp every sample).
+impl std::fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let off = self.utc_offset.abs();
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}{}{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second, self.millis,
+            if self.utc_offset < 0 { '-' } else { '+' }, off / 3600, (off % 3600) / 60)
+    }
+}
+
+impl LocalTime {
+    // 2026-10-16
+    #[cfg(feature = "csv")]
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    // 2026-10-16T14:03:07.123+02:00
+    pub fn rfc3339(&self) -> String {
+        self.to_string()
+    }
+
+    // 20261016-140307, for file names
+    pub fn compact(&self) -> String {
+        format!("{:04}{:02}{:02}-{:02}{:02}{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's days_from_civil).
+#[cfg(feature = "csv")]
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    era * 146097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719468
+}
+
+// Unix seconds from `2026-10-16T14:03:07.123+02:00` (`Z`, a space instead of `T`, and no
+// fraction are accepted too). Without an offset the time is taken as local, at today's offset.
+#[cfg(feature = "csv")]
+pub fn parse(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let num = |a: usize, b: usize| s.get(a..b)?.parse::<i64>().ok();
+    let (date, hour, minute, second) = (days_from_civil(num(0, 4)?, num(5, 7)?, num(8, 10)?), num(11, 13)?, num(14, 16)?, num(17, 19)?);
+    let rest = s.get(19..)?;
+    let frac_len = rest.strip_prefix('.').map(|f| f.chars().take_while(|c| c.is_ascii_digit()).count() + 1).unwrap_or(0);
+    let frac = if frac_len > 1 { rest[..frac_len].parse::<f64>().ok()? } else { 0.0 };
+    let offset = match &rest[frac_len..] {
+        "" => local(SystemTime::now()).utc_offset,
+        "Z" => 0,
+        o => {
+            let sign = match o.as_bytes()[0] { b'+' => 1, b'-' => -1, _ => return None };
+            let o = o[1..].replace(':', "");
+            sign * (o.get(..2)?.parse::<i64>().ok()? * 3600 + o.get(2..4).map_or(Some(0), |m| m.parse::<i64>().ok())? * 60)
+        }
+    };
+    Some((date * 86400 + hour * 3600 + minute * 60 + second - offset) as f64 + frac)
+}
@@ -0,0 +1,59 @@
+// Shell completion. `kim_temp completions bash|zsh` prints a script that completes modes and
+// flags statically and asks the binary for the rest (`kim_temp __complete keys|fields|config`),
+// so `--derive 'x = T<TAB>` lists the keys this machine actually has.
+
+const MODES: &[&str] = &[
+    "cpu", "gpu", "power", "power-all", "all", "json", "env", "read", "info", "keys", "scan-safety", "explain", "explore", "volts", "amps", "monitor", "burst", "replay", "stream", "profile", "debug-power", "measure", "follow", "battery-health", "charge-limit", "restore-hw-state", "write", "labels", "package", "install-daemon", "uninstall-daemon", "serve", "overlay", "bugreport", "sync-profiles", "setup-sudo", "doctor", "calibrate", "completions",
+];
+
+const FLAGS: &[&str] = &[
+    "--detail", "--split", "--power-detail", "--derive", "--qos", "--qos-priority", "--meter-self", "--tag", "--units", "--interval", "--adaptive", "--fixed-interval", "--event-log", "--no-event-log", "--health-log", "--no-health-log", "--config", "--no-config",
+    "--template-file", "--fields", "--log-csv", "--rotate", "--alert", "--notify", "--no-self-test", "--revalidate", "--attribution", "--baseline",
+    "--store", "--from-start", "--types", "--json", "--yes", "--list", "--capabilities-built",
+    "--out", "--identifier", "--version", "--sign", "--installer-sign", "--notarize-profile",
+    "--reference", "--recorded", "--lag", "--window", "--dry-run", "--clear", "--type", "--i-know-what-im-doing", "--lang", "--filter", "--sort", "--agent", "--sink", "--socket", "--socket-group", "--no-daemon", "--ttl", "--ws", "--ws-origin", "--ws-token", "--http", "--http-token", "--http-origin", "--grpc", "--seconds", "--hash-processes", "--serve", "--redact-processes", "--redact-salt", "--source", "--user", "--remove", "--quiet-hours", "--drop-on-backpressure", "--hz", "--secs", "--record", "--realtime", "--backend", "--seed", "--trigger", "--pre-trigger", "--post-trigger", "--verbose", "--log-json", "--subprocess-timeout", "--agg", "--reject-sigma",
+];
+
+// The bash script; zsh loads it through bashcompinit.
+const BASH: &str = r#"_kim_temp() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" bin="${COMP_WORDS[0]}"
+    case "$prev" in
+        --fields)
+            local done="${cur%"${cur##*,}"}"
+            COMPREPLY=($(compgen -P "$done" -W "$("$bin" __complete fields)" -- "${cur##*,}")); return ;;
+        --derive|--alert|--trigger)
+            COMPREPLY=($(compgen -W "$("$bin" __complete keys 2>/dev/null) $("$bin" __complete fields)" -- "${cur##*[ =(+*/-]}")); return ;;
+        --config|--template-file|--log-csv|--store|--event-log|--health-log|--record|--out|--reference|--recorded)
+            COMPREPLY=($(compgen -f -- "$cur")); return ;;
+        --units) COMPREPLY=($(compgen -W "celsius fahrenheit" -- "$cur")); return ;;
+        --agg) COMPREPLY=($(compgen -W "mean median trimmed max" -- "$cur")); return ;;
+        --qos) COMPREPLY=($(compgen -W "background utility default user-initiated" -- "$cur")); return ;;
+        --rotate) COMPREPLY=($(compgen -W "never daily size:50M" -- "$cur")); return ;;
+        --attribution) COMPREPLY=($(compgen -W "cpu-share baseline none" -- "$cur")); return ;;
+        --backend) COMPREPLY=($(compgen -W "smc linux mock" -- "$cur")); return ;;
+        --sink) COMPREPLY=($(compgen -W "jsonl csv" -- "$cur")); return ;;
+        --redact-processes) COMPREPLY=($(compgen -W "hash label" -- "$cur")); return ;;
+        --sort) COMPREPLY=($(compgen -W "key value" -- "$cur")); return ;;
+        --type) COMPREPLY=($(compgen -W "ui8 ui16 ui32 ui64 si8 si16 si32 si64 flt fpe2 fp88 sp78 flag ch8* hex_" -- "$cur")); return ;;
+        completions) COMPREPLY=($(compgen -W "bash zsh" -- "$cur")); return ;;
+        charge-limit) COMPREPLY=($(compgen -W "status set clear" -- "$cur")); return ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "__FLAGS__" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "__MODES__" -- "$cur"))
+    elif [[ "${COMP_WORDS[1]}" == read || "${COMP_WORDS[1]}" == info || ( "${COMP_WORDS[1]}" == write && $COMP_CWORD -eq 2 ) ]]; then
+        COMPREPLY=($(compgen -W "$("$bin" __complete keys 2>/dev/null)" -- "$cur"))
+    fi
+}
+complete -o default -F _kim_temp kim_temp kim_temp_bin
+"#;
+
+pub fn script(shell: &str) -> Option<String> {
+    let bash = BASH.replace("__FLAGS__", &FLAGS.join(" ")).replace("__MODES__", &MODES.join(" "));
+    match shell {
+        "bash" => Some(bash),
+        "zsh" => Some(format!("autoload -U +X bashcompinit && bashcompinit\n{}", bash)),
+        _ => None,
+    }
+}
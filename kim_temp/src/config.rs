@@ -0,0 +1,193 @@
+// Defaults loaded from ~/.config/kim_temp/config.toml (or $KIM_TEMP_CONFIG, or --config PATH).
+// Command-line flags always win over the file; a missing default file means built-in defaults.
+
+#[cfg(feature = "config")]
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(rename_all = "lowercase"))]
+pub enum Units {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl Units {
+    pub fn parse(s: &str) -> Option<Units> {
+        match s {
+            "celsius" | "c" => Some(Units::Celsius),
+            "fahrenheit" | "f" => Some(Units::Fahrenheit),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self { Units::Celsius => "celsius", Units::Fahrenheit => "fahrenheit" }
+    }
+
+    // Converts a Celsius reading into these units.
+    pub fn temp(self, c: f64) -> f64 {
+        match self { Units::Celsius => c, Units::Fahrenheit => c * 9.0 / 5.0 + 32.0 }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self { Units::Celsius => "°C", Units::Fahrenheit => "°F" }
+    }
+}
+
+// Temperature key prefixes to keep/drop, e.g. exclude = ["Ts", "TW"] to ignore palm rest and Wi-Fi.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct SensorFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl SensorFilter {
+    pub fn allows(&self, key: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| key.starts_with(p.as_str())))
+            && !self.exclude.iter().any(|p| key.starts_with(p.as_str()))
+    }
+}
+
+// Replaces the profile's rail keys for machines where detection picks the wrong one.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct PowerKeys {
+    pub system: Option<String>,
+    pub battery: Option<String>,
+    pub cpu: Option<String>,
+    pub gpu: Option<String>,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct Output {
+    // Every json/stream line is also appended to each of these files
+    pub files: Vec<String>,
+    // Default for --template-file
+    pub template: Option<String>,
+    // Defaults for --log-csv / --rotate
+    pub csv: Option<String>,
+    pub rotate: Option<String>,
+    // Default for stream's --event-log
+    pub event_log: Option<String>,
+    // Default for stream's --health-log
+    pub health_log: Option<String>,
+}
+
+// Defaults for `measure -- <cmd>`
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct Measure {
+    pub interval_ms: Option<u64>,
+    // cpu-share | baseline | none
+    pub attribution: Option<String>,
+    pub baseline_secs: Option<f64>,
+}
+
+// Where fleet profiles come from (see fleet.rs)
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct ProfileSync {
+    // A directory or an https:// URL
+    pub source: Option<String>,
+    pub manifest_sha256: Option<String>,
+    pub refresh_hours: Option<f64>,
+}
+
+// Daily window (local HH:MM) in which stream suspends powermetrics and notifications (see quiet.rs)
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct QuietHours {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct Config {
+    pub units: Units,
+    // Sampling interval for stream/monitor
+    pub interval_ms: Option<u64>,
+    pub qos: Option<String>,
+    // Defaults for --agg (mean | median | trimmed | max) and --reject-sigma
+    pub agg: Option<String>,
+    pub reject_sigma: Option<f64>,
+    // Deadline for powermetrics, pmset, ioreg and the other tools a sample runs (subprocess.rs)
+    pub subprocess_timeout_secs: Option<f64>,
+    pub sensors: SensorFilter,
+    pub power_keys: PowerKeys,
+    // Process names left out of top_cpu/high_wakeups (added to the built-in list)
+    pub exclude_processes: Vec<String>,
+    // Defaults for --redact-processes (hash | label) and --redact-salt
+    pub redact_processes: Option<String>,
+    pub redact_salt: Option<String>,
+    pub output: Output,
+    // Same syntax as --derive
+    pub derived: Vec<String>,
+    pub tags: BTreeMap<String, String>,
+    pub alerts: Vec<crate::alerts::AlertConfig>,
+    pub measure: Measure,
+    pub profile_sync: ProfileSync,
+    pub quiet_hours: QuietHours,
+}
+
+// Every key the config file accepts, dotted by table (for shell completion).
+pub const FIELD_NAMES: &[&str] = &[
+    "units", "interval_ms", "qos", "agg", "reject_sigma", "subprocess_timeout_secs", "exclude_processes", "redact_processes", "redact_salt", "derived", "tags",
+    "sensors.include", "sensors.exclude",
+    "power_keys.system", "power_keys.battery", "power_keys.cpu", "power_keys.gpu",
+    "output.files", "output.template", "output.csv", "output.rotate", "output.event_log", "output.health_log",
+    "measure.interval_ms", "measure.attribution", "measure.baseline_secs",
+    "profile_sync.source", "profile_sync.manifest_sha256", "profile_sync.refresh_hours",
+    "quiet_hours.start", "quiet_hours.end",
+    "alerts.name", "alerts.when", "alerts.run", "alerts.notify", "alerts.webhook", "alerts.escalate",
+];
+
+// `~/...` relative to $HOME.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(feature = "config")]
+pub fn default_path() -> Option<PathBuf> {
+    if let Some(p) = std::env::var_os("KIM_TEMP_CONFIG") { return Some(PathBuf::from(p)); }
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config/kim_temp/config.toml"))
+}
+
+// Loads `path`, or the default location when None. Only an explicit path is required to exist.
+#[cfg(feature = "config")]
+pub fn load(path: Option<&str>) -> Result<Config, String> {
+    let (path, required) = match path {
+        Some(p) => (expand_home(p), true),
+        None => match default_path() { Some(p) => (p, false), None => return Ok(Config::default()) },
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("cannot read config {}: {}", path.display(), e)),
+    };
+    toml::from_str(&text).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+}
+
+#[cfg(not(feature = "config"))]
+pub fn load(path: Option<&str>) -> Result<Config, String> {
+    match path {
+        Some(_) => Err("this build has no config file support (rebuild with --features config)".to_string()),
+        None => Ok(Config::default()),
+    }
+}
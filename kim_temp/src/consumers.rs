@@ -0,0 +1,58 @@
+// `dominant`: which consumers account for most of the power right now, so a dashboard can say
+// "display, cpu" instead of showing eight numbers. Shares are taken of everything the Mac draws:
+// the system rail plus the panel (screen_w is measured outside it) plus adapter losses while
+// charging. What the system rail has beyond the CPU and GPU rails (memory, SSD, radios, SoC
+// fabric) is `unknown_residual`.
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Breakdown {
+    pub system_w: f64,
+    // None when there is no built-in panel (or no battery rail to estimate it from)
+    pub display_w: Option<f64>,
+    pub cpu_w: Option<f64>,
+    pub gpu_w: Option<f64>,
+    // Conversion loss in the charger path, only while on an adapter
+    pub charging_loss_w: Option<f64>,
+}
+
+// A consumer is dominant when it has at least this share of the total...
+const MIN_SHARE: f64 = 0.25;
+// ...and draws at least this much; below it nothing is worth naming
+const MIN_W: f64 = 0.5;
+
+// Every label `classify` can give
+#[cfg(feature = "record")]
+const LABELS: [&str; 5] = ["display", "cpu", "gpu", "charging_losses", "unknown_residual"];
+
+// A label read back from a recording, as the name `classify` gives
+#[cfg(feature = "record")]
+pub fn label(name: &str) -> Option<&'static str> {
+    LABELS.into_iter().find(|l| *l == name)
+}
+
+// Largest first, at most two (a fixed pair, so the per-sample path allocates nothing); both None
+// when the Mac is drawing next to nothing.
+pub fn classify(b: &Breakdown) -> [Option<&'static str>; 2] {
+    let (cpu, gpu) = (b.cpu_w.unwrap_or(0.0).max(0.0), b.gpu_w.unwrap_or(0.0).max(0.0));
+    let mut parts = [
+        ("display", b.display_w.unwrap_or(0.0).max(0.0)),
+        ("cpu", cpu),
+        ("gpu", gpu),
+        ("charging_losses", b.charging_loss_w.unwrap_or(0.0).max(0.0)),
+        ("unknown_residual", (b.system_w - cpu - gpu).max(0.0)),
+    ];
+    let total: f64 = parts.iter().map(|(_, w)| w).sum();
+    if total < MIN_W { return [None, None]; }
+    parts.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let pick = |(name, w): (&'static str, f64)| (w >= MIN_W && w / total >= MIN_SHARE).then_some(name);
+    // Spread evenly (nothing reaches the share): still name the largest
+    [pick(parts[0]).or(Some(parts[0].0)), pick(parts[1])]
+}
+
+pub fn to_json(labels: [Option<&'static str>; 2]) -> impl std::fmt::Display {
+    crate::sample::Lazy(move |f| {
+        f.write_str("[")?;
+        for (i, l) in labels.iter().flatten().enumerate() { write!(f, "{}\"{}\"", if i > 0 { "," } else { "" }, l)?; }
+        f.write_str("]")
+    })
+}
@@ -0,0 +1,273 @@
+// `--log-csv PATH`: one CSV row per stream sample, with rotation by day or size.
+// Columns are the sample's scalar fields (plus `derived.*`) in output order, fixed by the
+// first sample written to each file. Rotated files are renamed with their date and gzipped
+// in the background.
+
+use crate::clock;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotate {
+    Never,
+    Daily,
+    Size(u64),
+}
+
+impl Rotate {
+    // never | daily | size:50M (K/M/G suffixes)
+    pub fn parse(s: &str) -> Option<Rotate> {
+        match s {
+            "never" | "none" => Some(Rotate::Never),
+            "daily" => Some(Rotate::Daily),
+            _ => {
+                let n = s.strip_prefix("size:")?;
+                let (digits, mult) = match n.chars().last()? {
+                    'K' | 'k' => (&n[..n.len() - 1], 1u64 << 10),
+                    'M' | 'm' => (&n[..n.len() - 1], 1 << 20),
+                    'G' | 'g' => (&n[..n.len() - 1], 1 << 30),
+                    _ => (n, 1),
+                };
+                digits.parse::<u64>().ok().filter(|v| *v > 0).map(|v| Rotate::Size(v * mult))
+            }
+        }
+    }
+}
+
+pub struct CsvLog {
+    path: PathBuf,
+    rotate: Rotate,
+    file: Option<File>,
+    columns: Vec<String>,
+    day: String,
+    written: u64,
+}
+
+fn csv_field(v: &serde_json::Value) -> String {
+    match v {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) if s.contains([',', '"', '\n']) => format!("\"{}\"", s.replace('"', "\"\"")),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// Scalar fields of a sample, flattening the `derived` object into `derived.<name>`.
+fn flatten(sample: &serde_json::Map<String, serde_json::Value>) -> Vec<(String, &serde_json::Value)> {
+    let mut out = Vec::new();
+    for (k, v) in sample {
+        match v {
+            serde_json::Value::Object(m) if k == "derived" => out.extend(m.iter().map(|(dk, dv)| (format!("derived.{}", dk), dv))),
+            serde_json::Value::Object(_) | serde_json::Value::Array(_) => {}
+            _ => out.push((k.clone(), v)),
+        }
+    }
+    out
+}
+
+impl CsvLog {
+    pub fn new(path: &str, rotate: Rotate) -> CsvLog {
+        CsvLog { path: crate::config::expand_home(path), rotate, file: None, columns: Vec::new(), day: String::new(), written: 0 }
+    }
+
+    fn rotated_name(&self) -> PathBuf {
+        let stem = self.path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let ext = self.path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+        let stamp = match self.rotate { Rotate::Daily => self.day.clone(), _ => clock::local(SystemTime::now()).compact() };
+        self.path.with_file_name(format!("{}-{}{}", stem, stamp, ext))
+    }
+
+    // Renames the current file out of the way and compresses it.
+    fn rotate_now(&mut self) {
+        self.file = None;
+        let target = self.rotated_name();
+        if std::fs::rename(&self.path, &target).is_ok() {
+            if let Ok(mut c) = std::process::Command::new("gzip").arg("-f").arg(&target).spawn() {
+                std::thread::spawn(move || { let _ = c.wait(); });
+            }
+        }
+    }
+
+    fn open(&mut self, today: &str) -> std::io::Result<()> {
+        // A file left over from an earlier day is rotated before we append to it
+        if self.rotate == Rotate::Daily && self.path.exists() {
+            let modified_day = std::fs::metadata(&self.path).and_then(|m| m.modified()).map(|t| clock::local(t).date()).unwrap_or_default();
+            if modified_day != today {
+                self.day = modified_day;
+                self.rotate_now();
+            }
+        }
+        let existing = std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.file = Some(file);
+        self.day = today.to_string();
+        self.written = existing;
+        // Appending to a non-empty file keeps its header; otherwise the next row writes one
+        if existing == 0 { self.columns.clear(); } else if self.columns.is_empty() { self.columns = header_of(&self.path); }
+        Ok(())
+    }
+
+    // Appends one JSON sample; failures are reported but never stop the stream.
+    pub fn write(&mut self, sample_json: &str) {
+        let Ok(serde_json::Value::Object(sample)) = serde_json::from_str::<serde_json::Value>(sample_json) else { return };
+        let now = clock::local(SystemTime::now());
+        let today = now.date();
+        let due = match self.rotate {
+            Rotate::Never => false,
+            Rotate::Daily => self.file.is_some() && self.day != today,
+            Rotate::Size(limit) => self.file.is_some() && self.written >= limit,
+        };
+        if due { self.rotate_now(); self.columns.clear(); }
+        if self.file.is_none() {
+            if let Err(e) = self.open(&today) { crate::diag::warn!("cannot open CSV log {}: {}", self.path.display(), e); return; }
+        }
+        let fields = flatten(&sample);
+        let mut text = String::new();
+        if self.columns.is_empty() {
+            self.columns = std::iter::once("timestamp".to_string()).chain(fields.iter().map(|(k, _)| k.clone())).collect();
+            text.push_str(&self.columns.join(","));
+            text.push('\n');
+        }
+        let row: Vec<String> = self.columns.iter().map(|c| {
+            if c == "timestamp" { return now.rfc3339(); }
+            fields.iter().find(|(k, _)| k == c).map(|(_, v)| csv_field(v)).unwrap_or_default()
+        }).collect();
+        text.push_str(&row.join(","));
+        text.push('\n');
+        if let Some(f) = &mut self.file {
+            match f.write_all(text.as_bytes()) {
+                Ok(()) => self.written += text.len() as u64,
+                Err(e) => { crate::diag::warn!("CSV log write failed: {}", e); self.file = None; }
+            }
+        }
+    }
+
+    // At shutdown, like Sinks::flush.
+    pub fn flush(&mut self) {
+        if let Some(Err(e)) = self.file.as_ref().map(|f| f.sync_data()) { crate::diag::warn!("cannot flush CSV log {}: {}", self.path.display(), e); }
+    }
+}
+
+fn header_of(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path).ok()
+        .and_then(|s| s.lines().next().map(|l| l.split(',').map(str::to_string).collect()))
+        .unwrap_or_default()
+}
+
+// ---- follow ----
+// `follow --store PATH` tails a CSV log written by another `stream --log-csv` process and
+// re-emits each new row as a JSON sample, so consumers can attach to a running logger without
+// starting a second collector. Rotation is followed: when the path is renamed away or truncated,
+// the rest of the old file is drained and the new file is read from its header.
+
+// Splits one CSV line, undoing csv_field's quoting.
+pub fn parse_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut cur = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => { cur.push('"'); chars.next(); }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut cur)),
+            _ => cur.push(c),
+        }
+    }
+    fields.push(cur);
+    fields
+}
+
+// Inverse of flatten(): empty cells become null, numbers and booleans get their JSON type,
+// and `derived.<name>` columns are gathered back into a `derived` object.
+fn row_to_json(columns: &[String], row: &[String]) -> String {
+    use serde_json::{Map, Value};
+    let mut sample = Map::new();
+    let mut derived = Map::new();
+    for (col, cell) in columns.iter().zip(row) {
+        let value = if cell.is_empty() { Value::Null }
+            else if let Ok(n) = serde_json::from_str::<serde_json::Number>(cell) { Value::Number(n) }
+            else if let Ok(b) = cell.parse::<bool>() { Value::Bool(b) }
+            else { Value::String(cell.clone()) };
+        match col.strip_prefix("derived.") {
+            Some(name) => { derived.insert(name.to_string(), value); }
+            None => { sample.insert(col.clone(), value); }
+        }
+    }
+    if !derived.is_empty() { sample.insert("derived".to_string(), Value::Object(derived)); }
+    Value::Object(sample).to_string()
+}
+
+struct Tail {
+    file: File,
+    ino: u64,
+    pos: u64,
+    columns: Vec<String>,
+    partial: String,
+}
+
+impl Tail {
+    // Opens `path`; `from_start` replays existing rows, otherwise only rows written from now on are emitted.
+    fn open(path: &Path, from_start: bool) -> Option<Tail> {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::fs::MetadataExt;
+        let file = File::open(path).ok()?;
+        let meta = file.metadata().ok()?;
+        let mut header = String::new();
+        let header_len = BufReader::new(&file).read_line(&mut header).ok()? as u64;
+        let columns = header.trim_end().split(',').map(str::to_string).collect();
+        let pos = if from_start || !header.ends_with('\n') { header_len } else { meta.len() };
+        let mut tail = Tail { file, ino: meta.ino(), pos, columns, partial: String::new() };
+        // A header still being written: re-read it on the next poll
+        if !header.ends_with('\n') { tail.pos = 0; tail.columns.clear(); }
+        Some(tail)
+    }
+
+    // Emits every complete row appended since the last call.
+    fn drain(&mut self, emit: &mut impl FnMut(&str)) {
+        use std::io::{Read, Seek, SeekFrom};
+        if self.file.seek(SeekFrom::Start(self.pos)).is_err() { return; }
+        let mut buf = String::new();
+        let Ok(n) = (&self.file).read_to_string(&mut buf) else { return };
+        self.pos += n as u64;
+        self.partial.push_str(&buf);
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() { continue; }
+            if self.columns.is_empty() { self.columns = line.split(',').map(str::to_string).collect(); continue; }
+            emit(&row_to_json(&self.columns, &parse_row(line)));
+        }
+    }
+}
+
+pub fn follow(path: &str, from_start: bool, mut emit: impl FnMut(&str)) -> ! {
+    use std::os::unix::fs::MetadataExt;
+    let path = crate::config::expand_home(path);
+    let poll = std::time::Duration::from_millis(250);
+    let mut tail: Option<Tail> = None;
+    let mut waiting = false;
+    loop {
+        match &mut tail {
+            None => match Tail::open(&path, from_start) {
+                Some(t) => { tail = Some(t); waiting = false; }
+                None if !waiting => { eprintln!("follow: waiting for {}", path.display()); waiting = true; }
+                None => {}
+            },
+            Some(t) => {
+                t.drain(&mut emit);
+                let current = std::fs::metadata(&path).ok();
+                let rotated = current.as_ref().is_some_and(|m| m.ino() != t.ino);
+                let truncated = current.as_ref().is_some_and(|m| m.ino() == t.ino && m.len() < t.pos);
+                if rotated || truncated {
+                    // The replacement file is new data: read it from the top
+                    tail = Tail::open(&path, true);
+                    continue;
+                }
+            }
+        }
+        std::thread::sleep(poll);
+    }
+}
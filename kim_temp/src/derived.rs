@@ -0,0 +1,133 @@
+// User-defined derived metrics, e.g. `soc_misc_w = PSTR - PZD1 - PP2b`.
+// Expressions support SMC keys, numbers, + - * / and parentheses.
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Num(f64),
+    Key(String),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Derived {
+    pub name: String,
+    pub expr: Expr,
+}
+
+impl Derived {
+    // Parses `name = expr`.
+    pub fn parse(def: &str) -> Result<Derived, String> {
+        let (name, rhs) = def.split_once('=').ok_or_else(|| format!("missing '=' in derived metric: {}", def))?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid derived metric name: {:?}", name));
+        }
+        let tokens = tokenize(rhs)?;
+        let mut pos = 0;
+        let expr = parse_sum(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(format!("unexpected trailing input in derived metric: {}", def));
+        }
+        Ok(Derived { name: name.to_string(), expr })
+    }
+
+    // Evaluates the expression; `read` resolves an SMC key to its current value.
+    // Returns None if any referenced key cannot be read.
+    pub fn eval(&self, read: &mut dyn FnMut(&str) -> Option<f64>) -> Option<f64> {
+        eval(&self.expr, read)
+    }
+}
+
+fn eval(expr: &Expr, read: &mut dyn FnMut(&str) -> Option<f64>) -> Option<f64> {
+    match expr {
+        Expr::Num(n) => Some(*n),
+        Expr::Key(k) => read(k),
+        Expr::Neg(e) => eval(e, read).map(|v| -v),
+        Expr::Bin(op, a, b) => {
+            let (a, b) = (eval(a, read)?, eval(b, read)?);
+            match op {
+                '+' => Some(a + b),
+                '-' => Some(a - b),
+                '*' => Some(a * b),
+                _ => if b == 0.0 { None } else { Some(a / b) },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Tok>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() { i += 1; continue; }
+        if "+-*/()".contains(c) { out.push(Tok::Op(c)); i += 1; continue; }
+        let start = i;
+        if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') { i += 1; }
+            let text: String = chars[start..i].iter().collect();
+            out.push(Tok::Num(text.parse().map_err(|_| format!("bad number: {}", text))?));
+        } else if c.is_ascii_alphanumeric() {
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() { i += 1; }
+            out.push(Tok::Ident(chars[start..i].iter().collect()));
+        } else {
+            return Err(format!("unexpected character {:?} in expression", c));
+        }
+    }
+    Ok(out)
+}
+
+fn parse_sum(t: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+    let mut lhs = parse_product(t, pos)?;
+    while let Some(Tok::Op(op @ ('+' | '-'))) = t.get(*pos) {
+        *pos += 1;
+        let rhs = parse_product(t, pos)?;
+        lhs = Expr::Bin(*op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_product(t: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+    let mut lhs = parse_atom(t, pos)?;
+    while let Some(Tok::Op(op @ ('*' | '/'))) = t.get(*pos) {
+        *pos += 1;
+        let rhs = parse_atom(t, pos)?;
+        lhs = Expr::Bin(*op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_atom(t: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+    let tok = t.get(*pos).cloned().ok_or("unexpected end of expression")?;
+    *pos += 1;
+    match tok {
+        Tok::Num(n) => Ok(Expr::Num(n)),
+        Tok::Ident(k) if k.len() == 4 => Ok(Expr::Key(k)),
+        Tok::Ident(k) => Err(format!("not a 4-character SMC key: {}", k)),
+        Tok::Op('-') => Ok(Expr::Neg(Box::new(parse_atom(t, pos)?))),
+        Tok::Op('(') => {
+            let e = parse_sum(t, pos)?;
+            if t.get(*pos) != Some(&Tok::Op(')')) { return Err("missing ')'".into()); }
+            *pos += 1;
+            Ok(e)
+        }
+        Tok::Op(c) => Err(format!("unexpected '{}' in expression", c)),
+    }
+}
+
+// Formats evaluated metrics as the body of a JSON object; unreadable metrics become null.
+pub fn to_json(values: &[(String, Option<f64>)]) -> String {
+    values.iter().map(|(n, v)| match v {
+        Some(v) => format!("\"{}\":{:.3}", n, v),
+        None => format!("\"{}\":null", n),
+    }).collect::<Vec<_>>().join(",")
+}
@@ -0,0 +1,73 @@
+// Internal diagnostics: what went wrong underneath a reading (keys skipped, values that don't
+// decode, subprocesses that fail, a server that stops answering), as opposed to the command's
+// own output and usage errors. Warnings always show; -v adds info, -vv debug (every swallowed
+// failure), -vvv trace (every SMC read and socket attempt). With feature "tracing" they go
+// through `tracing`, and --log-json writes them as one JSON object per line; without it they
+// are plain stderr lines and --log-json is refused.
+//
+// Call sites use only the format-string form (`warn!("cannot open {}: {}", path, e)`), which
+// both implementations accept.
+
+// How much a word on the command line adds to the verbosity: `-v` 1, `-vv` 2, `--verbose` 1.
+// None for anything else.
+pub fn verbosity(arg: &str) -> Option<u8> {
+    match arg {
+        "--verbose" => Some(1),
+        a if a.len() > 1 && a.starts_with('-') && a[1..].bytes().all(|b| b == b'v') => Some((a.len() - 1).min(u8::MAX as usize) as u8),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub use tracing::{debug, info, trace, warn};
+
+static DISCARDED: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// `v` when it is a number. NaN and the infinities (a sensor mid-reset, a "nan" in a tool's
+// output, an overflowing --derive) are left out of every average, sum and sort; each one is
+// logged at debug with the running count.
+pub fn finite(v: f64, what: &str) -> Option<f64> {
+    if v.is_finite() { return Some(v); }
+    let n = DISCARDED.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+    debug!("discarded non-finite {} ({}), {} so far", what, v, n);
+    None
+}
+
+#[cfg(feature = "tracing")]
+pub fn init(verbosity: u8, json: bool) -> Result<(), String> {
+    use std::io::IsTerminal;
+    use tracing::Level;
+    let level = match verbosity { 0 => Level::WARN, 1 => Level::INFO, 2 => Level::DEBUG, _ => Level::TRACE };
+    let fmt = tracing_subscriber::fmt().with_max_level(level).with_writer(std::io::stderr).with_ansi(std::io::stderr().is_terminal());
+    // Warnings alone read like the rest of stderr; once asked for more, say when and where from
+    if json { fmt.json().init(); } else if verbosity == 0 { fmt.without_time().with_target(false).init(); } else { fmt.init(); }
+    Ok(())
+}
+
+#[cfg(not(feature = "tracing"))]
+static VERBOSITY: std::sync::atomic::AtomicU8 = std::sync::atomic::AtomicU8::new(0);
+
+#[cfg(not(feature = "tracing"))]
+pub fn init(verbosity: u8, json: bool) -> Result<(), String> {
+    if json { return Err("--log-json needs a build with the \"tracing\" feature".to_string()); }
+    VERBOSITY.store(verbosity, std::sync::atomic::Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn enabled(verbosity: u8) -> bool {
+    VERBOSITY.load(std::sync::atomic::Ordering::Relaxed) >= verbosity
+}
+
+#[cfg(not(feature = "tracing"))]
+mod plain {
+    // Named apart from the built-in #[warn] attribute, which a plain `use warn` would be ambiguous with
+    macro_rules! warning { ($($arg:tt)*) => { eprintln!("Warning: {}", format_args!($($arg)*)) }; }
+    macro_rules! info { ($($arg:tt)*) => { if $crate::diag::enabled(1) { eprintln!("info: {}", format_args!($($arg)*)) } }; }
+    macro_rules! debug { ($($arg:tt)*) => { if $crate::diag::enabled(2) { eprintln!("debug: {}", format_args!($($arg)*)) } }; }
+    macro_rules! trace { ($($arg:tt)*) => { if $crate::diag::enabled(3) { eprintln!("trace: {}", format_args!($($arg)*)) } }; }
+    pub(crate) use {debug, info, trace, warning as warn};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use plain::{debug, info, trace, warn};
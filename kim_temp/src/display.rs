@@ -0,0 +1,20 @@
+// Built-in display detection. Mac minis/Studios (and clamshell-only setups) have no panel
+// backlight, so the rail-difference screen estimate is meaningless there and is reported as null.
+
+// True when IOMobileFramebuffer exposes a brightness level, i.e. there is a built-in panel.
+// Checked once at startup; the ioreg lookup is too slow to repeat every cycle.
+pub fn has_backlight() -> bool {
+    crate::subprocess::stdout(std::process::Command::new("ioreg").args(["-r", "-d", "1", "-k", "IOMFBBrightnessLevel"]))
+        .is_some_and(|s| s.contains("IOMFBBrightnessLevel"))
+}
+
+// Screen power estimated as battery rail minus system rail; only meaningful with both a panel and a battery.
+pub fn screen_power_w(backlight: bool, bat_power: Option<f32>, sys_power: f32) -> Option<f32> {
+    screen_power_raw_w(backlight, bat_power, sys_power).map(|w| w.max(0.0))
+}
+
+// The unclamped rail difference; negative when the rails are sampled at different moments.
+pub fn screen_power_raw_w(backlight: bool, bat_power: Option<f32>, sys_power: f32) -> Option<f32> {
+    if !backlight { return None; }
+    bat_power.map(|b| b - sys_power)
+}
@@ -0,0 +1,167 @@
+// `doctor`: checks what the other modes silently depend on and says how to fix what's missing,
+// since most "N/A" output is an environment problem rather than a bug: SMC access, the chip
+// profile, the temperature key families for it, the power rails, the battery, powermetrics
+// access (root, the root helper, or the sudo rule) and write access to every log path. Exits 1
+// when something fails outright; warnings leave some fields empty but everything else works.
+
+use crate::config::Config;
+use crate::profile::{Family, Profile};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status { Ok, Warn, Fail }
+
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+fn check(name: &'static str, status: Status, detail: impl Into<String>, fix: Option<&str>) -> Check {
+    Check { name, status, detail: detail.into(), fix: fix.map(str::to_string) }
+}
+
+// Whether `path` can be appended to: the file itself when it exists, else the nearest existing
+// directory above it (which the log writers create the rest of).
+fn writable(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        return std::fs::OpenOptions::new().append(true).open(path).map(|_| ()).map_err(|e| e.to_string());
+    }
+    let dir = path.ancestors().skip(1).find(|d| d.exists()).unwrap_or(Path::new("/"));
+    let c = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes()).map_err(|e| e.to_string())?;
+    if unsafe { libc::access(c.as_ptr(), libc::W_OK) } == 0 { Ok(()) } else { Err(format!("{} is not writable", dir.display())) }
+}
+
+fn smc(checks: &mut Vec<Check>) -> bool {
+    match crate::smcraw::shared().and_then(|raw| raw.key_count()) {
+        Ok(n) => { checks.push(check("smc", Status::Ok, format!("AppleSMC open, {} keys", n), None)); true }
+        Err(e) => {
+            checks.push(check("smc", Status::Fail, e, Some("kim_temp reads the AppleSMC driver, so it has to run on the Mac itself (not in a VM or container); if this is a Mac, reboot and try again")));
+            false
+        }
+    }
+}
+
+fn chip(profile: &Profile, checks: &mut Vec<Check>) {
+    let name = if profile.chip.is_empty() { "unknown chip".to_string() } else { profile.chip.clone() };
+    if profile.family == Family::Unknown {
+        checks.push(check("chip", Status::Warn, format!("{} ({}) has no built-in profile; generic key rules are used", name, profile.model),
+            Some("check the categories below; mark misfiled sensors with `kim_temp explore` (r cycles a key's role)")));
+    } else {
+        checks.push(check("chip", Status::Ok, format!("{} ({}), {} profile{}", name, profile.model, profile.family.as_str(), if profile.roles.is_empty() { String::new() } else { format!(", {} roles marked", profile.roles.len()) }), None));
+    }
+}
+
+fn sensors(config: &Config, profile: &Profile, has_battery: bool, checks: &mut Vec<Check>) {
+    let sensors = match crate::sensor_keys(&config.sensors) {
+        Ok(k) => crate::temps::sensors(k, profile),
+        Err(e) => { checks.push(check("sensors", Status::Fail, e, Some("see the smc check above"))); return; }
+    };
+    let t = crate::temps::Temps::collect(&sensors, None);
+    let mut families = vec![("CPU", t.cpu.len(), "cpu_temp"), ("GPU", t.gpu.len(), "gpu_temp"), ("memory", t.mem.len(), "mem_temp"), ("SSD", t.ssd.len(), "ssd_temp")];
+    if has_battery { families.push(("battery", t.bat.len(), "bat_temp")); }
+    let missing: Vec<&(&str, usize, &str)> = families.iter().filter(|(_, n, _)| *n == 0).collect();
+    let counts = families.iter().map(|(f, n, _)| format!("{} {}", f, n)).collect::<Vec<_>>().join(", ");
+    if missing.is_empty() {
+        checks.push(check("sensors", Status::Ok, format!("{} valid temperature keys: {}", t.sensors.len(), counts), None));
+        return;
+    }
+    let fields = missing.iter().map(|(_, _, f)| *f).collect::<Vec<_>>().join(", ");
+    let filtered = !config.sensors.include.is_empty() || !config.sensors.exclude.is_empty();
+    let fix = if filtered { "[sensors] include/exclude in the config may be filtering them out; otherwise mark the right keys with `kim_temp explore`" } else { "mark the right keys with `kim_temp explore` (r cycles a key's role), or ask for a fleet `roles` file" };
+    checks.push(check("sensors", Status::Warn, format!("{} valid temperature keys ({}); no readable keys for {}, which will read N/A", t.sensors.len(), counts, fields), Some(fix)));
+}
+
+fn rails(profile: &Profile, has_battery: bool, checks: &mut Vec<Check>) {
+    let read = |key: &str| crate::value::read_f64(key);
+    match read(&profile.system_power_key) {
+        Some(w) => checks.push(check("power", Status::Ok, format!("system rail {} reads {:.2} W", profile.system_power_key, w), None)),
+        None => checks.push(check("power", Status::Fail, format!("system rail {} can't be read, so power_w is 0", profile.system_power_key),
+            Some("find the total system power key with `kim_temp power-all` or `explore`, and set it as `system` under [power_keys] in the config"))),
+    }
+    if has_battery && read(&profile.battery_power_key).is_none() {
+        checks.push(check("power", Status::Warn, format!("battery rail {} can't be read, so bat_power_w and screen_w are unreliable", profile.battery_power_key),
+            Some("set `battery` under [power_keys] in the config")));
+    }
+    for (rail, candidates, current) in [("cpu", profile.cpu_power_keys, &profile.cpu_power_key), ("gpu", profile.gpu_power_keys, &profile.gpu_power_key)] {
+        let (picked, results) = crate::power::select(candidates, &profile.system_power_key);
+        match picked {
+            Some(key) => checks.push(check("power", Status::Ok, format!("{} rail {} validated{}", rail, key, if key != current.as_str() { format!(" (config or roles use {})", current) } else { String::new() }), None)),
+            None => checks.push(check("power", Status::Warn, format!("no {} rail validated ({})", rail, results.iter().map(|c| format!("{}: {}", c.key, c.reason)).collect::<Vec<_>>().join("; ")),
+                Some("`kim_temp profile` shows the candidates; set the right key under [power_keys] in the config"))),
+        }
+    }
+}
+
+fn battery(profile: &Profile, has_battery: Option<f32>, checks: &mut Vec<Check>) {
+    match has_battery {
+        Some(wh) => checks.push(check("battery", Status::Ok, format!("battery present, {:.0} Wh design capacity", wh), None)),
+        None if profile.model.starts_with("MacBook") => checks.push(check("battery", Status::Warn, format!("{} reports no battery, so battery fields are null", profile.model),
+            Some("`ioreg -r -c AppleSmartBattery` should list it; if it doesn't, the battery isn't being reported by macOS (check System Settings > Battery)"))),
+        None => checks.push(check("battery", Status::Ok, "no battery (desktop): battery fields are null", None)),
+    }
+}
+
+fn powermetrics(checks: &mut Vec<Check>) {
+    let socket = crate::serve::socket_path(None);
+    let fix = "install the root helper (`sudo kim_temp install-daemon --serve`) or the sudo rule (`sudo kim_temp setup-sudo`)";
+    if crate::serve::powermetrics_output().is_some() {
+        checks.push(check("powermetrics", Status::Ok, format!("root helper answering on {}", socket.display()), None));
+    } else if crate::sample::powermetrics_output().is_some() {
+        let how = if unsafe { libc::geteuid() } == 0 { "running as root" } else { "`sudo -n` is allowed" };
+        checks.push(check("powermetrics", Status::Ok, format!("runs directly ({})", how), None));
+    } else if std::os::unix::net::UnixStream::connect(&socket).is_err_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied) {
+        checks.push(check("powermetrics", Status::Warn, format!("the root helper on {} only lets in root and its group, and `sudo -n powermetrics` is refused", socket.display()),
+            Some("join the socket's group (`ls -l` shows it), or reinstall the helper for everyone: `sudo kim_temp install-daemon --serve -- --socket-group staff`")));
+    } else if socket.exists() {
+        checks.push(check("powermetrics", Status::Warn, format!("{} exists but no server answers powermetrics there, and `sudo -n powermetrics` is refused", socket.display()),
+            Some("restart the helper (`sudo kim_temp install-daemon --serve` replaces it) or see /var/log/kim_temp/serve.err")));
+    } else {
+        checks.push(check("powermetrics", Status::Warn, "not available: cpu_mw, gpu_mw, ane_mw, wakeups and process fields will be empty", Some(fix)));
+    }
+}
+
+fn logs(paths: &[(String, PathBuf)], checks: &mut Vec<Check>) {
+    for (what, path) in paths {
+        match writable(path) {
+            Ok(()) => checks.push(check("logging", Status::Ok, format!("{} {} is writable", what, path.display()), None)),
+            Err(e) => checks.push(check("logging", Status::Fail, format!("{} {}: {}", what, path.display(), e),
+                Some("fix the directory's owner or permissions (`ls -ld` it), or point this path elsewhere in the config"))),
+        }
+    }
+}
+
+// Every check in order; `log_paths` are the files this configuration would append to.
+pub fn run(config: &Config, log_paths: &[(String, PathBuf)]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut profile = crate::profile::detect();
+    for (over, key) in [(&config.power_keys.system, &mut profile.system_power_key), (&config.power_keys.battery, &mut profile.battery_power_key), (&config.power_keys.cpu, &mut profile.cpu_power_key), (&config.power_keys.gpu, &mut profile.gpu_power_key)] {
+        if let Some(k) = over.as_ref().filter(|k| k.len() == 4) { *key = k.clone(); }
+    }
+    let bat = crate::battery::detect().map(|b| b.design_wh);
+    if smc(&mut checks) {
+        chip(&profile, &mut checks);
+        sensors(config, &profile, bat.is_some(), &mut checks);
+        rails(&profile, bat.is_some(), &mut checks);
+    } else {
+        chip(&profile, &mut checks);
+    }
+    battery(&profile, bat, &mut checks);
+    powermetrics(&mut checks);
+    logs(log_paths, &mut checks);
+    checks
+}
+
+// The report, one check per line with its fix underneath.
+pub fn report(checks: &[Check]) -> String {
+    let mut out = String::new();
+    for c in checks {
+        let status = match c.status { Status::Ok => "ok  ", Status::Warn => "warn", Status::Fail => "FAIL" };
+        out.push_str(&format!("{}  {:<13} {}\n", status, c.name, c.detail));
+        if let Some(fix) = &c.fix { out.push_str(&format!("      {:<13} fix: {}\n", "", fix)); }
+    }
+    let (warn, fail) = (checks.iter().filter(|c| c.status == Status::Warn).count(), checks.iter().filter(|c| c.status == Status::Fail).count());
+    out.push_str(&match (warn, fail) { (0, 0) => "Everything kim_temp needs is in place.\n".to_string(), _ => format!("{} failed, {} warnings.\n", fail, warn) });
+    out
+}
@@ -0,0 +1,78 @@
+// Voltage (`V*`) and current (`I*`) keys: the `volts`/`amps` modes list every one this SMC
+// reports, `json --detail` the known rails. Values are decoded by each key's own SMC type (flt,
+// or fixed point like sp4b/fp2e), not assumed to be f32. Where a rail has both, V×I is an
+// independent power figure to hold the matching `P*` key against.
+
+use crate::keydb;
+use crate::smcraw::Raw;
+use crate::value::SmcValue;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantity { Volts, Amps }
+
+impl Quantity {
+    fn prefix(self) -> char {
+        match self { Quantity::Volts => 'V', Quantity::Amps => 'I' }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self { Quantity::Volts => "V", Quantity::Amps => "A" }
+    }
+
+    // Nothing in a Mac runs above USB-PD's 48 V or draws more than ~100 A on one rail; anything
+    // outside is a misdecoded or unrelated key. Currents can be negative (battery charging).
+    fn plausible(self, v: f64) -> bool {
+        v.is_finite() && match self { Quantity::Volts => (0.0..=50.0).contains(&v), Quantity::Amps => v.abs() <= 100.0 }
+    }
+}
+
+pub struct Reading {
+    pub key: String,
+    pub value: f64,
+    // The SMC type it was decoded from, e.g. "sp4b"
+    pub data_type: String,
+}
+
+fn read(raw: &Raw, key: &str, q: Quantity) -> Option<Reading> {
+    let (info, bytes) = crate::smcraw::guarded(key, || raw.read(key)).ok()?;
+    let value = SmcValue::from_info(&info, &bytes).as_f64().filter(|v| q.plausible(*v))?;
+    Some(Reading { key: key.to_string(), value, data_type: info.data_type })
+}
+
+// Every key of the quantity in SMC order; keys of a type we can't decode are left out.
+pub fn scan(raw: &Raw, keys: &[String], q: Quantity) -> Vec<Reading> {
+    keys.iter().filter(|k| k.starts_with(q.prefix())).filter_map(|k| read(raw, k, q)).collect()
+}
+
+// Only the rails keydb names exactly, read directly (no key enumeration).
+pub fn known(raw: &Raw, q: Quantity) -> Vec<Reading> {
+    keydb::exact_keys().filter(|k| k.starts_with(q.prefix())).filter_map(|k| read(raw, k, q)).collect()
+}
+
+pub fn to_json(readings: &[Reading], q: Quantity) -> String {
+    format!("[{}]", readings.iter().map(|r| format!("{{\"key\":\"{}\",\"name\":\"{}\",\"{}\":{:.3}}}", r.key, keydb::describe(&r.key).unwrap_or(""), q.unit().to_lowercase(), r.value)).collect::<Vec<_>>().join(","))
+}
+
+// V×I for a rail (`VD0R` and `ID0R` share the suffix `D0R`), with what its `P*` key reports.
+pub struct Power {
+    pub rail: String,
+    pub volts: f64,
+    pub amps: f64,
+    pub reported: Option<(String, f64)>,
+}
+
+impl Power {
+    pub fn watts(&self) -> f64 {
+        self.volts * self.amps
+    }
+}
+
+pub fn power(raw: &Raw, volts: &[Reading], amps: &[Reading]) -> Vec<Power> {
+    volts.iter().filter_map(|v| {
+        let rail = &v.key[1..];
+        let a = amps.iter().find(|a| &a.key[1..] == rail)?;
+        let p_key = format!("P{}", rail);
+        let reported = raw.read(&p_key).ok().and_then(|(info, bytes)| SmcValue::from_info(&info, &bytes).as_f64()).map(|w| (p_key, w));
+        Some(Power { rail: rail.to_string(), volts: v.value, amps: a.value, reported })
+    }).collect()
+}
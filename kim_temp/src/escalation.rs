@@ -0,0 +1,139 @@
+// Escalation for critical alerts, meant for unattended runs: a notification when the alert
+// fires, a sound if it is still firing after `sound_after`, and optionally SIGSTOP of the named
+// processes after `stop_after`. Stopped processes get SIGCONT when the alert resolves, and also
+// if kim_temp itself is interrupted or terminated, so a job is never left frozen.
+
+#[cfg(feature = "config")]
+use serde::Deserialize;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
+
+// Never stopped: the system, the display server, and anything keeping the machine awake.
+const PROTECTED: &[&str] = &["kernel_task", "launchd", "WindowServer", "loginwindow", "caffeinate", "powerd", "kim_temp", "kim_temp_bin"];
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(deny_unknown_fields))]
+pub struct EscalationConfig {
+    pub sound_after: Option<String>,
+    // Name of a sound in /System/Library/Sounds, default "Sosumi"
+    pub sound: Option<String>,
+    pub stop_after: Option<String>,
+    // Process names (exact match) to SIGSTOP
+    #[cfg_attr(feature = "config", serde(default))]
+    pub stop: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Escalation {
+    sound_after: Option<Duration>,
+    sound: String,
+    stop_after: Option<Duration>,
+    stop: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Progress {
+    sounded: bool,
+    stopped: Vec<i32>,
+}
+
+impl Escalation {
+    pub fn from_config(cfg: &EscalationConfig) -> Result<Escalation, String> {
+        let dur = |s: &Option<String>| match s {
+            Some(s) => crate::alerts::parse_duration(s).map(Some).ok_or_else(|| format!("invalid escalation duration {:?}", s)),
+            None => Ok(None),
+        };
+        if let Some(p) = cfg.stop.iter().find(|p| PROTECTED.contains(&p.as_str())) {
+            return Err(format!("refusing to escalate by stopping {:?}", p));
+        }
+        let stop_after = dur(&cfg.stop_after)?;
+        if stop_after.is_some() && cfg.stop.is_empty() {
+            return Err("escalation stop_after needs a `stop` process list".to_string());
+        }
+        Ok(Escalation { sound_after: dur(&cfg.sound_after)?, sound: cfg.sound.clone().unwrap_or_else(|| "Sosumi".to_string()), stop_after, stop: cfg.stop.clone() })
+    }
+
+    pub fn stops_processes(&self) -> bool { self.stop_after.is_some() }
+
+    // Advances the escalation for an alert that has been firing for `firing_for`; returns events.
+    pub fn step(&self, progress: &mut Progress, firing_for: Duration, rule_name: &str) -> Vec<String> {
+        let mut events = Vec::new();
+        if !progress.sounded && self.sound_after.is_some_and(|d| firing_for >= d) {
+            progress.sounded = true;
+            play(&self.sound);
+            events.push(format!("{{\"type\":\"escalation\",\"name\":{},\"action\":\"sound\"}}", crate::json_str(rule_name)));
+        }
+        if progress.stopped.is_empty() && self.stop_after.is_some_and(|d| firing_for >= d) {
+            for name in &self.stop {
+                for pid in pids_named(name) {
+                    if unsafe { libc::kill(pid, libc::SIGSTOP) } == 0 {
+                        remember(pid);
+                        progress.stopped.push(pid);
+                        events.push(format!("{{\"type\":\"escalation\",\"name\":{},\"action\":\"stop\",\"process\":{},\"pid\":{}}}", crate::json_str(rule_name), crate::json_str(name), pid));
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    // Resumes everything this alert stopped; called when it resolves.
+    pub fn resume(&self, progress: &mut Progress, rule_name: &str) -> Vec<String> {
+        progress.sounded = false;
+        progress.stopped.drain(..).map(|pid| {
+            unsafe { libc::kill(pid, libc::SIGCONT) };
+            forget(pid);
+            format!("{{\"type\":\"escalation\",\"name\":{},\"action\":\"resume\",\"pid\":{}}}", crate::json_str(rule_name), pid)
+        }).collect()
+    }
+}
+
+fn play(sound: &str) {
+    let path = format!("/System/Library/Sounds/{}.aiff", sound);
+    if let Ok(mut c) = std::process::Command::new("afplay").arg(path).spawn() {
+        std::thread::spawn(move || { let _ = c.wait(); });
+    }
+}
+
+fn pids_named(name: &str) -> Vec<i32> {
+    let me = std::process::id() as i32;
+    crate::subprocess::stdout(std::process::Command::new("pgrep").args(["-x", name])).unwrap_or_default()
+        .lines().filter_map(|l| l.trim().parse().ok()).filter(|p| *p != me).collect()
+}
+
+// ---- resume on exit ----
+// Stopped pids live in a fixed table of atomics so the signal handler can read it safely.
+
+const SLOTS: usize = 64;
+static STOPPED: [AtomicI32; SLOTS] = [const { AtomicI32::new(0) }; SLOTS];
+
+fn remember(pid: i32) {
+    if !STOPPED.iter().any(|s| s.compare_exchange(0, pid, Ordering::SeqCst, Ordering::SeqCst).is_ok()) {
+        crate::diag::warn!("too many stopped processes to track; pid {} will not be resumed on exit", pid);
+    }
+}
+
+fn forget(pid: i32) {
+    for s in &STOPPED { let _ = s.compare_exchange(pid, 0, Ordering::SeqCst, Ordering::SeqCst); }
+}
+
+// Sends SIGCONT to everything still stopped; async-signal-safe.
+pub fn resume_stopped() {
+    for s in &STOPPED {
+        let pid = s.load(Ordering::SeqCst);
+        if pid > 0 { unsafe { libc::kill(pid, libc::SIGCONT) }; }
+    }
+}
+
+extern "C" fn resume_and_exit(sig: libc::c_int) {
+    resume_stopped();
+    unsafe { libc::_exit(128 + sig) };
+}
+
+// Makes SIGINT/SIGTERM/SIGHUP resume stopped processes before exiting.
+pub fn install_exit_guard() {
+    for sig in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        unsafe { libc::signal(sig, resume_and_exit as *const () as libc::sighandler_t) };
+    }
+}
@@ -0,0 +1,45 @@
+// Persisted `stream` events (throttling, adapter changes, alerts, self-tests): one JSON object
+// per line with the sample's `ts` and `seq` added, appended across runs so episodes can be
+// lined up with workloads afterwards. Default `~/Library/Logs/kim_temp/events.jsonl`.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Logs/kim_temp/events.jsonl"))
+}
+
+pub struct EventLog {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl EventLog {
+    pub fn new(path: PathBuf) -> EventLog {
+        EventLog { path, file: None }
+    }
+
+    // Opened on the first event, so runs without events leave no file behind. A write error is
+    // reported once and turns the log off.
+    pub fn append(&mut self, ts: &str, seq: u64, events: &[String]) {
+        if events.is_empty() || self.path.as_os_str().is_empty() { return; }
+        if self.file.is_none() {
+            if let Some(dir) = self.path.parent() { let _ = std::fs::create_dir_all(dir); }
+            match std::fs::OpenOptions::new().create(true).append(true).open(&self.path) {
+                Ok(f) => self.file = Some(f),
+                Err(e) => { crate::diag::warn!("cannot open event log {}: {}", self.path.display(), e); self.path = PathBuf::new(); return; }
+            }
+        }
+        let lines: String = events.iter().filter_map(|e| e.strip_prefix('{')).map(|rest| format!("{{\"ts\":\"{}\",\"seq\":{},{}\n", ts, seq, rest)).collect();
+        if let Some(Err(e)) = self.file.as_mut().map(|f| f.write_all(lines.as_bytes())) {
+            crate::diag::warn!("dropping event log {}: {}", self.path.display(), e);
+            self.file = None;
+            self.path = PathBuf::new();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(f) = &self.file { let _ = f.sync_data(); }
+    }
+}
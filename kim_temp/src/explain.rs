@@ -0,0 +1,119 @@
+// `explain`: why the fan is on right now, as a ranked list of causes from one enriched sample
+// instead of a wall of numbers. Each cause is scored by the heat it puts into the chassis in W:
+// a hot zone by its rail's power, charging by the adapter path's conversion loss, the panel by
+// its estimated draw, warm intake air by 1 W per °C over 30 °C (rough, but it ranks a hot room
+// next to a busy GPU sensibly). Zones are named with the processes using the most CPU time,
+// since powermetrics' tasks sampler has no per-process GPU column.
+
+use crate::config::Units;
+use crate::profile::Profile;
+use crate::sample::Powermetrics;
+use crate::temps::Sensor;
+use crate::temps::{self, Temps};
+use crate::{json_num, json_opt, json_str};
+
+// (kind, name, °C from which the zone is worth naming); a cause needs this many W of heat
+const ZONES: &[(&str, &str, f64)] = &[("gpu", "GPU", 65.0), ("cpu", "CPU", 65.0), ("ssd", "SSD", 55.0), ("mem", "Memory", 65.0), ("battery", "Battery", 38.0)];
+const MIN_HEAT_W: f64 = 1.0;
+const AIR_BASE_C: f64 = 30.0;
+const DISPLAY_MIN_W: f64 = 3.0;
+// Processes named per zone
+const NAMED: usize = 2;
+
+pub struct Cause {
+    pub kind: &'static str,
+    pub heat_w: f64,
+    pub text: String,
+}
+
+// The enriched sample behind one explanation
+pub struct Inputs {
+    temps: Temps,
+    pm: Powermetrics,
+    cpu_w: Option<f64>,
+    gpu_w: Option<f64>,
+    display_w: Option<f64>,
+    adapter: Option<crate::battery::Adapter>,
+    charging: bool,
+    // (actual, max) rpm per fan
+    fans: Vec<(f64, Option<f64>)>,
+    pressure: Option<&'static str>,
+}
+
+pub fn collect(profile: &Profile, sensors: &[Sensor], excluded: &[&str]) -> Inputs {
+    let temps = Temps::collect(sensors, None);
+    let pm = crate::sample::powermetrics(excluded);
+    let read = |k: &str| crate::value::read_f64(k).filter(|v| v.is_finite());
+    let battery = crate::battery::detect();
+    let (sys_raw, bat_w) = (crate::read_w(&profile.system_power_key), battery.as_ref().map(|_| crate::read_w(&profile.battery_power_key)));
+    let fans = (0..read("FNum").map_or(0, |n| n as u32).min(4)).filter_map(|i| Some((read(&format!("F{}Ac", i))?, read(&format!("F{}Mx", i))))).collect();
+    Inputs {
+        cpu_w: read(&profile.cpu_power_key).or((pm.cpu_mw > 0).then(|| pm.cpu_mw as f64 / 1000.0)),
+        gpu_w: read(&profile.gpu_power_key).or((pm.gpu_mw > 0).then(|| pm.gpu_mw as f64 / 1000.0)),
+        display_w: crate::display::screen_power_w(crate::display::has_backlight(), bat_w, sys_raw).map(|w| w as f64),
+        adapter: battery.as_ref().and_then(|_| crate::battery::adapter()),
+        charging: battery.is_some() && crate::battery::pmset_status().1,
+        fans,
+        pressure: crate::thermal::Pressure::open().and_then(|p| p.level()).map(|(name, _)| name),
+        temps, pm,
+    }
+}
+
+fn processes(pm: &Powermetrics) -> String {
+    let names: Vec<&str> = pm.top.iter().filter(|(_, ms)| *ms >= 1.0).take(NAMED).map(|(n, _)| n.as_str()).collect();
+    if names.is_empty() { String::new() } else { format!(": {}", names.join(" + ")) }
+}
+
+// Largest heat first; empty when nothing stands out.
+pub fn causes(i: &Inputs, units: Units) -> Vec<Cause> {
+    let mut out = Vec::new();
+    let deg = |c: f64| format!("{:.0}{}", units.temp(c), units.symbol());
+    for &(kind, name, warm) in ZONES {
+        let (readings, rail) = match kind { "gpu" => (&i.temps.gpu, i.gpu_w), "cpu" => (&i.temps.cpu, i.cpu_w), "ssd" => (&i.temps.ssd, None), "mem" => (&i.temps.mem, None), _ => (&i.temps.bat, None) };
+        let Some(hottest) = temps::max(readings).filter(|t| *t >= warm) else { continue };
+        let text = match rail {
+            Some(w) => format!("{} at {} drawing {:.1} W{}", name, deg(hottest), w, processes(&i.pm)),
+            None => format!("{} at {}", name, deg(hottest)),
+        };
+        // A hot zone without a rail still ranks, by how far past warm it is
+        out.push(Cause { kind, heat_w: rail.unwrap_or(hottest - warm).max(MIN_HEAT_W), text });
+    }
+    if let Some(a) = i.adapter.as_ref().filter(|_| i.charging) {
+        if let Some(loss) = a.loss_w.filter(|w| *w >= MIN_HEAT_W) {
+            let into = a.charge_w.map(|w| format!(", {:.0} W into the battery", w)).unwrap_or_default();
+            out.push(Cause { kind: "charging", heat_w: loss, text: format!("charger adding {:.1} W of heat{}", loss, into) });
+        }
+    }
+    let air = i.temps.sensors.iter().filter(|(k, _, _, _)| k.starts_with("Ta")).max_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((key, t, _, _)) = air.filter(|(_, t, _, _)| *t - AIR_BASE_C >= MIN_HEAT_W) {
+        out.push(Cause { kind: "ambient", heat_w: t - AIR_BASE_C, text: format!("intake air at {} ({}): warm room or blocked vents", deg(*t), crate::labels::label(key).unwrap_or(key)) });
+    }
+    if let Some(w) = i.display_w.filter(|w| *w >= DISPLAY_MIN_W) {
+        out.push(Cause { kind: "display", heat_w: w, text: format!("display drawing {:.1} W", w) });
+    }
+    out.sort_by(|a, b| b.heat_w.total_cmp(&a.heat_w));
+    out
+}
+
+fn fans_text(i: &Inputs) -> String {
+    if i.fans.is_empty() { return "no fan".to_string(); }
+    if i.fans.iter().all(|(rpm, _)| *rpm < 1.0) { return "fan off".to_string(); }
+    let each: Vec<String> = i.fans.iter().map(|(rpm, max)| match max { Some(m) if *m > 0.0 => format!("{:.0} rpm ({:.0}% of max)", rpm, rpm / m * 100.0), _ => format!("{:.0} rpm", rpm) }).collect();
+    format!("fan {}", each.join(", "))
+}
+
+pub fn text(i: &Inputs, causes: &[Cause], units: Units) -> String {
+    let pressure = i.pressure.map(|p| format!(", thermal pressure {}", p)).unwrap_or_default();
+    let mut out = format!("Now: {}{}\n", fans_text(i), pressure);
+    if causes.is_empty() {
+        out.push_str(&format!("Nothing stands out: CPU {:.0}{s}, GPU {:.0}{s}.\n", units.temp(temps::avg(&i.temps.cpu)), units.temp(temps::avg(&i.temps.gpu)), s = units.symbol()));
+    }
+    for (n, c) in causes.iter().enumerate() { out.push_str(&format!("{}. {}\n", n + 1, c.text)); }
+    out
+}
+
+pub fn to_json(i: &Inputs, causes: &[Cause]) -> String {
+    let fans: Vec<String> = i.fans.iter().map(|(rpm, max)| format!("{{\"rpm\":{:.0},\"max_rpm\":{}}}", rpm, json_num(*max, 0))).collect();
+    let causes: Vec<String> = causes.iter().map(|c| format!("{{\"kind\":\"{}\",\"heat_w\":{:.2},\"text\":{}}}", c.kind, c.heat_w, json_str(&c.text))).collect();
+    format!("{{\"fans\":[{}],\"thermal_pressure\":{},\"causes\":[{}]}}", fans.join(","), json_opt(i.pressure.map(json_str)), causes.join(","))
+}
@@ -0,0 +1,209 @@
+// `explore`: a full-screen key browser. Every SMC key with its type, live value and name;
+// `/` filters by fuzzy match on key and name, `p` pins the selected key to the watch pane at the
+// bottom (live value with the min/max seen since pinning), `r` cycles the key's role (see
+// roles.rs), saved for this model as soon as it changes. Only visible and pinned keys are read,
+// once a second. Plain ANSI and termios, no TUI crate.
+
+use crate::roles::{self, Role};
+use crate::smcraw::{self, KeyInfo};
+use crate::value::SmcValue;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const REFRESH: Duration = Duration::from_secs(1);
+const WATCH_ROWS: usize = 8;
+
+struct Entry {
+    key: String,
+    name: &'static str,
+    info: Option<KeyInfo>,
+    value: Option<SmcValue>,
+}
+
+struct Pin {
+    key: String,
+    value: Option<SmcValue>,
+    // (min, max) of the numeric readings since pinning
+    range: Option<(f64, f64)>,
+}
+
+// Puts the terminal in raw mode on an alternate screen; restores both when dropped.
+struct Terminal {
+    saved: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> Result<Terminal, String> {
+        if unsafe { libc::isatty(0) } == 0 { return Err("explore needs a terminal".to_string()); }
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(0, &mut saved) } != 0 { return Err("cannot read terminal settings".to_string()); }
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(0, libc::TCSANOW, &raw) } != 0 { return Err("cannot switch the terminal to raw mode".to_string()); }
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(Terminal { saved })
+    }
+
+    fn size() -> (usize, usize) {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut ws) } == 0 && ws.ws_row > 0 { (ws.ws_row as usize, ws.ws_col as usize) } else { (24, 80) }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        unsafe { libc::tcsetattr(0, libc::TCSANOW, &self.saved) };
+    }
+}
+
+enum Input { Up, Down, PageUp, PageDown, Enter, Escape, Backspace, Char(char) }
+
+// Waits up to `timeout` for a key press.
+fn read_input(timeout: Duration) -> Option<Input> {
+    let mut fd = libc::pollfd { fd: 0, events: libc::POLLIN, revents: 0 };
+    if unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) } <= 0 { return None; }
+    let mut buf = [0u8; 16];
+    let n = unsafe { libc::read(0, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if n <= 0 { return None; }
+    match &buf[..n as usize] {
+        b"\x1b[A" | b"\x1bOA" => Some(Input::Up),
+        b"\x1b[B" | b"\x1bOB" => Some(Input::Down),
+        b"\x1b[5~" => Some(Input::PageUp),
+        b"\x1b[6~" => Some(Input::PageDown),
+        b"\x1b" => Some(Input::Escape),
+        b"\r" | b"\n" => Some(Input::Enter),
+        [0x7f] | [0x08] => Some(Input::Backspace),
+        // Ctrl-C: ISIG is off in raw mode, so it arrives as a byte
+        [0x03] => Some(Input::Char('q')),
+        bytes => std::str::from_utf8(bytes).ok()?.chars().next().filter(|c| !c.is_control()).map(Input::Char),
+    }
+}
+
+// Subsequence match, case-insensitive: where the match starts plus how far it spreads (lower is
+// better); None when `query` isn't a subsequence of `text`.
+fn fuzzy(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut pos, mut first) = (0, None);
+    for q in query.to_lowercase().chars() {
+        let i = pos + text.get(pos..)?.iter().position(|c| *c == q)?;
+        first.get_or_insert(i);
+        pos = i + 1;
+    }
+    Some(first.map_or(0, |f| f + (pos - f)))
+}
+
+fn clip(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+pub fn run(keys: Vec<String>, model: &str, mut key_roles: Vec<(String, Role)>) -> Result<(), String> {
+    let raw = smcraw::shared()?;
+    let mut entries: Vec<Entry> = keys.into_iter().map(|key| Entry { name: crate::labels::label(&key).unwrap_or(""), key, info: None, value: None }).collect();
+    let mut pins: Vec<Pin> = Vec::new();
+    let (mut query, mut searching) = (String::new(), false);
+    let (mut selected, mut top) = (0usize, 0usize);
+    let mut status = String::from("↑↓ move  / search  p pin  r role  q quit");
+    let mut last_read: Option<Instant> = None;
+    let mut out = std::io::stdout();
+    let _terminal = Terminal::enter()?;
+
+    loop {
+        let (rows, cols) = Terminal::size();
+        // Indices of entries matching the query, best first (SMC order when there is none)
+        let mut shown: Vec<(usize, usize)> = entries.iter().enumerate()
+            .filter_map(|(i, e)| if query.is_empty() { Some((0, i)) } else { fuzzy(&query, &format!("{} {}", e.key, e.name)).map(|s| (s, i)) }).collect();
+        shown.sort();
+        let watch_height = if pins.is_empty() { 0 } else { pins.len().min(WATCH_ROWS) + 1 };
+        let list_height = rows.saturating_sub(3 + watch_height).max(1);
+        selected = selected.min(shown.len().saturating_sub(1));
+        if selected < top { top = selected; }
+        if selected >= top + list_height { top = selected + 1 - list_height; }
+
+        if last_read.is_none_or(|t| t.elapsed() >= REFRESH) {
+            last_read = Some(Instant::now());
+            for &(_, i) in shown.iter().skip(top).take(list_height) {
+                let e = &mut entries[i];
+                match smcraw::guarded(&e.key, || raw.read(&e.key)) {
+                    Ok((info, bytes)) => { e.value = Some(SmcValue::from_info(&info, &bytes)); e.info = Some(info); }
+                    Err(_) => e.value = None,
+                }
+            }
+            for p in &mut pins {
+                p.value = crate::value::read(&p.key).ok();
+                if let Some(v) = p.value.as_ref().and_then(|v| v.as_f64()) {
+                    p.range = Some(p.range.map_or((v, v), |(lo, hi)| (lo.min(v), hi.max(v))));
+                }
+            }
+        }
+
+        let mut screen = String::from("\x1b[H");
+        let mut line = |row: usize, text: &str, highlight: bool| {
+            screen.push_str(&format!("\x1b[{};1H{}{}\x1b[K{}", row, if highlight { "\x1b[7m" } else { "" }, clip(text, cols), if highlight { "\x1b[0m" } else { "" }));
+        };
+        line(1, &format!("kim_temp explore  {}  {} of {} keys", model, shown.len(), entries.len()), false);
+        line(2, &if searching || !query.is_empty() { format!("/{}{}", query, if searching { "_" } else { "" }) } else { String::new() }, false);
+        for row in 0..list_height {
+            let text = match shown.get(top + row) {
+                Some(&(_, i)) => {
+                    let e = &entries[i];
+                    let role = key_roles.iter().find(|(k, _)| *k == e.key).map(|(_, r)| format!("  [{}]", r.as_str())).unwrap_or_default();
+                    let pinned = if pins.iter().any(|p| p.key == e.key) { "*" } else { " " };
+                    let value = e.value.as_ref().map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+                    format!("{}{}  {:<4}  {:<20}  {}{}", pinned, e.key, e.info.as_ref().map_or("", |i| i.data_type.as_str()), clip(&value, 20), e.name, role)
+                }
+                None => String::new(),
+            };
+            line(3 + row, &text, top + row == selected && !shown.is_empty());
+        }
+        if watch_height > 0 {
+            let base = 3 + list_height;
+            line(base, &format!("── watch ({}) ──", pins.len()), false);
+            for (n, p) in pins.iter().take(WATCH_ROWS).enumerate() {
+                let value = p.value.as_ref().map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+                let range = p.range.map(|(lo, hi)| format!("  min {} max {}", lo, hi)).unwrap_or_default();
+                line(base + 1 + n, &format!(" {}  {:<20}{}  {}", p.key, clip(&value, 20), range, crate::labels::label(&p.key).unwrap_or("")), false);
+            }
+        }
+        line(rows, &status, false);
+        let _ = out.write_all(screen.as_bytes());
+        let _ = out.flush();
+
+        let Some(input) = read_input(REFRESH.saturating_sub(last_read.map_or(Duration::ZERO, |t| t.elapsed()))) else { continue };
+        let current = shown.get(selected).map(|&(_, i)| entries[i].key.clone());
+        match (searching, input) {
+            (true, Input::Enter) | (true, Input::Escape) => searching = false,
+            (true, Input::Backspace) => { query.pop(); }
+            (true, Input::Char(c)) => { query.push(c); selected = 0; }
+            (_, Input::Up) => selected = selected.saturating_sub(1),
+            (_, Input::Down) => selected += 1,
+            (_, Input::PageUp) => selected = selected.saturating_sub(list_height),
+            (_, Input::PageDown) => selected += list_height,
+            (false, Input::Escape) => { query.clear(); selected = 0; }
+            (false, Input::Char('q')) => return Ok(()),
+            (false, Input::Char('/')) => searching = true,
+            (false, Input::Char('j')) => selected += 1,
+            (false, Input::Char('k')) => selected = selected.saturating_sub(1),
+            (false, Input::Char('p')) => if let Some(key) = current {
+                match pins.iter().position(|p| p.key == key) {
+                    Some(i) => { pins.remove(i); }
+                    None => pins.push(Pin { value: crate::value::read(&key).ok(), key, range: None }),
+                }
+                last_read = None;
+            },
+            (false, Input::Char('r')) => if let Some(key) = current {
+                let next = Role::next(key_roles.iter().find(|(k, _)| *k == key).map(|(_, r)| *r));
+                // One key per rail
+                let rail = next.filter(|r| !matches!(r, Role::Temp(_) | Role::Ignore));
+                key_roles.retain(|(k, r)| *k != key && Some(*r) != rail);
+                if let Some(role) = next { key_roles.push((key.clone(), role)); }
+                status = match roles::save(model, &key_roles) {
+                    Ok(path) => format!("{}: {} (saved to {})", key, next.map_or("no role", |r| r.as_str()), path.display()),
+                    Err(e) => format!("{}: {}", key, e),
+                };
+            },
+            _ => {}
+        }
+    }
+}
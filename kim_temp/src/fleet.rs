@@ -0,0 +1,108 @@
+// Fleet profiles: an admin publishes corrected key mappings once and every machine picks them up,
+// without a rebuild or a hand-edited config on each. `[profile_sync] source` is a directory (a
+// mounted share) or an HTTPS URL holding SHA256SUMS and any of:
+//   roles               MODEL<TAB>KEY<TAB>role, as `explore` writes it (see roles.rs)
+//   power-calibration   MODEL<TAB>scale<TAB>offset<TAB>..., as `calibrate` writes it (see powercal.rs)
+//   keys                KEY<TAB>name, SMC key names over the built-in database (see keydb.rs)
+// SHA256SUMS is `shasum -a 256` output for those files. Every file it lists must match, and it
+// must itself match `manifest_sha256` when the config pins it, or nothing is replaced. Verified
+// files live in ~/.config/kim_temp/fleet/ and are read under the machine's own files, which win
+// for the same model or key. Syncs run every `refresh_hours` (default 24) on startup, or now
+// with `kim_temp sync-profiles`.
+
+use crate::config::ProfileSync;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+pub const FILES: &[&str] = &["roles", "power-calibration", "keys"];
+const MANIFEST: &str = "SHA256SUMS";
+// Touched on every attempt, so an unreachable source is retried once per refresh, not every run
+const ATTEMPT: &str = ".last-attempt";
+
+pub fn dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config/kim_temp/fleet"))
+}
+
+// The synced copy of `name`, when there is one.
+pub fn file(name: &str) -> Option<PathBuf> {
+    dir().map(|d| d.join(name)).filter(|p| p.is_file())
+}
+
+fn fetch(source: &str, name: &str, to: &Path) -> Result<(), String> {
+    if source.starts_with("http://") { return Err(format!("{}: only https:// sources are accepted", source)); }
+    if source.starts_with("https://") {
+        let url = format!("{}/{}", source.trim_end_matches('/'), name);
+        let out = Command::new("curl").args(["-fsS", "--max-time", "20", "-o"]).arg(to).arg(&url).output().map_err(|e| format!("cannot run curl: {}", e))?;
+        return if out.status.success() { Ok(()) } else { Err(format!("{}: {}", url, String::from_utf8_lossy(&out.stderr).trim())) };
+    }
+    let from = crate::config::expand_home(source).join(name);
+    std::fs::copy(&from, to).map(|_| ()).map_err(|e| format!("{}: {}", from.display(), e))
+}
+
+fn sha256(path: &Path) -> Result<String, String> {
+    let out = Command::new("shasum").args(["-a", "256"]).arg(path).output().map_err(|e| format!("cannot run shasum: {}", e))?;
+    String::from_utf8_lossy(&out.stdout).split_whitespace().next().filter(|_| out.status.success()).map(str::to_string)
+        .ok_or_else(|| format!("shasum {} failed", path.display()))
+}
+
+// (file, sha256) for the known files SHA256SUMS lists; other names are ignored.
+fn parse_manifest(text: &str) -> Vec<(&'static str, String)> {
+    text.lines().filter_map(|l| {
+        let (hash, name) = l.trim().split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        FILES.iter().find(|f| **f == name).map(|f| (*f, hash.to_ascii_lowercase()))
+    }).collect()
+}
+
+// Fetches and verifies everything, then replaces the synced files. Returns one line per file.
+pub fn sync(cfg: &ProfileSync) -> Result<Vec<String>, String> {
+    let source = cfg.source.as_deref().ok_or("no [profile_sync] source in the config (or --source)")?;
+    let dir = dir().ok_or("HOME is not set")?;
+    let staging = dir.join(".incoming");
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging).map_err(|e| format!("cannot create {}: {}", staging.display(), e))?;
+    let result = (|| {
+        fetch(source, MANIFEST, &staging.join(MANIFEST))?;
+        if let Some(pin) = &cfg.manifest_sha256 {
+            let got = sha256(&staging.join(MANIFEST))?;
+            if !got.eq_ignore_ascii_case(pin) { return Err(format!("{} has sha256 {}, the config pins {}", MANIFEST, got, pin)); }
+        }
+        let manifest = std::fs::read_to_string(staging.join(MANIFEST)).map_err(|e| e.to_string())?;
+        let listed = parse_manifest(&manifest);
+        if listed.is_empty() { return Err(format!("{} lists none of {}", MANIFEST, FILES.join(", "))); }
+        for (name, hash) in &listed {
+            fetch(source, name, &staging.join(name))?;
+            let got = sha256(&staging.join(name))?;
+            if got != *hash { return Err(format!("{}: sha256 {} does not match {} ({})", name, got, MANIFEST, hash)); }
+        }
+        let mut report = Vec::new();
+        for name in FILES {
+            let (from, to) = (staging.join(name), dir.join(name));
+            if listed.iter().any(|(n, _)| n == name) {
+                let changed = std::fs::read(&from).ok() != std::fs::read(&to).ok();
+                std::fs::rename(&from, &to).map_err(|e| format!("cannot replace {}: {}", to.display(), e))?;
+                report.push(format!("{}: {}", name, if changed { "updated" } else { "unchanged" }));
+            } else if to.exists() {
+                // No longer published: the machine falls back to its own files and the built-ins
+                std::fs::remove_file(&to).map_err(|e| format!("cannot remove {}: {}", to.display(), e))?;
+                report.push(format!("{}: removed", name));
+            }
+        }
+        Ok(report)
+    })();
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+// Syncs when `refresh_hours` have passed since the last attempt; failures keep the files that are
+// there and are reported on stderr.
+pub fn auto(cfg: &ProfileSync) {
+    if cfg.source.is_none() { return; }
+    let Some(dir) = dir() else { return };
+    let refresh = Duration::from_secs_f64(cfg.refresh_hours.unwrap_or(24.0).max(0.0) * 3600.0);
+    let last = std::fs::metadata(dir.join(ATTEMPT)).and_then(|m| m.modified()).ok().and_then(|t| t.elapsed().ok());
+    if last.is_some_and(|age| age < refresh) { return; }
+    if std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(dir.join(ATTEMPT), "")).is_err() { return; }
+    if let Err(e) = sync(cfg) { crate::diag::warn!("profile sync failed, keeping the profiles already synced: {}", e); }
+}
@@ -0,0 +1,129 @@
+// `serve --grpc ADDR`: the `kim_temp.v1.KimTemp` service in proto/kim_temp.proto, so fleet
+// tooling can use generated Go/Python clients instead of parsing JSON lines.
+//   GetSnapshot      what `--http`'s /snapshot answers (fields, units and detail as it takes
+//                    them), from the server's shared cache; numbers typed, the whole object in `json`
+//   StreamSnapshots  the same every TTL (or interval_ms, when longer) until the client cancels; a
+//                    sample that fails ends the stream with its status
+//   ReadKey          one SMC key read now, uncached, decoded by its declared type
+// Errors are the HTTP ones as gRPC codes: INVALID_ARGUMENT, NOT_FOUND (no such key, no battery),
+// UNAVAILABLE (no sample, no SMC). tonic needs an async runtime, so the service runs on a tokio
+// runtime of its own thread, and the sampling, which blocks, on tokio's blocking pool. There is no
+// authentication: keep ADDR on localhost, or reach it through a tunnel.
+
+use crate::http::Params;
+use crate::value::SmcValue;
+use proto::kim_temp_server::{KimTemp, KimTempServer};
+use proto::{key_value, KeyValue, ReadKeyRequest, Snapshot, SnapshotRequest, StreamRequest};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+// Generated by build.rs
+mod proto {
+    tonic::include_proto!("kim_temp.v1");
+}
+
+// `--http`'s /snapshot: URL parameters to the JSON text, or an HTTP status and why
+type Answer = dyn Fn(Params) -> Result<String, (&'static str, String)> + Send + Sync;
+
+struct Service {
+    answer: Arc<Answer>,
+    ttl: Duration,
+}
+
+// Binds ADDR and serves from a thread of its own. Fails only when the address can't be bound.
+pub fn spawn<F>(addr: SocketAddr, ttl: Duration, answer: F) -> Result<(), String>
+where F: Fn(Params) -> Result<String, (&'static str, String)> + Send + Sync + 'static {
+    let listener = std::net::TcpListener::bind(addr).and_then(|l| l.set_nonblocking(true).map(|_| l)).map_err(|e| format!("cannot listen on {}: {}", addr, e))?;
+    let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(2).enable_all().build().map_err(|e| format!("cannot start gRPC: {}", e))?;
+    let incoming = { let _in = runtime.enter(); tokio::net::TcpListener::from_std(listener) }.map_err(|e| format!("cannot listen on {}: {}", addr, e))?;
+    let service = KimTempServer::new(Service { answer: Arc::new(answer), ttl });
+    std::thread::spawn(move || runtime.block_on(async move {
+        if let Err(e) = tonic::transport::Server::builder().add_service(service).serve_with_incoming(TcpListenerStream::new(incoming)).await {
+            crate::diag::warn!("serve --grpc stopped: {}", e);
+        }
+    }));
+    eprintln!("serve: gRPC on {} (kim_temp.v1.KimTemp)", addr);
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl KimTemp for Service {
+    async fn get_snapshot(&self, request: Request<SnapshotRequest>) -> Result<Response<Snapshot>, Status> {
+        snapshot(&self.answer, params(request.into_inner())).await.map(Response::new)
+    }
+
+    type StreamSnapshotsStream = ReceiverStream<Result<Snapshot, Status>>;
+
+    async fn stream_snapshots(&self, request: Request<StreamRequest>) -> Result<Response<Self::StreamSnapshotsStream>, Status> {
+        let request = request.into_inner();
+        let (params, answer) = (params(request.request.unwrap_or_default()), Arc::clone(&self.answer));
+        // The first sample answers the call, so a bad request fails it instead of an empty stream
+        let mut next = Ok(snapshot(&answer, params.clone()).await?);
+        let mut tick = tokio::time::interval(self.ttl.max(Duration::from_millis(request.interval_ms.into())));
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            tick.tick().await;
+            // Until the client cancels (the channel closes) or a sample fails
+            loop {
+                let failed = next.is_err();
+                if tx.send(next).await.is_err() || failed { return; }
+                tick.tick().await;
+                next = snapshot(&answer, params.clone()).await;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn read_key(&self, request: Request<ReadKeyRequest>) -> Result<Response<KeyValue>, Status> {
+        let key = request.into_inner().key;
+        let (key, (info, bytes)) = tokio::task::spawn_blocking(move || crate::serve::read_key(&key).map(|read| (key, read))).await.map_err(|e| Status::internal(e.to_string()))?.map_err(status)?;
+        let value = match SmcValue::from_info(&info, &bytes) {
+            SmcValue::Float(v) => Some(key_value::Value::Number(v)),
+            SmcValue::Int(v) => Some(key_value::Value::Integer(v)),
+            SmcValue::Flag(v) => Some(key_value::Value::Flag(v)),
+            SmcValue::Text(s) => Some(key_value::Value::Text(s)),
+            SmcValue::Hex(_) | SmcValue::Bytes(_) => None,
+        };
+        Ok(Response::new(KeyValue { key, r#type: info.data_type.trim().to_string(), size: info.size, value, bytes }))
+    }
+}
+
+// A request as /snapshot's URL parameters: `fields=cpu_temp,power_w&units=fahrenheit&detail`
+fn params(request: SnapshotRequest) -> Params {
+    let mut params = Vec::new();
+    if !request.fields.is_empty() { params.push(("fields".to_string(), Some(request.fields.join(",")))); }
+    if !request.units.is_empty() { params.push(("units".to_string(), Some(request.units))); }
+    if request.detail { params.push(("detail".to_string(), None)); }
+    params
+}
+
+async fn snapshot(answer: &Arc<Answer>, params: Params) -> Result<Snapshot, Status> {
+    let answer = Arc::clone(answer);
+    let json = tokio::task::spawn_blocking(move || answer(params)).await.map_err(|e| Status::internal(e.to_string()))?.map_err(status)?;
+    let s = kim_temp::snapshot::Snapshot::from_json(&json).map_err(Status::internal)?;
+    Ok(Snapshot {
+        cpu_temp: s.cpu_temp, cpu_p_temp: s.cpu_p_temp, cpu_e_temp: s.cpu_e_temp, gpu_temp: s.gpu_temp, gpu_temp_max: s.gpu_temp_max,
+        mem_temp: s.mem_temp, ssd_temp: s.ssd_temp, bat_temp: s.bat_temp,
+        power_w: s.power_w, power_raw_w: s.power_raw_w, bat_power_w: s.bat_power_w, mem_power_w: s.mem_power_w,
+        cpu_mw: s.cpu_mw, gpu_mw: s.gpu_mw, ane_mw: s.ane_mw,
+        battery_pct: s.battery_pct, charging: s.charging, mem_free_pct: s.mem_free_pct, efficiency_hrs: s.efficiency_hrs, screen_w: s.screen_w,
+        wakeups_per_sec: s.wakeups_per_sec, cpu_ms_per_j: s.cpu_ms_per_j, wakeups_per_w: s.wakeups_per_w, thermal_pressure: s.thermal_pressure,
+        drain_pct_per_hr: s.drain_pct_per_hr, est_hours_remaining: s.est_hours_remaining,
+        charge_input_w: s.charge_input_w, charge_overhead_w: s.charge_overhead_w, adapter_w: s.adapter_w, dominant: s.dominant.unwrap_or_default(),
+        cache_age_ms: s.cache_age_ms, temp_unit: s.temp_unit, json: json.trim_end().to_string(),
+    })
+}
+
+// The HTTP statuses `rest` and `read_key` answer with, as gRPC codes
+fn status((http, message): (&str, String)) -> Status {
+    match http.split(' ').next() {
+        Some("400") => Status::invalid_argument(message),
+        Some("404") => Status::not_found(message),
+        _ => Status::unavailable(message),
+    }
+}
@@ -0,0 +1,48 @@
+// Battery-health history: `stream` records capacity and cycle count at the moment each charge
+// reaches 100% (when the gauge has just recalibrated against a full cell, so the reading is at
+// its most accurate), one JSON object per line, appended across runs. `battery-health` shows
+// the recent entries. Default `~/Library/Logs/kim_temp/battery-health.jsonl`.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// How many entries `battery-health` shows
+const SHOWN: usize = 10;
+
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Logs/kim_temp/battery-health.jsonl"))
+}
+
+// One line for this full charge; a write error is reported and the entry lost, the stream goes on.
+pub fn append(path: &Path, ts: &str, health: &crate::battery::Health) {
+    if path.as_os_str().is_empty() { return; }
+    if let Some(dir) = path.parent() { let _ = std::fs::create_dir_all(dir); }
+    let line = format!("{{\"ts\":\"{}\",\"trigger\":\"full_charge\",{}\n", ts, health.to_json().strip_prefix('{').unwrap_or_default());
+    if let Err(e) = std::fs::OpenOptions::new().create(true).append(true).open(path).and_then(|mut f| f.write_all(line.as_bytes())) {
+        crate::diag::warn!("cannot write battery-health history {}: {}", path.display(), e);
+    }
+}
+
+// The last entries, oldest first; none when there is no history yet.
+pub fn recent(path: &Path) -> Vec<String> {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    let lines: Vec<&str> = text.lines().filter(|l| l.starts_with('{')).collect();
+    lines[lines.len().saturating_sub(SHOWN)..].iter().map(|l| l.to_string()).collect()
+}
+
+// A top-level string or number field of an entry we wrote, without a JSON parser (not in every build).
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = &line[line.find(&format!("\"{}\":", name))? + name.len() + 3..];
+    let value = &rest[..rest.find([',', '}']).unwrap_or(rest.len())];
+    Some(value.trim_matches('"')).filter(|v| *v != "null")
+}
+
+// `battery-health`'s history table.
+pub fn print(entries: &[String]) {
+    if entries.is_empty() { println!("History:    none yet (recorded by `stream` at each full charge)"); return; }
+    println!("History:    capacity at full charge, last {}", entries.len());
+    for e in entries {
+        let f = |name| field(e, name).unwrap_or("?");
+        println!("  {:<26} {:>6} mAh  {:>6}%  {:>5} cycles", f("ts"), f("max_mah"), f("health_pct"), f("cycle_count"));
+    }
+}
@@ -0,0 +1,145 @@
+// The little HTTP/1.1 that `serve`'s network listeners speak (`--http`, `--ws`): one request per
+// connection, its head read under a timeout, and one reply with `Connection: close`. Enough for
+// dashboards, curl and a WebSocket upgrade; no keep-alive, chunking or request bodies.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+// Header lines read before giving up on a request
+const MAX_HEADERS: usize = 100;
+
+// A query's parameters, decoded: `a=1&b` is ("a", Some("1")), ("b", None)
+pub type Params = Vec<(String, Option<String>)>;
+
+pub struct Request {
+    pub method: String,
+    // Path and query, e.g. `/temps?units=fahrenheit`
+    pub resource: String,
+    headers: Vec<(String, String)>,
+    // Bytes the client sent after the head (a WebSocket client's first frames)
+    pub rest: Vec<u8>,
+}
+
+impl Request {
+    // None when the connection closes, times out or doesn't send an HTTP request head.
+    pub fn read(conn: &TcpStream) -> Option<Request> {
+        let _ = conn.set_read_timeout(Some(TIMEOUT));
+        let mut reader = BufReader::new(conn);
+        let (mut first, mut line, mut headers) = (String::new(), String::new(), Vec::new());
+        reader.read_line(&mut first).ok()?;
+        let mut words = first.split_whitespace();
+        let (method, resource) = (words.next()?.to_string(), words.next()?.to_string());
+        for _ in 0..MAX_HEADERS {
+            line.clear();
+            if reader.read_line(&mut line).ok()? == 0 { return None; }
+            let Some((name, value)) = line.split_once(':') else { break };
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+        Some(Request { method, resource, headers, rest: reader.buffer().to_vec() })
+    }
+
+    // `name` in lower case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn path(&self) -> &str {
+        self.resource.split_once('?').map_or(&self.resource, |(p, _)| p)
+    }
+
+    pub fn params(&self) -> Params {
+        let query = self.resource.split_once('?').map_or("", |(_, q)| q);
+        query.split('&').filter(|p| !p.is_empty()).map(|p| match p.split_once('=') {
+            Some((n, v)) => (decode(n), Some(decode(v))),
+            None => (decode(p), None),
+        }).collect()
+    }
+}
+
+// A whole reply: `status` like "404 Not Found", `headers` each ending in \r\n.
+pub fn reply(conn: &mut TcpStream, status: &str, headers: &str, content_type: &str, body: &str) {
+    let _ = conn.set_write_timeout(Some(TIMEOUT));
+    let _ = write!(conn, "HTTP/1.1 {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}", status, headers, content_type, body.len(), body);
+}
+
+// `:8080` is localhost only; `0.0.0.0:8080` (or another address) to be reached from elsewhere.
+pub fn parse_addr(flag: &str, s: &str) -> Result<SocketAddr, String> {
+    let full = if s.starts_with(':') { format!("127.0.0.1{}", s) } else { s.to_string() };
+    full.parse().map_err(|_| format!("{} takes [ADDRESS]:PORT, e.g. :8080, got {:?}", flag, s))
+}
+
+// Binds `addr` and hands each connection to `handle` on a thread of its own. Fails only when the
+// address can't be bound.
+pub fn listen<F>(addr: SocketAddr, handle: F) -> Result<(), String>
+where F: Fn(TcpStream) + Send + Sync + 'static {
+    let listener = TcpListener::bind(addr).map_err(|e| format!("cannot listen on {}: {}", addr, e))?;
+    let handle = Arc::new(handle);
+    std::thread::spawn(move || for conn in listener.incoming() {
+        let Ok(conn) = conn else { continue };
+        let handle = Arc::clone(&handle);
+        std::thread::spawn(move || handle(conn));
+    });
+    Ok(())
+}
+
+// Checks the bearer token a client sent against `token` (a None `token` lets everyone in), as an
+// `Authorization: Bearer` header or as `?access_token=` for browsers' EventSource and WebSocket,
+// which can't send headers. The parameter is taken out of `params` either way.
+pub fn bearer(request: &Request, params: &mut Params, token: Option<&str>) -> bool {
+    let mut given = request.header("authorization").and_then(|a| a.strip_prefix("Bearer ")).map(|t| t.trim().to_string());
+    if let Some(i) = params.iter().position(|(n, _)| n == "access_token") { given = given.or(params.remove(i).1); }
+    // Compared in constant time, so the token can't be guessed byte by byte from timings
+    let same = |a: &str, b: &str| a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |d, (x, y)| d | (x ^ y)) == 0;
+    token.is_none_or(|t| given.is_some_and(|g| same(&g, t)))
+}
+
+// Whether a browser page from `origin` may use the answer: local pages (any scheme and port on
+// localhost, *.localhost, 127.0.0.1 or [::1]), local files (`file://`), and the `extra` list ("*"
+// for any). Not `null`: sandboxed iframes and `data:` pages on any site send it, so it has to be
+// listed to be let in.
+pub fn allowed_origin(origin: &str, extra: &[String]) -> bool {
+    if extra.iter().any(|o| o == "*" || o.eq_ignore_ascii_case(origin)) || origin.starts_with("file://") { return true; }
+    let host = strip_port(origin.split_once("://").map_or(origin, |(_, rest)| rest));
+    host.eq_ignore_ascii_case("localhost") || host.to_ascii_lowercase().ends_with(".localhost") || host == "127.0.0.1" || host == "[::1]"
+}
+
+// Whether a request's Host header names this server, so a DNS-rebinding page (its own domain
+// pointed at 127.0.0.1, a local origin to the checks above) is refused: localhost, *.localhost,
+// the loopback or bound address (any address when bound to 0.0.0.0 or [::]), or this machine's
+// name with or without `.local`. Requests without a Host (scripts, nc) aren't from a browser.
+pub fn allowed_host(host: Option<&str>, bound: SocketAddr) -> bool {
+    static OWN: OnceLock<String> = OnceLock::new();
+    let Some(host) = host else { return true };
+    let name = strip_port(host).trim_end_matches('.').to_ascii_lowercase();
+    if let Ok(ip) = name.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return ip.is_loopback() || ip == bound.ip() || bound.ip().is_unspecified();
+    }
+    let own = OWN.get_or_init(|| crate::profile::sysctl("kern.hostname").unwrap_or_default().to_ascii_lowercase());
+    let bare = |n: &str| n.strip_suffix(".local").unwrap_or(n).to_string();
+    name == "localhost" || name.ends_with(".localhost") || (!own.is_empty() && bare(&name) == bare(own))
+}
+
+// `host:port` or `[v6]:port` without the port
+fn strip_port(s: &str) -> &str {
+    match s.find(']') { Some(end) if s.starts_with('[') => &s[..=end], _ => s.split(':').next().unwrap_or(s) }
+}
+
+// `%2C` and `+` in a URL's query, as browsers may send them
+pub fn decode(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) { Some(v) => out.push(v), None => { out.push(b'%'); out.extend(hex); } }
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,85 @@
+// Undo journal for SMC writes. Before a key is written for the first time, its current bytes
+// are appended to a journal file; `restore-hw-state` writes them back and drops the entries it
+// restored. Only the first original of each key is kept, so repeated experiments still roll
+// back to the state before the first one, and the file survives crashes and reboots.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+// Writes need root, so the journal lives in a root-owned system location ($KIM_TEMP_JOURNAL overrides).
+pub fn path() -> PathBuf {
+    std::env::var_os("KIM_TEMP_JOURNAL").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("/var/db/kim_temp/hw-journal"))
+}
+
+pub struct Entry {
+    pub key: String,
+    pub bytes: Vec<u8>,
+    pub when: String,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) { return None; }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// `KEY<TAB>hex<TAB>rfc3339` per line; malformed lines are skipped.
+pub fn entries() -> Vec<Entry> {
+    std::fs::read_to_string(path()).unwrap_or_default().lines().filter_map(|l| {
+        let mut f = l.split('\t');
+        Some(Entry { key: f.next()?.to_string(), bytes: unhex(f.next()?)?, when: f.next().unwrap_or("").to_string() })
+    }).collect()
+}
+
+// Records `key`'s original bytes unless it is already journaled. An error here aborts the write.
+pub fn record(key: &str, original: &[u8]) -> Result<(), String> {
+    if entries().iter().any(|e| e.key == key) { return Ok(()); }
+    let path = path();
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?; }
+    let line = format!("{}\t{}\t{}\n", key, hex(original), crate::clock::local(std::time::SystemTime::now()).rfc3339());
+    std::fs::OpenOptions::new().create(true).append(true).open(&path)
+        .and_then(|mut f| { f.write_all(line.as_bytes())?; f.sync_all() })
+        .map_err(|e| format!("cannot write undo journal {}: {}", path.display(), e))
+}
+
+// Rewrites the journal with only the entries that are still pending (removing it when empty).
+fn keep(pending: &[Entry]) -> Result<(), String> {
+    let path = path();
+    if pending.is_empty() { return std::fs::remove_file(&path).or_else(|e| if e.kind() == std::io::ErrorKind::NotFound { Ok(()) } else { Err(e) }).map_err(|e| e.to_string()); }
+    let text: String = pending.iter().map(|e| format!("{}\t{}\t{}\n", e.key, hex(&e.bytes), e.when)).collect();
+    std::fs::write(&path, text).map_err(|e| format!("cannot update {}: {}", path.display(), e))
+}
+
+// `restore-hw-state`: writes every journaled value back, read-back verified. Returns one line per key.
+pub fn restore(list_only: bool) -> Result<Vec<String>, String> {
+    let entries = entries();
+    if entries.is_empty() { return Ok(vec![format!("nothing to restore ({} is empty)", path().display())]); }
+    if list_only {
+        return Ok(entries.iter().map(|e| format!("{} = {} (saved {})", e.key, hex(&e.bytes), e.when)).collect());
+    }
+    let smc = crate::smcraw::Raw::open()?;
+    let mut report = Vec::new();
+    let mut pending = Vec::new();
+    for e in entries {
+        let result = smc.write_unjournaled(&e.key, &e.bytes).and_then(|_| smc.read(&e.key)).and_then(|(_, now)| {
+            if now == e.bytes { Ok(()) } else { Err(format!("read back {} instead", hex(&now))) }
+        });
+        match result {
+            Ok(()) => report.push(format!("{} restored to {}", e.key, hex(&e.bytes))),
+            Err(err) => { report.push(format!("{} NOT restored: {}", e.key, err)); pending.push(e); }
+        }
+    }
+    keep(&pending)?;
+    Ok(report)
+}
+
+// Asks before a hardware write when a person is at the terminal; `--yes` (or no TTY) skips it.
+pub fn confirm(action: &str, yes: bool) -> bool {
+    if yes || unsafe { libc::isatty(0) } == 0 { return true; }
+    eprint!("{}? [y/N] ", action);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
@@ -0,0 +1,83 @@
+// Human-readable names for SMC keys.
+// Exact matches come first; anything else falls back to the family implied by the key prefix.
+// `KEY<TAB>name` lines in ~/.config/kim_temp/keys, then in a fleet `keys` file (see fleet.rs),
+// name keys ahead of both, so a wrong or missing name is fixed without a rebuild.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+static OVERRIDES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+fn overrides() -> &'static HashMap<String, String> {
+    OVERRIDES.get_or_init(|| {
+        let own = std::env::var_os("HOME").map(|h| std::path::PathBuf::from(h).join(".config/kim_temp/keys"));
+        let mut table = HashMap::new();
+        // Fleet first, so the machine's own file overwrites it
+        for path in [crate::fleet::file("keys"), own].into_iter().flatten() {
+            let Ok(text) = std::fs::read_to_string(path) else { continue };
+            for line in text.lines().filter(|l| !l.trim_start().starts_with('#')) {
+                let Some((key, name)) = line.split_once('\t') else { continue };
+                if key.len() == 4 && !name.trim().is_empty() { table.insert(key.to_string(), name.trim().to_string()); }
+            }
+        }
+        table
+    })
+}
+
+const NAMES: &[(&str, &str)] = &[
+    // CPU (M1/M2 die sensors)
+    ("Tp01", "P-core cluster 1"), ("Tp05", "P-core cluster 2"), ("Tp0D", "P-core cluster 3"), ("Tp0H", "P-core cluster 4"),
+    ("Tp0L", "P-core cluster 5"), ("Tp0P", "P-core cluster 6"), ("Tp0X", "P-core cluster 7"), ("Tp0b", "P-core cluster 8"),
+    ("Tp1h", "E-core cluster 1"), ("Tp1t", "E-core cluster 2"), ("Tp1p", "E-core cluster 3"), ("Tp1l", "E-core cluster 4"),
+    ("Te05", "E-core cluster 1"), ("Te0L", "E-core cluster 2"), ("Te0P", "E-core cluster 3"), ("Te0S", "E-core cluster 4"),
+    // GPU
+    ("Tg05", "GPU cluster 1"), ("Tg0D", "GPU cluster 2"), ("Tg0L", "GPU cluster 3"), ("Tg0T", "GPU cluster 4"),
+    ("Tg0f", "GPU cluster 1"), ("Tg0j", "GPU cluster 2"),
+    // Storage
+    ("TSCD", "NVMe die"), ("TH0a", "NAND flash A"), ("TH0b", "NAND flash B"), ("TH0x", "NAND flash"),
+    // Battery
+    ("TB0T", "Battery max"), ("TB1T", "Battery cell 1"), ("TB2T", "Battery cell 2"), ("TB3T", "Battery cell 3"),
+    // Chassis / misc
+    ("TW0P", "Wi-Fi module proximity"), ("Ts0P", "Palm rest left"), ("Ts1P", "Palm rest right"),
+    ("TaLP", "Airflow left"), ("TaRF", "Airflow right"), ("TCHP", "Charger proximity"),
+    // Power rails
+    ("PSTR", "Total system"), ("PPBR", "Battery rail"), ("PHPS", "Package"), ("PHPM", "Memory (system)"),
+    ("PP0b", "CPU package"), ("PP7b", "GPU"), ("PP2b", "GPU"), ("PDTR", "Display"), ("PBLR", "Backlight"),
+    ("PZD1", "CPU package"), ("PC0C", "CPU cores"), ("PCPC", "CPU package (alt)"), ("PG0C", "GPU cores"), ("PGTR", "GPU rail"),
+    // Voltage rails
+    ("VD0R", "DC in"), ("VP0R", "Main rail"), ("VBAT", "Battery"), ("VC0C", "CPU core"), ("VG0C", "GPU core"), ("VM0R", "Memory"),
+    // Current rails
+    ("ID0R", "DC in"), ("IP0R", "Main rail"), ("IBAT", "Battery"), ("IC0C", "CPU core"), ("IG0C", "GPU core"), ("IM0R", "Memory"),
+];
+
+const FAMILIES: &[(&str, &str)] = &[
+    ("Tp", "CPU die"), ("Te", "CPU E-cluster"), ("Tc", "CPU core"), ("TC", "CPU core"),
+    ("Tg", "GPU die"), ("TG", "GPU die"),
+    ("TM", "Memory"), ("Tm", "Memory"),
+    ("TS", "SSD"), ("TH", "NAND flash"),
+    ("TB", "Battery"),
+    ("Ta", "Airflow"), ("Ts", "Chassis surface"), ("TW", "Wi-Fi"),
+    ("PP", "Power rail"), ("PH", "Power rail"),
+    ("VD", "DC in voltage"), ("VP", "Rail voltage"), ("VB", "Battery voltage"), ("VC", "CPU voltage"), ("VG", "GPU voltage"), ("VM", "Memory voltage"),
+    ("ID", "DC in current"), ("IP", "Rail current"), ("IB", "Battery current"), ("IC", "CPU current"), ("IG", "GPU current"), ("IM", "Memory current"),
+];
+
+// Keys with a name of their own (not just a family).
+pub fn exact_keys() -> impl Iterator<Item = &'static str> {
+    NAMES.iter().map(|(k, _)| *k).chain(overrides().keys().map(String::as_str).filter(|k| !NAMES.iter().any(|(n, _)| n == k)))
+}
+
+// Every English name, exact ones first (repeats included), for translation files.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    NAMES.iter().chain(FAMILIES).map(|(_, n)| *n)
+}
+
+pub fn describe(key: &str) -> Option<&'static str> {
+    if let Some(name) = overrides().get(key) {
+        return Some(name);
+    }
+    if let Some((_, name)) = NAMES.iter().find(|(k, _)| *k == key) {
+        return Some(name);
+    }
+    FAMILIES.iter().find(|(p, _)| key.starts_with(p)).map(|(_, name)| *name)
+}
@@ -0,0 +1,114 @@
+// `keys`: every sensor key grouped by what it measures, each with its decoded value, unit and
+// name. Temperatures go through the same type and range checks as the averages (temps::celsius),
+// so a key that isn't really a temperature shows its raw value without a unit. Keys that are
+// not sensors (flags, settings, counters) only show up when `--filter` asks for their prefix.
+
+use crate::config::Units;
+use crate::profile::{Category, Profile};
+use crate::value::SmcValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Group { Cpu, Gpu, Memory, Ssd, Battery, OtherTemp, Power, Fans, Voltage, Current, Other }
+
+impl Group {
+    fn title(self) -> &'static str {
+        match self {
+            Group::Cpu => "CPU",
+            Group::Gpu => "GPU",
+            Group::Memory => "Memory",
+            Group::Ssd => "SSD",
+            Group::Battery => "Battery",
+            Group::OtherTemp => "Other temperatures",
+            Group::Power => "Power",
+            Group::Fans => "Fans",
+            Group::Voltage => "Voltage",
+            Group::Current => "Current",
+            Group::Other => "Other",
+        }
+    }
+
+    fn of(key: &str, profile: &Profile) -> Group {
+        match key.as_bytes().first() {
+            Some(b'T') => match profile.classify(key) {
+                Some(Category::CpuP | Category::CpuE) => Group::Cpu,
+                Some(Category::Gpu) => Group::Gpu,
+                Some(Category::Mem) => Group::Memory,
+                Some(Category::Ssd) => Group::Ssd,
+                Some(Category::Battery) => Group::Battery,
+                None => Group::OtherTemp,
+            },
+            Some(b'P') => Group::Power,
+            Some(b'F') => Group::Fans,
+            Some(b'V') => Group::Voltage,
+            Some(b'I') => Group::Current,
+            _ => Group::Other,
+        }
+    }
+}
+
+pub enum Sort { Key, Value }
+
+impl Sort {
+    pub fn parse(s: &str) -> Option<Sort> {
+        match s { "key" => Some(Sort::Key), "value" => Some(Sort::Value), _ => None }
+    }
+}
+
+struct Row {
+    group: Group,
+    key: String,
+    // Display value (unit included) and the number it sorts by
+    text: String,
+    number: Option<f64>,
+}
+
+// One key's value as shown: temperatures in the chosen unit, power in W, fan speeds in rpm
+// (F0Ac actual, F0Tg target, F0Mn/F0Mx limits), voltages in V, currents in A.
+fn row(key: &str, group: Group, profile: &Profile, units: Units) -> Row {
+    let raw = crate::value::read(key).ok();
+    let info = crate::smcraw::shared().ok().and_then(|r| r.info(key).ok());
+    let number = raw.as_ref().and_then(SmcValue::as_f64).filter(|v| v.is_finite());
+    let unit = match group {
+        Group::Power => "W",
+        Group::Voltage => "V",
+        Group::Current => "A",
+        Group::Fans if ["Ac", "Tg", "Mn", "Mx"].iter().any(|s| key.ends_with(s)) => "rpm",
+        _ => "",
+    };
+    let temp = match (&info, number, key.starts_with('T')) {
+        (Some(i), Some(v), true) => crate::temps::celsius(&i.data_type, profile.classify(key), v),
+        _ => None,
+    };
+    let (text, number) = match (temp, number, &raw) {
+        (Some(c), _, _) => (format!("{:.1} {}", units.temp(c), units.symbol()), Some(units.temp(c))),
+        (None, Some(v), _) if !unit.is_empty() => (format!("{:.2} {}", v, unit), Some(v)),
+        (None, _, Some(v)) => (v.to_string(), number),
+        (None, _, None) => ("-".to_string(), None),
+    };
+    Row { group, key: key.to_string(), text, number }
+}
+
+// `filter` is a key prefix; without one only sensor groups are listed.
+pub fn run(keys: &[String], profile: &Profile, units: Units, filter: Option<&str>, sort: Sort) {
+    let mut rows: Vec<Row> = keys.iter()
+        .filter(|k| filter.is_none_or(|f| k.starts_with(f)))
+        .map(|k| (k, Group::of(k, profile)))
+        .filter(|(_, g)| *g != Group::Other || filter.is_some())
+        .map(|(k, g)| row(k, g, profile, units))
+        .collect();
+    // Stable: SMC order within a group unless sorting by value (largest first, unreadable last)
+    match sort {
+        Sort::Key => rows.sort_by_key(|r| r.group),
+        Sort::Value => rows.sort_by(|a, b| a.group.cmp(&b.group).then_with(|| b.number.unwrap_or(f64::NEG_INFINITY).total_cmp(&a.number.unwrap_or(f64::NEG_INFINITY)))),
+    }
+    if rows.is_empty() { println!("N/A"); return; }
+    let mut current = None;
+    for r in &rows {
+        if current != Some(r.group) {
+            if current.is_some() { println!(); }
+            println!("{}", r.group.title());
+            current = Some(r.group);
+        }
+        println!("  {}  {:>12}  {}", r.key, r.text, crate::labels::label(&r.key).unwrap_or(""));
+    }
+}
@@ -0,0 +1,59 @@
+// `write KEY VALUE --type TYPE`: sets any writable SMC key, for fan control, charge-limit and
+// calibration experiments that have no dedicated mode. Guard rails, in order: the key must not be
+// on the blocklist below, `--type` must name the type the SMC declares (so the value is encoded
+// the way the firmware reads it), the encoded bytes must fill the key's size exactly, and the
+// key must carry the write attribute. `--dry-run` stops there and prints the bytes; a real write
+// also needs `--i-know-what-im-doing`, goes through the undo journal and is read back.
+
+use crate::smcraw::Raw;
+use crate::value::{self, SmcValue};
+
+// Keys (or prefixes) never written: SMC bookkeeping, the write-protection keys, and the live
+// sensor readings the fan and power controllers act on (a fake reading steers them blind).
+const BLOCKED: &[(&str, &str)] = &[
+    ("#", "key table bookkeeping"),
+    ("$", "SMC firmware internals"),
+    ("+LKS", "SMC lockout state"),
+    ("KPPW", "SMC write-protection password"),
+    ("KPST", "SMC write-protection state"),
+    ("T", "temperature readings the fan and throttle controllers act on"),
+    ("V", "voltage readings"),
+    ("I", "current readings"),
+    ("P", "power readings"),
+];
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn blocked(key: &str) -> Option<&'static str> {
+    BLOCKED.iter().find(|(prefix, _)| key.starts_with(prefix)).map(|(_, why)| *why)
+}
+
+pub fn run(key: &str, text: &str, data_type: &str, dry_run: bool, armed: bool, yes: bool) -> Result<String, String> {
+    if let Some(why) = blocked(key) { return Err(format!("{} is blocklisted ({}); it is never written", key, why)); }
+    let smc = Raw::open()?;
+    let (info, current) = smc.read(key)?;
+    if data_type.trim() != info.data_type.trim() {
+        return Err(format!("{} is {:?}, not {:?}; pass --type {} if that is what you meant", key, info.data_type, data_type, info.data_type.trim()));
+    }
+    let mut bytes = value::encode(&info.data_type, text)?;
+    // Text keys are NUL-padded to their size
+    if matches!(info.data_type.as_str(), "ch8*" | "char") && bytes.len() < info.size as usize { bytes.resize(info.size as usize, 0); }
+    if bytes.len() != info.size as usize {
+        return Err(format!("{} holds {} byte(s) of {:?}; {:?} encodes to {} ({})", key, info.size, info.data_type, text, bytes.len(), hex(&bytes)));
+    }
+    if !info.attribute_names().contains(&"write") {
+        return Err(format!("{} is not writable (attributes {:#04x}: {})", key, info.attributes, info.attribute_names().join(", ")));
+    }
+    let plan = format!("{} = {} ({}, {} bytes: {}); currently {} ({})", key, SmcValue::from_info(&info, &bytes), info.data_type.trim(), info.size, hex(&bytes),
+        SmcValue::from_info(&info, &current), hex(&current));
+    if dry_run { return Ok(format!("would write {}", plan)); }
+    if !armed { return Err(format!("refusing to write {} without --i-know-what-im-doing (try --dry-run first)", plan)); }
+    if current == bytes { return Ok(format!("{} already holds {}; nothing written", key, hex(&bytes))); }
+    if !crate::journal::confirm(&format!("Write {}", plan), yes) { return Err("cancelled".to_string()); }
+    smc.write(key, &bytes)?;
+    let (_, now) = smc.read(key)?;
+    if now != bytes { return Err(format!("{} read back as {} after writing {}; the firmware ignored or adjusted the change (undo with restore-hw-state)", key, hex(&now), hex(&bytes))); }
+    Ok(format!("wrote {} = {} ({}); undo with restore-hw-state", key, SmcValue::from_info(&info, &now), hex(&now)))
+}
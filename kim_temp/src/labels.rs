@@ -0,0 +1,65 @@
+// Sensor labels in the user's language, layered over keydb's English names so a translation
+// never means editing (or forking) the key database. A translation file is plain text at
+// ~/.config/kim_temp/labels/<lang>, one `source<TAB>translation` per line, where source is either
+// an English name from keydb ("P-core cluster 1", "Battery") or a key ("Tp01") for a label of
+// its own; `#` starts a comment. `kim_temp labels` prints a starting file.
+// Only the human-facing modes (all, info, volts/amps, explore) use labels; JSON `name` fields
+// stay English so consumers can match on them.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+static LABELS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+// `--lang`, else $KIM_TEMP_LANG, $LC_ALL, $LC_MESSAGES, $LANG; "de_DE.UTF-8" becomes "de_DE".
+// None for English and the C locale.
+pub fn language(flag: Option<&str>) -> Option<String> {
+    let lang = flag.map(str::to_string).or_else(|| ["KIM_TEMP_LANG", "LC_ALL", "LC_MESSAGES", "LANG"].iter().find_map(|v| std::env::var(v).ok().filter(|s| !s.is_empty())))?;
+    let lang = lang.split(['.', '@']).next().unwrap_or("").to_string();
+    (!lang.is_empty() && !matches!(lang.as_str(), "C" | "POSIX" | "en") && !lang.starts_with("en_")).then_some(lang)
+}
+
+pub fn dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config/kim_temp/labels"))
+}
+
+// The file for `lang`, falling back from "de_AT" to "de".
+fn path(lang: &str) -> Option<PathBuf> {
+    let dir = dir()?;
+    [Some(lang), lang.split('_').next()].into_iter().flatten().map(|l| dir.join(l)).find(|p| p.is_file())
+}
+
+fn parse(text: &str) -> HashMap<String, String> {
+    text.lines().filter(|l| !l.trim_start().starts_with('#')).filter_map(|l| {
+        let (source, translation) = l.split_once('\t')?;
+        Some((source.trim().to_string(), translation.trim().to_string())).filter(|(s, t)| !s.is_empty() && !t.is_empty())
+    }).collect()
+}
+
+// Loads the translation for `lang` once per process; call before the first `label`. Returns the
+// file used, if any.
+pub fn load(lang: Option<&str>) -> Option<PathBuf> {
+    let path = lang.and_then(path);
+    let table = path.as_ref().and_then(|p| std::fs::read_to_string(p).ok()).map(|t| parse(&t)).unwrap_or_default();
+    let _ = LABELS.set(table);
+    path
+}
+
+// keydb's name for `key`, translated when the loaded file has it (by key first, then by name).
+pub fn label(key: &str) -> Option<&'static str> {
+    let english = crate::keydb::describe(key);
+    let Some(table) = LABELS.get() else { return english };
+    table.get(key).or_else(|| table.get(english?)).map(String::as_str).or(english)
+}
+
+// `kim_temp labels`: every English name keydb knows, with the loaded translation (or nothing)
+// after the TAB, ready to be saved as a translation file and filled in.
+pub fn template() -> Vec<String> {
+    let table = LABELS.get();
+    let mut seen = Vec::new();
+    for name in crate::keydb::names() {
+        if !seen.contains(&name) { seen.push(name); }
+    }
+    seen.iter().map(|n| format!("{}\t{}", n, table.and_then(|t| t.get(*n)).map_or("", String::as_str))).collect()
+}
@@ -0,0 +1,109 @@
+// `install-daemon` / `uninstall-daemon`: always-on logging on this Mac without building a pkg.
+// Writes the same plist `package` ships (pointing at this binary where it is) and loads it:
+//   system (default)  /Library/LaunchDaemons/ID.plist, runs as root, logs to /var/log/kim_temp
+//   --agent           ~/Library/LaunchAgents/ID.plist, runs as you, logs to ~/Library/Logs/kim_temp
+// The daemon runs as root so powermetrics needs no password; an agent needs the `setup-sudo` rule
+// (see the README) or the root helper for the power breakdown. `--serve` installs the root
+// helper instead: a LaunchDaemon running `serve` (see serve.rs), which every other invocation
+// then asks for powermetrics, so nothing else needs sudo.
+
+use crate::package;
+use std::path::PathBuf;
+
+// Where samples go, besides the JSON lines launchd captures from stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink { Jsonl, Csv }
+
+impl Sink {
+    pub fn parse(s: &str) -> Result<Sink, String> {
+        match s {
+            "jsonl" => Ok(Sink::Jsonl),
+            "csv" => Ok(Sink::Csv),
+            "sqlite" => Err("no SQLite store exists yet; use --sink csv (rotated daily, readable by `follow` and `calibrate`)".to_string()),
+            _ => Err(format!("unknown --sink {} (jsonl|csv)", s)),
+        }
+    }
+}
+
+pub struct Options<'a> {
+    pub identifier: &'a str,
+    pub agent: bool,
+    // `serve` instead of `stream` (interval and sinks don't apply)
+    pub serve: bool,
+    pub interval_s: Option<f64>,
+    pub sinks: Vec<Sink>,
+    pub config: Option<PathBuf>,
+    // Passed through to `stream` after the generated arguments
+    pub stream_args: Vec<String>,
+}
+
+struct Target {
+    plist: PathBuf,
+    log_dir: PathBuf,
+    // launchctl domain: "system" or "gui/UID"
+    domain: String,
+}
+
+fn target(identifier: &str, agent: bool) -> Result<Target, String> {
+    if !agent {
+        return Ok(Target { plist: PathBuf::from(format!("/Library/LaunchDaemons/{}.plist", identifier)), log_dir: PathBuf::from(package::LOG_DIR), domain: "system".to_string() });
+    }
+    let home = std::env::var_os("HOME").map(PathBuf::from).ok_or("HOME is not set")?;
+    Ok(Target {
+        plist: home.join(format!("Library/LaunchAgents/{}.plist", identifier)),
+        log_dir: home.join("Library/Logs/kim_temp"),
+        domain: format!("gui/{}", unsafe { libc::getuid() }),
+    })
+}
+
+fn root_check(agent: bool, action: &str) -> Result<(), String> {
+    if agent || unsafe { libc::geteuid() } == 0 { Ok(()) } else { Err(format!("{} a LaunchDaemon needs root: run it with sudo, or pass --agent to {} a per-user LaunchAgent instead", action, action.to_lowercase())) }
+}
+
+// A daemon's `stream` runs at the adaptive rate (adaptive.rs), which an interactive one only
+// does with --adaptive, unless its arguments set a rate or a trigger.
+pub fn adaptive(mut stream_args: Vec<String>) -> Vec<String> {
+    if !stream_args.iter().any(|a| matches!(a.as_str(), "--interval" | "--fixed-interval" | "--adaptive" | "--trigger")) { stream_args.push("--adaptive".to_string()); }
+    stream_args
+}
+
+// The job's arguments for the options, in the order they were asked for.
+fn job_args(opts: &Options, log_dir: &std::path::Path) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(s) = opts.interval_s { args.extend(["--interval".to_string(), format!("{}", (s * 1000.0).round() as u64)]); }
+    if opts.sinks.contains(&Sink::Csv) { args.extend(["--log-csv".to_string(), log_dir.join("samples.csv").display().to_string(), "--rotate".to_string(), "daily".to_string()]); }
+    if let Some(c) = &opts.config { args.extend(["--config".to_string(), c.display().to_string()]); }
+    args.extend(opts.stream_args.iter().cloned());
+    if opts.serve { args } else { adaptive(args) }
+}
+
+// Writes the plist, creates the log directory and (re)loads the job. Returns what was done.
+pub fn install(opts: &Options) -> Result<Vec<String>, String> {
+    root_check(opts.agent, "Installing")?;
+    let t = target(opts.identifier, opts.agent)?;
+    let exe = std::env::current_exe().and_then(|p| p.canonicalize()).map_err(|e| format!("cannot locate this binary: {}", e))?;
+    let log_dir = t.log_dir.display().to_string();
+    std::fs::create_dir_all(&t.log_dir).map_err(|e| format!("cannot create {}: {}", log_dir, e))?;
+    let args = job_args(opts, &t.log_dir);
+    let mode = if opts.serve { "serve" } else { "stream" };
+    package::write(&t.plist, &package::plist(opts.identifier, &exe.to_string_lossy(), mode, &args, &log_dir))?;
+    // Replacing a loaded job: bootout fails harmlessly when it isn't loaded
+    let _ = std::process::Command::new("launchctl").args(["bootout", &format!("{}/{}", t.domain, opts.identifier)]).output();
+    package::run("launchctl", &["bootstrap", &t.domain, &t.plist.to_string_lossy()])?;
+    Ok(vec![
+        format!("Installed {} ({})", t.plist.display(), if opts.agent { "LaunchAgent" } else { "LaunchDaemon" }),
+        format!("Runs: {} {} {}", exe.display(), mode, args.join(" ")),
+        if opts.serve { format!("Listens on {} (log: {}/serve.err)", crate::serve::socket_path(None).display(), log_dir) }
+        else { format!("Samples: {}/stream.jsonl{}", log_dir, if opts.sinks.contains(&Sink::Csv) { format!(", {}/samples.csv", log_dir) } else { String::new() }) },
+    ])
+}
+
+// Unloads the job and removes its plist; the logs stay.
+pub fn uninstall(identifier: &str, agent: bool) -> Result<String, String> {
+    root_check(agent, "Removing")?;
+    let t = target(identifier, agent)?;
+    if !t.plist.exists() { return Err(format!("{} is not installed ({} does not exist)", identifier, t.plist.display())); }
+    let _ = std::process::Command::new("launchctl").args(["bootout", &format!("{}/{}", t.domain, identifier)]).output();
+    std::fs::remove_file(&t.plist).map_err(|e| format!("cannot remove {}: {}", t.plist.display(), e))?;
+    Ok(format!("Removed {}; logs kept in {}", t.plist.display(), t.log_dir.display()))
+}
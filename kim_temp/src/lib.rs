@@ -0,0 +1,3 @@
+// Library side of kim_temp: types for reading what the binary records.
+
+pub mod snapshot;
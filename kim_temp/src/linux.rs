@@ -0,0 +1,297 @@
+// `--backend linux` (the default for `stream` on Linux): the SensorSource for Linux laptops and
+// desktops, so their lines have the same schema and go to the same sinks as the Macs'.
+//   temperatures  /sys/class/hwmon, each sensor keyed `chip/label` (`coretemp/Core 0`,
+//                 `nvme/Composite`) and grouped by its driver: coretemp, k10temp and zenpower
+//                 are the CPU, amdgpu/radeon/nouveau the GPU, nvme/drivetemp the SSD,
+//                 jc42/spd5118 memory; the battery's own sensor when power_supply has one
+//   power         RAPL (/sys/class/powercap) energy counters, differenced between samples: the
+//                 package, its core (cpu) and uncore (integrated GPU) domains, DRAM (memory) and
+//                 psys, the whole platform, where the firmware has it. Without psys the system
+//                 rail is the battery's discharge power or, on AC, the package. amdgpu's own
+//                 power sensor is the GPU rail on machines with one
+//   battery       /sys/class/power_supply
+// Since Linux 5.10 the RAPL counters are readable by root only (run with sudo, or make energy_uj
+// readable with a udev rule); without them the rails read 0. There is no powermetrics: cpu_mw and
+// gpu_mw are the core and GPU rails, and the process lists stay empty. Thermal pressure and the
+// P/E-core split are null.
+
+use crate::diag::{debug, info, trace};
+use crate::battery::{Adapter, Battery, Health};
+use crate::sample::Powermetrics;
+use crate::source::{Rail, SensorSource};
+use crate::temps::{Noise, Temps};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const HWMON: &str = "/sys/class/hwmon";
+const POWERCAP: &str = "/sys/class/powercap";
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+fn read(path: &Path) -> Option<String> {
+    fs::read_to_string(path).map_err(|e| trace!("{}: {}", path.display(), e)).ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+// Into a stack buffer rather than a String: a stream reads every sensor and rail this way each
+// sample, and sysfs numbers are a few bytes.
+fn read_num(path: &Path) -> Option<f64> {
+    use std::io::Read;
+    let mut buf = [0u8; 64];
+    let n = fs::File::open(path).and_then(|mut f| f.read(&mut buf)).map_err(|e| trace!("{}: {}", path.display(), e)).ok()?;
+    let v: f64 = std::str::from_utf8(&buf[..n]).ok()?.trim().parse().ok()?;
+    crate::diag::finite(v, &path.to_string_lossy())
+}
+
+// Directory entries sorted by name, so sensors keep their order between runs
+fn entries(dir: &str) -> Vec<PathBuf> {
+    let mut v: Vec<PathBuf> = fs::read_dir(dir).map(|d| d.flatten().map(|e| e.path()).collect()).unwrap_or_default();
+    v.sort();
+    v
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Cpu,
+    Gpu,
+    Mem,
+    Ssd,
+    Battery,
+}
+
+impl Group {
+    // As Category::group names it
+    fn name(self) -> &'static str {
+        match self { Group::Cpu => "cpu", Group::Gpu => "gpu", Group::Mem => "mem", Group::Ssd => "ssd", Group::Battery => "bat" }
+    }
+}
+
+fn group(chip: &str, label: &str) -> Option<Group> {
+    match chip {
+        "coretemp" | "k10temp" | "zenpower" | "cpu_thermal" => Some(Group::Cpu),
+        "amdgpu" | "radeon" | "nouveau" => Some(Group::Gpu),
+        "nvme" | "drivetemp" => Some(Group::Ssd),
+        "jc42" | "spd5118" => Some(Group::Mem),
+        // Laptop EC drivers label theirs
+        "thinkpad" | "dell_smm" | "asus" => match label { "CPU" => Some(Group::Cpu), "GPU" => Some(Group::Gpu), _ => None },
+        _ => None,
+    }
+}
+
+struct Sensor {
+    key: String,
+    // In m°C, or 0.1 °C for the battery's
+    input: PathBuf,
+    scale: f64,
+    group: Option<Group>,
+}
+
+fn sensors() -> Vec<Sensor> {
+    let mut out = Vec::new();
+    for dir in entries(HWMON) {
+        let Some(chip) = read(&dir.join("name")) else { continue };
+        let mut inputs: Vec<PathBuf> = fs::read_dir(&dir).map(|d| d.flatten().map(|e| e.path()).collect()).unwrap_or_default();
+        inputs.retain(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("temp") && n.ends_with("_input")));
+        // temp2 before temp10
+        inputs.sort_by_key(|p| p.file_name().and_then(|n| n.to_str()).and_then(|n| n[4..n.len() - 6].parse::<u32>().ok()).unwrap_or(0));
+        for input in inputs {
+            let name = input.file_name().and_then(|n| n.to_str()).unwrap_or_default().trim_end_matches("_input").to_string();
+            let label = read(&dir.join(format!("{}_label", name))).unwrap_or(name);
+            out.push(Sensor { key: format!("{}/{}", chip, label), group: group(&chip, &label), input, scale: 1000.0 });
+        }
+    }
+    for dir in entries(POWER_SUPPLY) {
+        if read(&dir.join("type")).as_deref() == Some("Battery") && dir.join("temp").exists() {
+            let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or("BAT").to_string();
+            out.push(Sensor { key: format!("{}/temp", name), input: dir.join("temp"), scale: 10.0, group: Some(Group::Battery) });
+        }
+    }
+    out
+}
+
+// One RAPL domain's counter and the power it showed over the last interval
+struct Domain {
+    name: String,
+    energy: PathBuf,
+    // Where energy_uj wraps
+    range_uj: f64,
+    last: Option<(Instant, f64)>,
+    watts: Option<f64>,
+}
+
+impl Domain {
+    fn sample(&mut self) {
+        let now = Instant::now();
+        let Some(uj) = read_num(&self.energy) else { self.watts = None; return };
+        if let Some((at, prev)) = self.last {
+            let secs = now.duration_since(at).as_secs_f64();
+            let delta = if uj >= prev { uj - prev } else { uj + self.range_uj - prev };
+            self.watts = (secs > 0.0).then(|| delta / 1e6 / secs);
+        }
+        self.last = Some((now, uj));
+    }
+}
+
+fn domains() -> Vec<Domain> {
+    entries(POWERCAP).into_iter()
+        .filter(|d| d.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("intel-rapl:")))
+        .filter_map(|d| Some(Domain { name: read(&d.join("name"))?, range_uj: read_num(&d.join("max_energy_range_uj")).unwrap_or(f64::MAX), energy: d.join("energy_uj"), last: None, watts: None }))
+        .collect()
+}
+
+// The first system battery (a mouse's reports scope "Device") and the adapter
+fn supply(kind: &[&str]) -> Option<PathBuf> {
+    entries(POWER_SUPPLY).into_iter().find(|d| read(&d.join("type")).is_some_and(|t| kind.contains(&t.as_str())) && read(&d.join("scope")).as_deref() != Some("Device"))
+}
+
+pub struct Linux {
+    sensors: Vec<Sensor>,
+    domains: Vec<Domain>,
+    amdgpu_power: Option<PathBuf>,
+    battery: Option<PathBuf>,
+}
+
+impl Linux {
+    pub fn open() -> Linux {
+        let amdgpu_power = entries(HWMON).into_iter().filter(|d| read(&d.join("name")).as_deref() == Some("amdgpu")).map(|d| d.join("power1_average")).find(|p| p.exists());
+        let mut linux = Linux { sensors: sensors(), domains: domains(), amdgpu_power, battery: supply(&["Battery"]) };
+        // The first sample then has a delta to show
+        for d in &mut linux.domains {
+            d.sample();
+            if d.last.is_none() { debug!("RAPL {} unreadable (energy_uj is root-only since Linux 5.10)", d.name); }
+        }
+        info!("linux backend: {} sensors, {} RAPL domains, amdgpu power {}, battery {}", linux.sensors.len(), linux.domains.len(),
+            linux.amdgpu_power.is_some(), linux.battery.as_ref().map_or("none".to_string(), |b| b.display().to_string()));
+        linux
+    }
+
+    // Summed over sockets (package-0, package-1, ...)
+    fn domain(&self, prefix: &str) -> Option<f64> {
+        let watts: Vec<f64> = self.domains.iter().filter(|d| d.name.starts_with(prefix)).filter_map(|d| d.watts).collect();
+        (!watts.is_empty()).then(|| watts.iter().sum())
+    }
+
+    fn bat(&self, file: &str) -> Option<f64> {
+        read_num(&self.battery.as_ref()?.join(file))
+    }
+
+    fn status(&self) -> Option<String> {
+        read(&self.battery.as_ref()?.join("status"))
+    }
+
+    // Out of the battery in W; negative while it charges
+    fn battery_w(&self) -> Option<f64> {
+        let w = self.bat("power_now").or_else(|| Some(self.bat("current_now")? * self.bat("voltage_now")? / 1e6))? / 1e6;
+        Some(if self.status().as_deref() == Some("Charging") { -w.abs() } else { w.abs() })
+    }
+
+    // Energy-based gauges report µWh; mAh then goes through the design voltage
+    fn mah(&self, charge: &str, energy: &str) -> Option<i64> {
+        let mah = self.bat(charge).map(|uah| uah / 1000.0).or_else(|| Some(self.bat(energy)? / self.bat("voltage_min_design")? * 1000.0))?;
+        Some(mah.round() as i64)
+    }
+
+    fn adapter_online(&self) -> bool {
+        supply(&["Mains", "USB"]).and_then(|d| read(&d.join("online"))).as_deref() == Some("1")
+    }
+}
+
+impl SensorSource for Linux {
+    fn refresh(&mut self) {
+        for d in &mut self.domains { d.sample(); }
+    }
+
+    fn rail(&mut self, rail: Rail) -> Option<f64> {
+        match rail {
+            Rail::System => self.domain("psys").or_else(|| self.battery_w().filter(|w| *w > 0.0)).or_else(|| self.domain("package")),
+            Rail::Battery => self.battery_w(),
+            Rail::Memory => self.domain("dram"),
+            Rail::Cpu => self.domain("core").or_else(|| self.domain("package")),
+            Rail::Gpu => self.amdgpu_power.as_ref().and_then(|p| read_num(p)).map(|uw| uw / 1e6).or_else(|| self.domain("uncore")),
+        }
+    }
+
+    // RAPL domains by name (`psys`, `package-0`, `core`, ...) for derived metrics
+    fn key(&mut self, key: &str) -> Option<f64> {
+        self.domains.iter().find(|d| d.name == key).and_then(|d| d.watts)
+    }
+
+    fn temps(&mut self, temps: &mut Temps, noise: &mut Noise) {
+        for v in [&mut temps.cpu, &mut temps.cpu_p, &mut temps.cpu_e, &mut temps.gpu, &mut temps.mem, &mut temps.ssd, &mut temps.bat] { v.clear(); }
+        let (mut gpu_n, mut n) = (0, 0);
+        for s in &self.sensors {
+            // Unplugged drives and sleeping GPUs fail the read or report nonsense
+            let Some(t) = read_num(&s.input).map(|v| v / s.scale).filter(|t| *t > 0.0 && *t < 150.0) else { continue };
+            let entry = (t, noise.observe(&s.key, t));
+            match s.group {
+                Some(Group::Cpu) => temps.cpu.push(entry),
+                Some(Group::Gpu) => { temps.gpu.push(entry); temps.set_gpu_sensor(gpu_n, &s.key, t); gpu_n += 1; }
+                Some(Group::Mem) => temps.mem.push(entry),
+                Some(Group::Ssd) => temps.ssd.push(entry),
+                Some(Group::Battery) => temps.bat.push(entry),
+                None => {}
+            }
+            temps.set_sensor(n, &s.key, t, entry.1, s.group.map(Group::name));
+            n += 1;
+        }
+        temps.gpu_sensors.truncate(gpu_n);
+        temps.sensors.truncate(n);
+    }
+
+    fn battery(&mut self) -> Option<Battery> {
+        self.battery.as_ref()?;
+        let uwh = self.bat("energy_full_design").or_else(|| Some(self.bat("charge_full_design")? * self.bat("voltage_min_design")? / 1e6))?;
+        Some(Battery { design_wh: (uwh / 1e6) as f32 })
+    }
+
+    fn pmset(&mut self) -> (i32, bool) {
+        (self.bat("capacity").map_or(0, |p| p as i32), self.status().is_some_and(|s| s != "Discharging"))
+    }
+
+    fn adapter(&mut self) -> Option<Adapter> {
+        if !self.adapter_online() { return None; }
+        Some(Adapter { watts: None, input_w: None, loss_w: None, charge_w: self.battery_w().filter(|w| *w < 0.0).map(f64::abs) })
+    }
+
+    fn health(&mut self) -> Option<Health> {
+        self.battery.as_ref()?;
+        let status = self.status();
+        Some(Health {
+            cycle_count: self.bat("cycle_count").map(|c| c as i64).filter(|c| *c > 0),
+            design_cycle_count: None,
+            design_mah: self.mah("charge_full_design", "energy_full_design"),
+            max_mah: self.mah("charge_full", "energy_full"),
+            serial: self.battery.as_ref().and_then(|b| read(&b.join("serial_number"))),
+            permanent_failure: self.battery.as_ref().and_then(|b| read(&b.join("health"))).is_some_and(|h| h == "Dead"),
+            fully_charged: status.as_deref().map(|s| s == "Full"),
+            charging: status.as_deref().map(|s| s == "Charging"),
+            external_connected: Some(self.adapter_online()),
+        })
+    }
+
+    fn powermetrics(&mut self, _excluded: &[&str]) -> Powermetrics {
+        let mw = |w: Option<f64>| w.map_or(0, |w| (w * 1000.0) as i32);
+        Powermetrics { cpu_mw: mw(self.rail(Rail::Cpu)), gpu_mw: mw(self.rail(Rail::Gpu)), ..Default::default() }
+    }
+
+    fn thermal_level(&mut self) -> Option<(&'static str, u8)> {
+        None
+    }
+
+    // MemAvailable, the kernel's estimate of what can be had without swapping
+    fn mem_free_pct(&mut self) -> i32 {
+        let info = read(Path::new("/proc/meminfo")).unwrap_or_default();
+        let kb = |name: &str| info.lines().find(|l| l.starts_with(name)).and_then(|l| l.split_whitespace().nth(1)).and_then(|v| v.parse::<f64>().ok());
+        match (kb("MemAvailable:"), kb("MemTotal:")) { (Some(a), Some(t)) if t > 0.0 => (a / t * 100.0) as i32, _ => 0 }
+    }
+}
+
+// The machine's product name from DMI, e.g. "ThinkPad X1 Carbon Gen 11" (ThinkPads keep it in
+// product_version), for the `model` field.
+pub fn model() -> String {
+    let dmi = |f: &str| read(&Path::new("/sys/class/dmi/id").join(f));
+    dmi("product_version").filter(|v| v.starts_with("ThinkPad")).or_else(|| dmi("product_name")).unwrap_or_else(|| "Linux".to_string())
+}
+
+pub fn hostname() -> String {
+    read(Path::new("/proc/sys/kernel/hostname")).unwrap_or_default()
+}
@@ -0,0 +1,229 @@
+// `measure -- <cmd>`: runs a command while integrating system power, then attributes a share
+// of the energy to the command's process tree so the report separates "cost of my command"
+// from everything else the machine was doing. Peak power and peak temperatures over the run
+// are reported alongside, so two builds can be compared on more than just total energy.
+//
+// Attribution methods:
+//   cpu-share (default)  task energy = total energy * task CPU time / whole-system CPU time over the run
+//   baseline             idle power is measured for a few seconds first; task energy = total - baseline * duration
+//   none                 report only the total
+
+use crate::profile::Profile;
+use crate::{json_num, json_str};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribution { CpuShare, Baseline, None }
+
+impl Attribution {
+    pub fn parse(s: &str) -> Option<Attribution> {
+        match s {
+            "cpu-share" => Some(Attribution::CpuShare),
+            "baseline" => Some(Attribution::Baseline),
+            "none" => Some(Attribution::None),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self { Attribution::CpuShare => "cpu-share", Attribution::Baseline => "baseline", Attribution::None => "none" }
+    }
+}
+
+pub struct Options {
+    pub interval: Duration,
+    pub attribution: Attribution,
+    pub baseline: Duration,
+    // Temperature keys swept for the peak temps (empty: no temperatures in the report)
+    pub temp_sensors: Vec<crate::temps::Sensor>,
+    pub units: crate::config::Units,
+}
+
+// ---- whole-system CPU time (host_statistics / HOST_CPU_LOAD_INFO) ----
+
+const HOST_CPU_LOAD_INFO: i32 = 3;
+const CPU_STATE_MAX: u32 = 4; // user, system, idle, nice
+const TICKS_PER_SEC: f64 = 100.0;
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn mach_host_self() -> u32;
+    fn host_statistics(host: u32, flavor: i32, info: *mut u32, count: *mut u32) -> i32;
+}
+
+// No mach host port elsewhere: the call fails (KERN_FAILURE) and the system share is unknown.
+#[cfg(not(target_os = "macos"))]
+unsafe fn mach_host_self() -> u32 {
+    0
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn host_statistics(_host: u32, _flavor: i32, _info: *mut u32, _count: *mut u32) -> i32 {
+    5
+}
+
+// Busy CPU seconds summed over all cores since boot.
+fn system_cpu_secs() -> Option<f64> {
+    let mut ticks = [0u32; CPU_STATE_MAX as usize];
+    let mut count = CPU_STATE_MAX;
+    let kr = unsafe { host_statistics(mach_host_self(), HOST_CPU_LOAD_INFO, ticks.as_mut_ptr(), &mut count) };
+    if kr != 0 { return None; }
+    let busy = ticks[0] as u64 + ticks[1] as u64 + ticks[3] as u64;
+    Some(busy as f64 / TICKS_PER_SEC)
+}
+
+// ---- process tree CPU time ----
+
+// `ps` cputime: [[dd-]hh:]mm:ss.ss
+fn parse_cputime(s: &str) -> Option<f64> {
+    let (days, rest) = match s.split_once('-') { Some((d, r)) => (d.parse::<f64>().ok()?, r), None => (0.0, s) };
+    let mut secs = 0.0;
+    for part in rest.split(':') { secs = secs * 60.0 + part.parse::<f64>().ok()?; }
+    Some(days * 86400.0 + secs)
+}
+
+// Follows every descendant of the root pid, keeping the highest CPU time seen for each, so
+// short-lived children still count as long as one poll caught them.
+struct ProcessTree {
+    root: u32,
+    members: HashSet<u32>,
+    cpu: HashMap<u32, f64>,
+}
+
+impl ProcessTree {
+    fn new(root: u32) -> ProcessTree {
+        ProcessTree { root, members: HashSet::from([root]), cpu: HashMap::new() }
+    }
+
+    fn poll(&mut self) {
+        let Some(out) = crate::subprocess::stdout(std::process::Command::new("ps").args(["-A", "-o", "pid=,ppid=,time="])) else { return };
+        let procs: Vec<(u32, u32, f64)> = out.lines().filter_map(|l| {
+            let mut f = l.split_whitespace();
+            Some((f.next()?.parse().ok()?, f.next()?.parse().ok()?, parse_cputime(f.next()?)?))
+        }).collect();
+        // Repeat until no new descendants appear (children may be listed before their parents)
+        loop {
+            let before = self.members.len();
+            for (pid, ppid, _) in &procs {
+                if self.members.contains(ppid) { self.members.insert(*pid); }
+            }
+            if self.members.len() == before { break; }
+        }
+        for (pid, _, secs) in &procs {
+            if *pid == self.root || self.members.contains(pid) {
+                let e = self.cpu.entry(*pid).or_insert(0.0);
+                *e = e.max(*secs);
+            }
+        }
+    }
+
+    fn total(&self) -> f64 { self.cpu.values().sum() }
+}
+
+// CPU seconds of reaped descendants, which covers anything that exited between polls.
+#[allow(clippy::unnecessary_cast)]
+fn children_cpu_secs() -> f64 {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 { return 0.0; }
+    (usage.ru_utime.tv_sec as f64 + usage.ru_stime.tv_sec as f64) + (usage.ru_utime.tv_usec as f64 + usage.ru_stime.tv_usec as f64) / 1e6
+}
+
+// Highest category averages and single hottest sensor seen during the run (°C).
+#[derive(Default)]
+struct PeakTemps {
+    cpu: f64,
+    gpu: f64,
+    mem: f64,
+    ssd: f64,
+    sensor: Option<(String, f64)>,
+}
+
+impl PeakTemps {
+    fn observe(&mut self, t: &crate::temps::Temps) {
+        use crate::temps::avg;
+        self.cpu = self.cpu.max(avg(&t.cpu));
+        self.gpu = self.gpu.max(avg(&t.gpu));
+        self.mem = self.mem.max(avg(&t.mem));
+        self.ssd = self.ssd.max(avg(&t.ssd));
+        for (key, temp, _, _) in &t.sensors {
+            if self.sensor.as_ref().is_none_or(|(_, max)| temp > max) { self.sensor = Some((key.clone(), *temp)); }
+        }
+    }
+
+    fn to_json(&self, units: crate::config::Units) -> String {
+        let sensor = match &self.sensor {
+            Some((key, temp)) => format!("{{\"key\":\"{}\",\"temp\":{:.1}}}", key, units.temp(*temp)),
+            None => "null".to_string(),
+        };
+        format!("{{\"cpu\":{:.1},\"gpu\":{:.1},\"mem\":{:.1},\"ssd\":{:.1},\"sensor\":{}}}", units.temp(self.cpu), units.temp(self.gpu), units.temp(self.mem), units.temp(self.ssd), sensor)
+    }
+}
+
+// Average power over `duration`, sampled every `interval`.
+fn average_power(profile: &Profile, key: &str, duration: Duration, interval: Duration) -> f64 {
+    let start = Instant::now();
+    let mut samples = Vec::new();
+    while start.elapsed() < duration {
+        if let Some(p) = crate::value::read_f64(key) { samples.push(profile.system_power(p as f32) as f64); }
+        std::thread::sleep(interval);
+    }
+    if samples.is_empty() { 0.0 } else { samples.iter().sum::<f64>() / samples.len() as f64 }
+}
+
+// Runs `cmd`, returning the JSON report and the command's exit code.
+pub fn run(profile: &Profile, cmd: &[String], opts: &Options, extra_json: &str) -> (String, i32) {
+    let power_key = profile.system_power_key.as_str();
+    let baseline_w = if opts.attribution == Attribution::Baseline {
+        eprintln!("measure: sampling idle power for {:.0}s...", opts.baseline.as_secs_f64());
+        Some(average_power(profile, power_key, opts.baseline, opts.interval))
+    } else { None };
+
+    let mut child = match std::process::Command::new(&cmd[0]).args(&cmd[1..]).spawn() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("measure: cannot run {}: {}", cmd[0], e); return (String::new(), 127); }
+    };
+    let mut tree = ProcessTree::new(child.id());
+    let cpu_start = system_cpu_secs();
+    let start = Instant::now();
+    let mut last = start;
+    let mut energy_j = 0.0;
+    let mut peak_w: f64 = 0.0;
+    let mut peak_temps = PeakTemps::default();
+    let mut last_poll: Option<Instant> = None;
+
+    let status = loop {
+        if let Ok(Some(status)) = child.try_wait() { break status; }
+        let now = Instant::now();
+        let watts = crate::value::read_f64(power_key).map(|p| profile.system_power(p as f32) as f64).unwrap_or(0.0);
+        energy_j += watts * now.duration_since(last).as_secs_f64();
+        peak_w = peak_w.max(watts);
+        last = now;
+        // ps and the sensor sweep are much more expensive than one SMC read, so they run at most twice a second
+        if last_poll.is_none_or(|t| now.duration_since(t) >= Duration::from_millis(500)) {
+            tree.poll();
+            if !opts.temp_sensors.is_empty() { peak_temps.observe(&crate::temps::Temps::collect(&opts.temp_sensors, None)); }
+            last_poll = Some(now);
+        }
+        std::thread::sleep(opts.interval);
+    };
+    let duration = start.elapsed().as_secs_f64();
+    let system_cpu = match (cpu_start, system_cpu_secs()) { (Some(a), Some(b)) => Some(b - a), _ => None };
+    let task_cpu = tree.total().max(children_cpu_secs());
+
+    let (task_j, share) = match opts.attribution {
+        Attribution::CpuShare => {
+            let share = system_cpu.filter(|s| *s > 0.0).map(|s| (task_cpu / s).clamp(0.0, 1.0));
+            (share.map(|s| energy_j * s), share)
+        }
+        Attribution::Baseline => (baseline_w.map(|b| (energy_j - b * duration).max(0.0)), None),
+        Attribution::None => (None, None),
+    };
+    let code = status.code().unwrap_or(-1);
+    let report = format!("{{\"command\":{},\"exit_code\":{},\"duration_s\":{:.3},\"energy_j\":{:.2},\"energy_wh\":{:.5},\"avg_power_w\":{:.2},\"peak_power_w\":{:.2},\"peak_temps\":{},\"attribution\":{{\"method\":\"{}\",\"task_cpu_s\":{:.3},\"system_cpu_s\":{},\"share\":{},\"baseline_w\":{},\"task_energy_j\":{},\"background_energy_j\":{}}}{}}}",
+        json_str(&cmd.join(" ")), code, duration, energy_j, energy_j / 3600.0, if duration > 0.0 { energy_j / duration } else { 0.0 }, peak_w,
+        if opts.temp_sensors.is_empty() { "null".to_string() } else { peak_temps.to_json(opts.units) },
+        opts.attribution.as_str(), task_cpu, json_num(system_cpu, 3), json_num(share, 4), json_num(baseline_w, 2),
+        json_num(task_j, 2), json_num(task_j.map(|t| energy_j - t), 2), extra_json);
+    (report, code)
+}
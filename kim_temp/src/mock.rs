@@ -0,0 +1,311 @@
+// `--backend mock` (feature "mock"): a simulated Mac behind the SensorSource trait. Twelve
+// sensors wander after a load that comes in bursts, a 70 Wh battery drains and charges, and
+// powermetrics reports processes whose names need escaping. Everything comes from one xorshift
+// generator, so a --seed gives the same readings every run; each sample is one simulated second,
+// however long the stream actually waits. `soak` drives the same machine with faults on:
+//   key_gone      one sensor (sometimes every sensor and rail) stops answering for a while
+//   timeout       a powermetrics or pmset run hangs and is killed, leaving empty output
+//   clock_jump    the wall clock steps forward or back (NTP, a user changing the time)
+//   sleep_gap     the machine sleeps: both clocks move on by minutes to hours between samples
+
+use crate::battery::{Adapter, Battery, Health};
+use crate::profile::Category;
+use crate::sample::Powermetrics;
+use crate::source::{Rail, SensorSource};
+use crate::temps::{Noise, Temps};
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const MODEL: &str = "Mock14,1";
+const DESIGN_WH: f32 = 70.0;
+
+const SENSORS: &[(&str, Category)] = &[
+    ("Tp01", Category::CpuP), ("Tp05", Category::CpuP), ("Tp09", Category::CpuP), ("Tp0D", Category::CpuP),
+    ("Te05", Category::CpuE), ("Te0L", Category::CpuE),
+    ("Tg05", Category::Gpu), ("Tg0D", Category::Gpu), ("Tg0L", Category::Gpu),
+    ("Tm02", Category::Mem), ("TSCD", Category::Ssd), ("TB1T", Category::Battery),
+];
+// Process names for top_cpu / high_wakeups, including ones that need escaping. No whitespace:
+// they go through the real parser, which splits powermetrics' task table on it.
+const PROCESSES: &[&str] = &["WindowServer", "kernel_task", "com.apple.WebKit.WebContent", "evil\"quote", "bell\u{7}char", "naïve", "back\\slash"];
+
+// xorshift64*: reproducible from --seed, no dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // True once in `n` calls on average
+    fn one_in(&mut self, n: u64) -> bool {
+        self.next().is_multiple_of(n)
+    }
+
+    fn range(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (self.next() >> 11) as f64 / (1u64 << 53) as f64 * (hi - lo)
+    }
+}
+
+// How often each fault has been injected
+#[derive(Default)]
+pub struct Faults {
+    pub key_gone: u64,
+    pub all_gone: u64,
+    pub timeout: u64,
+    pub clock_jump: u64,
+    pub sleep_gap: u64,
+}
+
+pub struct Mock {
+    rng: Rng,
+    inject: bool,
+    temps: Vec<f64>,
+    // Samples until each sensor answers again (0: present)
+    gone: Vec<u32>,
+    all_gone: u32,
+    power_w: f64,
+    battery_pct: f64,
+    charging: bool,
+    // The simulated clocks, as offsets from the real ones at creation
+    base: (Instant, SystemTime),
+    pub mono: Duration,
+    wall: Duration,
+    wall_back: Duration,
+    pub faults: Faults,
+}
+
+impl Mock {
+    // `inject`: roll the faults above on every step.
+    pub fn new(seed: u64, inject: bool) -> Mock {
+        Mock {
+            rng: Rng(seed.max(1)), inject, temps: SENSORS.iter().map(|_| 45.0).collect(), gone: vec![0; SENSORS.len()], all_gone: 0,
+            power_w: 8.0, battery_pct: 100.0, charging: false, base: (Instant::now(), SystemTime::now()),
+            mono: Duration::ZERO, wall: Duration::ZERO, wall_back: Duration::ZERO, faults: Faults::default(),
+        }
+    }
+
+    // Moves the simulated time on by `interval` and rolls this sample's faults.
+    pub fn advance(&mut self, interval: Duration) {
+        let mut step = interval;
+        if self.inject && self.rng.one_in(3000) {
+            self.faults.sleep_gap += 1;
+            step += Duration::from_secs_f64(self.rng.range(300.0, 4.0 * 3600.0));
+        }
+        self.mono += step;
+        self.wall += step;
+        if self.inject && self.rng.one_in(2000) {
+            self.faults.clock_jump += 1;
+            let jump = Duration::from_secs_f64(self.rng.range(1.0, 7200.0));
+            if self.rng.one_in(2) { self.wall += jump; } else { self.wall_back += jump; }
+        }
+        for g in &mut self.gone { *g = g.saturating_sub(1); }
+        self.all_gone = self.all_gone.saturating_sub(1);
+        if self.inject && self.rng.one_in(500) {
+            self.faults.key_gone += 1;
+            let i = self.rng.next() as usize % SENSORS.len();
+            self.gone[i] = 1 + (self.rng.next() % 600) as u32;
+        }
+        if self.inject && self.rng.one_in(5000) {
+            self.faults.all_gone += 1;
+            self.all_gone = 1 + (self.rng.next() % 60) as u32;
+        }
+        // Load comes in bursts; temperatures follow it
+        if self.rng.one_in(60) { self.power_w = self.rng.range(2.0, 60.0); }
+        let target = 35.0 + self.power_w * 1.2;
+        for t in &mut self.temps { *t += (target - *t) * 0.05 + self.rng.range(-0.8, 0.8); }
+        if self.charging { self.battery_pct = (self.battery_pct + step.as_secs_f64() / 60.0).min(100.0); } else { self.battery_pct = (self.battery_pct - self.power_w * step.as_secs_f64() / 20_000.0).max(0.0); }
+        if self.battery_pct <= 5.0 { self.charging = true; } else if (self.charging && self.battery_pct >= 100.0) || self.rng.one_in(20_000) { self.charging = !self.charging; }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.base.0 + self.mono
+    }
+
+    pub fn wall_now(&self) -> SystemTime {
+        (self.base.1 + self.wall).checked_sub(self.wall_back).unwrap_or(self.base.1)
+    }
+
+    // Whether a subprocess run hangs this time
+    fn timed_out(&mut self) -> bool {
+        let hung = self.inject && self.rng.one_in(20);
+        if hung { self.faults.timeout += 1; }
+        hung
+    }
+}
+
+impl SensorSource for Mock {
+    fn refresh(&mut self) {
+        self.advance(Duration::from_secs(1));
+    }
+
+    fn clock(&self) -> (Instant, SystemTime) {
+        (self.now(), self.wall_now())
+    }
+
+    fn rail(&mut self, rail: Rail) -> Option<f64> {
+        if self.all_gone > 0 { return None; }
+        let share = match rail { Rail::System => 1.0, Rail::Battery => 0.9, Rail::Memory => 0.05, Rail::Cpu => 0.5, Rail::Gpu => 0.2 };
+        Some(self.power_w * share + self.rng.range(-0.2, 0.2))
+    }
+
+    // The rails under the keys a real Mac reports them on
+    fn key(&mut self, key: &str) -> Option<f64> {
+        match key { "PSTR" => self.rail(Rail::System), "PPBR" => self.rail(Rail::Battery), "PHPM" => self.rail(Rail::Memory), _ => None }
+    }
+
+    fn temps(&mut self, temps: &mut Temps, noise: &mut Noise) {
+        for v in [&mut temps.cpu, &mut temps.cpu_p, &mut temps.cpu_e, &mut temps.gpu, &mut temps.mem, &mut temps.ssd, &mut temps.bat] { v.clear(); }
+        let (mut gpu_n, mut n) = (0, 0);
+        for (i, (key, category)) in SENSORS.iter().enumerate() {
+            if self.gone[i] > 0 || self.all_gone > 0 { continue; }
+            let t = self.temps[i];
+            let entry = (t, noise.observe(key, t));
+            match category {
+                Category::CpuP => { temps.cpu.push(entry); temps.cpu_p.push(entry); }
+                Category::CpuE => { temps.cpu.push(entry); temps.cpu_e.push(entry); }
+                Category::Gpu => { temps.gpu.push(entry); temps.set_gpu_sensor(gpu_n, key, t); gpu_n += 1; }
+                Category::Mem => temps.mem.push(entry),
+                Category::Ssd => temps.ssd.push(entry),
+                Category::Battery => temps.bat.push(entry),
+            }
+            temps.set_sensor(n, key, t, entry.1, Some(category.group()));
+            n += 1;
+        }
+        temps.gpu_sensors.truncate(gpu_n);
+        temps.sensors.truncate(n);
+    }
+
+    fn battery(&mut self) -> Option<Battery> {
+        Some(Battery { design_wh: DESIGN_WH })
+    }
+
+    // A timeout leaves what an empty stdout does
+    fn pmset(&mut self) -> (i32, bool) {
+        if self.timed_out() { return (0, false); }
+        (self.battery_pct.round() as i32, self.charging)
+    }
+
+    fn adapter(&mut self) -> Option<Adapter> {
+        self.charging.then_some(Adapter { watts: Some(96), input_w: Some(self.power_w + 30.0), loss_w: Some(2.5), charge_w: Some(27.5) })
+    }
+
+    fn health(&mut self) -> Option<Health> {
+        Some(Health {
+            cycle_count: Some(212), design_cycle_count: Some(1000), design_mah: Some(6075), max_mah: Some(5620), serial: Some("MOCK0000000001".to_string()),
+            permanent_failure: false, fully_charged: Some(self.battery_pct >= 100.0), charging: Some(self.charging), external_connected: Some(self.charging),
+        })
+    }
+
+    // powermetrics' text for this sample, through the parser the real source uses
+    fn powermetrics(&mut self, excluded: &[&str]) -> Powermetrics {
+        if self.timed_out() { return Powermetrics::default(); }
+        let busy = (self.power_w / 60.0 * 100.0).min(100.0);
+        let mut out = String::from("*** Sampled system activity (Mock) (100.00ms elapsed) ***\n\n*** Running tasks ***\n\n");
+        out.push_str("Name                               ID     CPU ms/s  User%  Deadlines (<2 ms, 2-5 ms)  Wakeups (Intr, Pkg idle)  GPU ms/s\n");
+        for (pid, name) in PROCESSES.iter().enumerate() {
+            let (cpu_ms, wakeups) = (self.rng.range(0.0, 200.0), self.rng.range(0.0, 120.0));
+            let _ = writeln!(out, "{:<34} {:<6} {:<9.2} {:<6.2} {:<7.2} {:<7.2} {:<8.2} {:<8.2} {:.2}", name, 100 + pid, cpu_ms, 50.0, 0.0, 0.0, wakeups, wakeups / 4.0, 0.0);
+        }
+        out.push_str("ALL_TASKS                          -2     0.00      0.00   0.00    0.00    0.00     0.00     0.00\n\n**** Processor usage ****\n\n");
+        let p_mhz = if self.rng.one_in(50) { 900.0 } else { 3504.0 };
+        for (cluster, mhz, active) in [("E", 2064.0, busy.max(20.0)), ("P0", p_mhz, busy)] {
+            let _ = writeln!(out, "{}-Cluster HW active frequency: {:.0} MHz\n{}-Cluster HW active residency: {:.2}%", cluster, mhz, cluster, active);
+        }
+        let _ = write!(out, "\nCPU Power: {} mW\nGPU Power: {} mW\nANE Power: 0 mW\n", (self.power_w * 500.0) as i32, (self.power_w * 200.0) as i32);
+        crate::sample::parse_powermetrics(&out, self.wall_now(), excluded)
+    }
+
+    fn thermal_level(&mut self) -> Option<(&'static str, u8)> {
+        let temp = self.temps[0];
+        Some(if temp > 95.0 { ("critical", 3) } else if temp > 85.0 { ("serious", 2) } else if temp > 75.0 { ("fair", 1) } else { ("nominal", 0) })
+    }
+
+    fn mem_free_pct(&mut self) -> i32 {
+        40
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{Family, Profile};
+
+    fn swept(mock: &mut Mock) -> Temps {
+        let mut temps = Temps::default();
+        mock.refresh();
+        mock.temps(&mut temps, &mut Noise::default());
+        temps
+    }
+
+    #[test]
+    fn sensors_classify_as_an_m4_profile_does() {
+        let profile = Profile::for_family(Family::M4, "Apple M4");
+        for (key, category) in SENSORS {
+            assert_eq!(profile.classify(key), Some(*category), "{}", key);
+        }
+        let t = swept(&mut Mock::new(1, false));
+        assert_eq!((t.cpu.len(), t.cpu_p.len(), t.cpu_e.len(), t.gpu.len(), t.mem.len(), t.ssd.len(), t.bat.len()), (6, 4, 2, 3, 1, 1, 1));
+        assert_eq!(t.sensors.len(), SENSORS.len());
+        assert_eq!(t.gpu_sensors.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["Tg05", "Tg0D", "Tg0L"]);
+    }
+
+    #[test]
+    fn aggregates_follow_the_readings() {
+        let t = swept(&mut Mock::new(2, false));
+        let mean = |v: &[(f64, f64)]| v.iter().map(|(t, _)| t).sum::<f64>() / v.len() as f64;
+        assert!((crate::temps::plain_avg(&t.cpu) - mean(&t.cpu)).abs() < 1e-9);
+        let weighted = t.cpu.iter().map(|(t, w)| t * w).sum::<f64>() / t.cpu.iter().map(|(_, w)| w).sum::<f64>();
+        assert!((crate::temps::avg(&t.cpu) - weighted).abs() < 1e-9);
+        let max = t.cpu.iter().map(|(t, _)| *t).fold(f64::MIN, f64::max);
+        assert_eq!(crate::temps::max(&t.cpu), Some(max));
+        let hottest = t.sensors.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        assert_eq!(crate::temps::hottest(&t).map(|(k, temp, _)| (k, temp)), Some((hottest.0.as_str(), hottest.1)));
+    }
+
+    #[test]
+    fn gone_sensors_leave_their_categories() {
+        // Counted down by the refresh before the sweep
+        let mut mock = Mock::new(3, false);
+        mock.gone[0] = 2;
+        let t = swept(&mut mock);
+        assert_eq!((t.cpu.len(), t.cpu_p.len(), t.sensors.len()), (5, 3, SENSORS.len() - 1));
+        mock.all_gone = 2;
+        let t = swept(&mut mock);
+        assert!(t.cpu.is_empty() && t.sensors.is_empty());
+        assert_eq!(crate::temps::avg_opt(&t.cpu), None);
+        assert_eq!(mock.rail(Rail::System), None);
+    }
+
+    #[test]
+    fn powermetrics_output_is_sorted_and_escaped() {
+        let mut mock = Mock::new(4, false);
+        mock.refresh();
+        let pm = mock.powermetrics(&["kernel_task"]);
+        assert_eq!(pm.top.len(), 5);
+        assert!(pm.top.windows(2).all(|w| w[0].1 >= w[1].1), "{:?}", pm.top);
+        assert!(pm.top.iter().all(|(n, _)| n != "kernel_task"));
+        let top = pm.top.iter().map(|(n, c)| format!("{{\"name\":{},\"cpu_ms\":{:.1},", crate::sample::Quoted(n), c)).collect::<Vec<_>>();
+        assert!(top.iter().all(|p| pm.top_json.contains(p.as_str())) && !pm.top_json.contains('\u{7}'), "{}", pm.top_json);
+        assert_eq!(crate::sample::Quoted("evil\"quote\u{7}back\\slash").to_string(), r#""evil\"quote\u0007back\\slash""#);
+        assert!(pm.high_wakeups.iter().all(|(_, w)| *w > 50.0));
+        assert_eq!((pm.cpu_mw, pm.gpu_mw, pm.ane_mw), ((mock.power_w * 500.0) as i32, (mock.power_w * 200.0) as i32, 0));
+        assert_eq!(pm.clusters.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["E", "P0"]);
+        // The window ends at this sample, on the mock's clock
+        let (start, ms) = pm.window.unwrap();
+        assert_eq!((start + Duration::from_millis(100), ms), (mock.wall_now(), 100.0));
+    }
+
+    #[test]
+    fn a_seed_reads_the_same_every_run() {
+        let run = |seed| {
+            let mut mock = Mock::new(seed, false);
+            (0..20).map(|_| { let t = swept(&mut mock); (crate::temps::avg(&t.cpu), mock.rail(Rail::System), mock.powermetrics(&[]).top_json) }).collect::<Vec<_>>()
+        };
+        assert_eq!(run(5), run(5));
+        assert_ne!(run(5), run(6));
+    }
+}
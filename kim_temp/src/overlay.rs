@@ -0,0 +1,71 @@
+// `overlay`: live power and temperatures for screen recordings. Each interval (default 1 s) one
+// short line is written to --out, which OBS shows with a Text source set to "Read from file"
+// (any other tool that polls a text file works the same way). The file is replaced atomically,
+// so a reader never sees it half-written, and emptied on exit so a stopped overlay doesn't leave
+// stale numbers in the video. Only SMC reads run (no powermetrics), so the overlay doesn't
+// disturb the benchmark it is showing. `--template-file` renders the sample JSON (plus
+// `energy_wh` since start) instead of the built-in line.
+
+use crate::config::Units;
+use crate::profile::Profile;
+use crate::reading::Reading;
+use crate::sample::{Fields, Powermetrics};
+use crate::temps::Sensor;
+use crate::temps;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// What the built-in line shows, unless --fields says otherwise
+pub const FIELDS: &str = "cpu_temp,gpu_temp,power_w,battery_pct,charging";
+
+pub struct Options {
+    pub out: PathBuf,
+    pub interval: Duration,
+    #[cfg(feature = "templates")]
+    pub template: Option<crate::template::Template>,
+}
+
+// e.g. "9.04 W · 0.125 Wh · CPU 58°C · GPU 47°C · 82% ⚡"
+fn line(reading: &Reading, energy_wh: f64, units: Units) -> String {
+    let mut parts = vec![match reading.power_w() { Some(w) => format!("{:.2} W", w), None => "-- W".to_string() }, format!("{:.3} Wh", energy_wh)];
+    let t = &reading.temps;
+    if let Some(c) = temps::avg_opt(&t.cpu) { parts.push(format!("CPU {:.0}{}", units.temp(c), units.symbol())); }
+    if let Some(g) = temps::avg_opt(&t.gpu) { parts.push(format!("GPU {:.0}{}", units.temp(g), units.symbol())); }
+    if let Some((pct, charging)) = reading.battery() { parts.push(format!("{}%{}", pct, if charging { " ⚡" } else { "" })); }
+    parts.join(" · ")
+}
+
+// Written next to `path` and renamed over it.
+fn replace(path: &Path, text: &str) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, text).and_then(|_| std::fs::rename(&tmp, path)).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+// Runs until interrupted.
+pub fn run(opts: &Options, profile: &Profile, sensors: &[Sensor], fields: &Fields, units: Units, derived: &[crate::derived::Derived]) -> Result<(), String> {
+    #[cfg(feature = "templates")]
+    let start = Instant::now();
+    let mut last = None::<Instant>;
+    let mut energy_j = 0.0;
+    eprintln!("overlay: writing {} every {} ms (Ctrl-C stops)", opts.out.display(), opts.interval.as_millis());
+    while crate::session::interrupted().is_none() {
+        let reading = crate::reading::collect(profile, sensors, fields, false, Powermetrics::default(), derived);
+        let now = Instant::now();
+        if let (Some(l), Some(w)) = (last, reading.power_w()) { energy_j += w as f64 * now.duration_since(l).as_secs_f64(); }
+        last = Some(now);
+        let text = line(&reading, energy_j / 3600.0, units);
+        #[cfg(feature = "templates")]
+        let text = match &opts.template {
+            Some(t) => {
+                let mut reading = reading;
+                let json = reading.json(fields, units, false, None, None);
+                let json = format!("{},\"energy_wh\":{:.4},\"elapsed_s\":{:.0}}}", json.strip_suffix('}').unwrap_or(&json), energy_j / 3600.0, start.elapsed().as_secs_f64());
+                t.render(&json).unwrap_or(text)
+            }
+            None => text,
+        };
+        replace(&opts.out, &text)?;
+        crate::session::sleep(opts.interval.saturating_sub(now.elapsed()));
+    }
+    replace(&opts.out, "")
+}
@@ -0,0 +1,110 @@
+// `package`: builds an installer for the always-on setup, a LaunchDaemon running `stream` as
+// root (so powermetrics needs no password), from the running binary:
+//   OUT/root/usr/local/bin/kim_temp                   signed with --sign (hardened runtime)
+//   OUT/root/Library/LaunchDaemons/IDENTIFIER.plist
+//   OUT/scripts/postinstall                           (re)loads the daemon
+//   OUT/kim_temp-VERSION.pkg                          pkgbuild, signed with --installer-sign
+// With --notarize-profile (a `xcrun notarytool store-credentials` profile) the pkg is
+// submitted, waited for, and stapled. Notarization needs both identities.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub struct Options<'a> {
+    pub out: PathBuf,
+    pub identifier: &'a str,
+    pub version: &'a str,
+    // "Developer ID Application: ..." for the binary, "Developer ID Installer: ..." for the pkg
+    pub sign: Option<&'a str>,
+    pub installer_sign: Option<&'a str>,
+    pub notarize_profile: Option<&'a str>,
+    // Arguments for the daemon's `kim_temp stream`
+    pub stream_args: Vec<String>,
+}
+
+const BIN: &str = "/usr/local/bin/kim_temp";
+pub const LOG_DIR: &str = "/var/log/kim_temp";
+
+fn xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Launchd job running `PROGRAM MODE ARGS`, kept alive, output appended to `log_dir`: samples to
+// stream.jsonl for `stream`, MODE.log otherwise, and stderr to MODE.err (also used by
+// `install-daemon`, see launchd.rs).
+pub fn plist(identifier: &str, program: &str, mode: &str, mode_args: &[String], log_dir: &str) -> String {
+    let out = if mode == "stream" { "stream.jsonl".to_string() } else { format!("{}.log", mode) };
+    let args: String = [program, mode].iter().map(|s| s.to_string()).chain(mode_args.iter().cloned())
+        .map(|a| format!("        <string>{}</string>\n", xml(&a))).collect();
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{id}</string>
+    <key>ProgramArguments</key>
+    <array>
+{args}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>ProcessType</key>
+    <string>Background</string>
+    <key>StandardOutPath</key>
+    <string>{log}/{out}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}/{mode}.err</string>
+</dict>
+</plist>
+"#, id = xml(identifier), args = args, log = xml(log_dir), out = xml(&out), mode = xml(mode))
+}
+
+fn postinstall(identifier: &str) -> String {
+    format!("#!/bin/sh\nmkdir -p {log}\nlaunchctl bootout system/{id} 2>/dev/null\nlaunchctl bootstrap system /Library/LaunchDaemons/{id}.plist\nexit 0\n", log = LOG_DIR, id = identifier)
+}
+
+// Runs a tool, echoing the command line; a non-zero exit is an error carrying its stderr.
+pub fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    eprintln!("+ {} {}", program, args.join(" "));
+    let out = Command::new(program).args(args).output().map_err(|e| format!("cannot run {}: {}", program, e))?;
+    if out.status.success() { Ok(()) } else { Err(format!("{} failed: {}", program, String::from_utf8_lossy(&out.stderr).trim())) }
+}
+
+pub fn write(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?; }
+    std::fs::write(path, contents).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+// Returns the path of the built pkg.
+pub fn build(opts: &Options) -> Result<PathBuf, String> {
+    if opts.notarize_profile.is_some() && (opts.sign.is_none() || opts.installer_sign.is_none()) {
+        return Err("notarization needs --sign and --installer-sign (Developer ID identities)".to_string());
+    }
+    let root = opts.out.join("root");
+    let bin = root.join(BIN.trim_start_matches('/'));
+    if let Some(dir) = bin.parent() { std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?; }
+    let exe = std::env::current_exe().map_err(|e| format!("cannot locate this binary: {}", e))?;
+    std::fs::copy(&exe, &bin).map_err(|e| format!("cannot copy {} to {}: {}", exe.display(), bin.display(), e))?;
+    write(&root.join(format!("Library/LaunchDaemons/{}.plist", opts.identifier)), &plist(opts.identifier, BIN, "stream", &crate::launchd::adaptive(opts.stream_args.clone()), LOG_DIR))?;
+    let scripts = opts.out.join("scripts");
+    write(&scripts.join("postinstall"), &postinstall(opts.identifier))?;
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(scripts.join("postinstall"), std::fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+
+    match opts.sign {
+        Some(id) => run("codesign", &["--force", "--options", "runtime", "--timestamp", "--identifier", opts.identifier, "--sign", id, &bin.to_string_lossy()])?,
+        None => crate::diag::warn!("binary left unsigned (--sign); Gatekeeper will block it on other Macs"),
+    }
+    let pkg = opts.out.join(format!("kim_temp-{}.pkg", opts.version));
+    let (root_s, scripts_s, pkg_s) = (root.to_string_lossy(), scripts.to_string_lossy(), pkg.to_string_lossy());
+    let mut args = vec!["--root", &root_s, "--scripts", &scripts_s, "--identifier", opts.identifier, "--version", opts.version, "--install-location", "/"];
+    if let Some(id) = opts.installer_sign { args.extend(["--sign", id, "--timestamp"]); }
+    args.push(&pkg_s);
+    run("pkgbuild", &args)?;
+    if let Some(profile) = opts.notarize_profile {
+        run("xcrun", &["notarytool", "submit", &pkg_s, "--keychain-profile", profile, "--wait"])?;
+        run("xcrun", &["stapler", "staple", &pkg_s])?;
+    }
+    Ok(pkg)
+}
@@ -0,0 +1,566 @@
+// `stream` as three stages, each taking only its inputs and returning only its outputs, so a new
+// field touches one stage and any stage can be driven on its own (`soak` feeds the real
+// serializer from a mock machine). The first two read the machine only through a SensorSource
+// (source.rs), so `--backend mock` runs them without one:
+//   sample     SMC rails and temperatures plus the cached pmset state: fast, every cycle
+//   enrich     powermetrics (every 5th sample), the adapter, drain, thermal pressure, derived
+//              metrics, every event (self-test, quiet hours, throttling, alerts) and the interval
+//              to the next sample: the slow, stateful part. On a Mac powermetrics runs on its own
+//              thread (Slow), so a run that takes seconds never holds up a sample
+//   serialize  the JSON line, written into one reused Record
+// What each stage took is in `meter_self.stages_ms`; the serialize time is the previous line's,
+// since a line can't contain its own.
+
+use crate::alerts::Engine;
+use crate::battery::{Adapter, Drain};
+use crate::clock::LocalTime;
+use crate::config::Units;
+use crate::profile::Profile;
+use crate::sample::{self, Fields, Powermetrics, Record};
+use crate::source::{Rail, SensorSource};
+use crate::temps::{self, Noise, Temps};
+use crate::{json_num, json_opt};
+use arc_swap::ArcSwap;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+// ---- sample ----
+
+pub struct Sample {
+    pub seq: u64,
+    // Monotonic time, for rates and rolling windows
+    pub mono: Instant,
+    pub at: SystemTime,
+    pub ts: LocalTime,
+    pub sys_power_raw: f32,
+    // After any wall-meter correction
+    pub sys_power: f32,
+    pub bat_power: f32,
+    pub mem_power: f32,
+    // Design capacity (Wh) when the Mac has a battery
+    pub design_wh: Option<f32>,
+    pub battery_pct: Option<i32>,
+    pub charging: Option<bool>,
+    pub mem_free_pct: i32,
+}
+
+impl Sample {
+    // The battery rail, when there is a battery
+    pub fn bat_power_w(&self) -> Option<f32> {
+        self.design_wh.map(|_| self.bat_power)
+    }
+}
+
+pub struct Sampler<'a> {
+    profile: &'a Profile,
+    noise: Noise,
+    design_wh: Option<f32>,
+    // pmset is a subprocess: refreshed every 5th sample, not every sample, and at least every
+    // 5 s so an adaptive stream at its idle rate sees the charger on the next sample
+    pmset: bool,
+    cached_pmset: Option<(i32, bool)>,
+    pmset_at: Instant,
+    mem: bool,
+}
+
+impl<'a> Sampler<'a> {
+    // `pmset`/`mem`: whether anything needs the battery state or free memory.
+    pub fn new(profile: &'a Profile, design_wh: Option<f32>, pmset: bool, mem: bool) -> Sampler<'a> {
+        Sampler { profile, noise: Noise::default(), design_wh, pmset, cached_pmset: None, pmset_at: Instant::now(), mem }
+    }
+
+    // Reads this cycle's values; temperatures go into `temps`, whose buffers are reused.
+    pub fn sample(&mut self, source: &mut dyn SensorSource, seq: u64, temps: &mut Temps) -> Sample {
+        let started = Instant::now();
+        source.refresh();
+        // After the refresh, which moves a simulated source's clocks on to this sample
+        let (mono, at) = source.clock();
+        // 0 W when a rail can't be read, like read_w
+        let mut rail = |r| source.rail(r).map_or(0.0, |w| w as f32);
+        let (sys_power_raw, bat_power, mem_power) = (rail(Rail::System), rail(Rail::Battery), rail(Rail::Memory));
+        let rails_at = Instant::now();
+        source.temps(temps, &mut self.noise);
+        let temps_at = Instant::now();
+        if self.design_wh.is_some() && self.pmset && (self.cached_pmset.is_none() || seq % 5 == 1 || mono.duration_since(self.pmset_at).as_secs() >= 5) {
+            (self.cached_pmset, self.pmset_at) = (Some(source.pmset()), mono);
+        }
+        // What this tick's reads cost, to see the observer effect without a profiler
+        let ms = |from: Instant, to: Instant| to.duration_since(from).as_secs_f64() * 1000.0;
+        crate::diag::debug!("sample {}: {:.2} ms (rails {:.2} ms, {} temperature sensors {:.2} ms)", seq, ms(started, Instant::now()), ms(started, rails_at), temps.sensors.len(), ms(rails_at, temps_at));
+        Sample {
+            seq, mono, at, ts: crate::clock::local(at), sys_power_raw, sys_power: self.profile.system_power(sys_power_raw), bat_power, mem_power,
+            design_wh: self.design_wh, battery_pct: self.cached_pmset.map(|(p, _)| p), charging: self.cached_pmset.map(|(_, c)| c),
+            mem_free_pct: if self.mem { source.mem_free_pct() } else { 0 },
+        }
+    }
+}
+
+// ---- enrich ----
+
+#[derive(Default)]
+pub struct Enrichment {
+    // powermetrics ran this cycle (the Enricher's `pm` is new)
+    pub pm_fresh: bool,
+    pub quiet: bool,
+    pub events: Vec<String>,
+    pub derived: Vec<(String, Option<f64>)>,
+    pub thermal_level: Option<(&'static str, u8)>,
+    pub drain_pct_per_hr: Option<f64>,
+    pub est_hours_remaining: Option<f64>,
+    pub charge_input_w: Option<f64>,
+    pub charge_overhead_w: Option<f64>,
+    pub adapter_w: Option<u32>,
+    pub dominant: Option<[Option<&'static str>; 2]>,
+    // Read the moment the battery reached full charge, for the battery-health history
+    pub full_charge: Option<crate::battery::Health>,
+    // --trigger isn't recording: the line goes to the pre-trigger ring instead of the output
+    pub held: bool,
+    // Until the next sample
+    pub interval_ms: u64,
+}
+
+// Which of the enrich stage's collectors anything (a field or an alert rule) needs
+#[derive(Default, Clone, Copy)]
+pub struct Needs {
+    pub temps: bool,
+    pub powermetrics: bool,
+    pub adapter: bool,
+    pub derived: bool,
+    pub dominant: bool,
+}
+
+// The powermetrics thread. Each run is asked for (and skipped while one is still going); its
+// result replaces the shared sample, which the enrich stage picks up on its next cycle without
+// waiting. Stopping `stream` ends a run in progress (session.rs). The SMC sweep keeps to the
+// emitting loop: it takes milliseconds, and sweeps into the loop's reused buffers (bench.rs).
+pub struct Slow {
+    latest: Arc<ArcSwap<Powermetrics>>,
+    // The sample last taken from `latest`, to tell a new one
+    seen: Arc<Powermetrics>,
+    wake: SyncSender<()>,
+}
+
+impl Slow {
+    pub fn spawn(run: fn(&[&str]) -> Powermetrics, excluded: &[&str]) -> Slow {
+        let seen = Arc::new(Powermetrics::default());
+        let latest = Arc::new(ArcSwap::new(seen.clone()));
+        let (wake, woken) = mpsc::sync_channel(1);
+        let (shared, excluded) = (latest.clone(), excluded.iter().map(|p| p.to_string()).collect::<Vec<_>>());
+        std::thread::spawn(move || {
+            let excluded: Vec<&str> = excluded.iter().map(String::as_str).collect();
+            // Ends with the Enricher
+            while woken.recv().is_ok() { shared.store(Arc::new(run(&excluded))); }
+        });
+        Slow { latest, seen, wake }
+    }
+
+    fn request(&self) {
+        let _ = self.wake.try_send(());
+    }
+
+    // The sample a run finished since the last call, if any
+    fn take(&mut self) -> Option<Arc<Powermetrics>> {
+        let latest = self.latest.load_full();
+        if Arc::ptr_eq(&latest, &self.seen) { return None; }
+        self.seen = latest.clone();
+        Some(latest)
+    }
+}
+
+pub struct Enricher<'a> {
+    profile: &'a Profile,
+    excluded: &'a [&'a str],
+    derived: &'a [crate::derived::Derived],
+    units: Units,
+    needs: Needs,
+    has_battery: bool,
+    backlight: bool,
+    quiet_hours: Option<crate::quiet::Window>,
+    // The latest powermetrics sample, reused between runs
+    pub pm: Arc<Powermetrics>,
+    // None: powermetrics runs inline, in this stage
+    slow: Option<Slow>,
+    adapter: Option<Adapter>,
+    drain: Drain,
+    // None: no battery-health history
+    full_charge: Option<crate::battery::FullCharge>,
+    throttle: crate::throttle::Detector,
+    alerts: Engine,
+    trigger: Option<crate::trigger::Trigger>,
+    // None: a fixed interval of `interval_ms`; otherwise that is the fast rate
+    adaptive: Option<crate::adaptive::Controller>,
+    interval_ms: u64,
+    revalidate_every: Duration,
+    last_validated: Instant,
+    // Events for the next sample, e.g. the startup self-test
+    pending: Vec<String>,
+}
+
+pub struct EnrichOptions<'a> {
+    pub profile: &'a Profile,
+    pub excluded: &'a [&'a str],
+    pub derived: &'a [crate::derived::Derived],
+    pub units: Units,
+    pub needs: Needs,
+    pub has_battery: bool,
+    pub backlight: bool,
+    pub quiet_hours: Option<crate::quiet::Window>,
+    pub adaptive: bool,
+    // Read the battery's health at each full charge
+    pub full_charge: bool,
+    // Some: run powermetrics this way on its own thread (SensorSource::powermetrics_fn)
+    pub powermetrics_fn: Option<fn(&[&str]) -> Powermetrics>,
+    pub interval_ms: u64,
+    // Zero turns rail re-validation off
+    pub revalidate_every: Duration,
+}
+
+impl<'a> Enricher<'a> {
+    pub fn new(opts: EnrichOptions<'a>, alerts: Engine, trigger: Option<crate::trigger::Trigger>, startup_events: Vec<String>) -> Enricher<'a> {
+        Enricher {
+            profile: opts.profile, excluded: opts.excluded, derived: opts.derived, units: opts.units, needs: opts.needs,
+            has_battery: opts.has_battery, backlight: opts.backlight, quiet_hours: opts.quiet_hours,
+            pm: Default::default(), slow: opts.powermetrics_fn.filter(|_| opts.needs.powermetrics).map(|run| Slow::spawn(run, opts.excluded)), adapter: None, drain: Drain::default(), full_charge: opts.full_charge.then(Default::default),
+            throttle: Default::default(), alerts, trigger, adaptive: opts.adaptive.then(|| crate::adaptive::Controller::new(opts.interval_ms)), interval_ms: opts.interval_ms,
+            revalidate_every: opts.revalidate_every, last_validated: Instant::now(), pending: startup_events,
+        }
+    }
+
+    pub fn enrich(&mut self, source: &mut dyn SensorSource, s: &Sample, temps: &Temps) -> Enrichment {
+        let mut e = Enrichment { events: std::mem::take(&mut self.pending), ..Default::default() };
+        let units = self.units;
+        // Quiet hours suspend powermetrics and notifications; the SMC readings go on
+        e.quiet = self.quiet_hours.is_some_and(|w| w.contains(&s.ts));
+        let quiet_changed = e.quiet != self.alerts.quiet;
+        self.alerts.quiet = e.quiet;
+        if e.quiet && quiet_changed { self.pm = Default::default(); }
+        if let (true, Some(w)) = (quiet_changed, self.quiet_hours) {
+            e.events.push(format!("{{\"type\":\"quiet_hours\",\"state\":\"{}\",\"window\":{}}}", if e.quiet { "started" } else { "ended" }, w.to_json()));
+        }
+        if let (Some(p), Some(c)) = (s.battery_pct, s.charging) { (e.drain_pct_per_hr, e.est_hours_remaining) = self.drain.observe(s.mono, p, c); }
+        if let (Some(fc), Some(p), Some(c)) = (&mut self.full_charge, s.battery_pct, s.charging) {
+            e.full_charge = fc.observe(p, c).then(|| source.health()).flatten();
+            if let Some(h) = &e.full_charge {
+                e.events.push(format!("{{\"type\":\"full_charge\",\"max_mah\":{},\"health_pct\":{},\"cycle_count\":{}}}", json_opt(h.max_mah), json_num(h.health_pct(), 1), json_opt(h.cycle_count)));
+            }
+        }
+        if !self.revalidate_every.is_zero() && self.last_validated.elapsed() >= self.revalidate_every {
+            self.last_validated = Instant::now();
+            // With the powermetrics thread, its latest sample rather than a run that would hold this one up
+            let pm = (self.needs.powermetrics && !e.quiet).then(|| if self.slow.is_some() { self.pm.clone() } else { Arc::new(source.powermetrics(&[])) });
+            e.events.push(crate::selftest::revalidate(self.profile, self.has_battery, pm.as_deref()));
+        }
+        // Adapter state: charge input every cycle while plugged in, events when the contract changes
+        if s.design_wh.is_some() && self.needs.adapter && (s.charging == Some(true) || self.adapter.is_some() || s.seq % 5 == 1) {
+            let adapter = source.adapter();
+            let (old_w, new_w) = (self.adapter.as_ref().and_then(|a| a.watts), adapter.as_ref().and_then(|a| a.watts));
+            if s.seq > 1 && (self.adapter.is_some() != adapter.is_some() || old_w != new_w) {
+                let kind = match (&self.adapter, &adapter) { (None, Some(_)) => "adapter_connected", (Some(_), None) => "adapter_disconnected", _ => "adapter_changed" };
+                e.events.push(format!("{{\"type\":\"{}\",\"from_w\":{},\"to_w\":{}}}", kind, json_opt(old_w), json_opt(new_w)));
+            }
+            self.adapter = adapter;
+        }
+        let run = self.needs.powermetrics && !e.quiet && (s.seq % 5 == 1 || quiet_changed);
+        match &mut self.slow {
+            // A run asked for now lands a sample or more later; one that finished during quiet
+            // hours is dropped
+            Some(slow) => {
+                if run { slow.request(); }
+                if let Some(pm) = slow.take().filter(|_| !e.quiet) { (self.pm, e.pm_fresh) = (pm, true); }
+            }
+            None => {
+                e.pm_fresh = run;
+                if run { self.pm = Arc::new(source.powermetrics(self.excluded)); }
+            }
+        }
+
+        if self.needs.derived { e.derived = crate::eval_derived(self.derived, &mut |k| source.key(k)); }
+        let (cpu_ms_per_j, wakeups_per_w) = self.pm.efficiency();
+        e.charge_input_w = self.adapter.as_ref().and_then(|a| a.input_w);
+        e.charge_overhead_w = self.adapter.as_ref().filter(|_| s.charging == Some(true)).and_then(|a| a.overhead_w(s.sys_power as f64));
+        e.adapter_w = self.adapter.as_ref().and_then(|a| a.watts);
+        e.thermal_level = source.thermal_level();
+        let cpu_avg = units.temp(temps::avg(&temps.cpu));
+        let pm = &self.pm;
+        let context = || format!("\"cpu_temp\":{},\"power_w\":{:.2},\"top_cpu\":[{}]", json_num(self.needs.temps.then_some(cpu_avg), 1), s.sys_power, pm.top_json);
+        e.events.extend(self.throttle.update(e.thermal_level, e.pm_fresh.then_some(pm.clusters.as_slice()), context));
+        if !self.alerts.is_empty() || self.trigger.is_some() {
+            let avg = |v: &[(f64, f64)]| Some(units.temp(temps::avg(v)));
+            let live = !e.quiet;
+            let mut metrics: Vec<(&str, Option<f64>)> = vec![
+                ("cpu_temp", Some(cpu_avg)), ("gpu_temp", avg(&temps.gpu)), ("gpu_temp_max", temps::max(&temps.gpu).map(|t| units.temp(t))), ("mem_temp", avg(&temps.mem)), ("ssd_temp", avg(&temps.ssd)),
+                ("bat_temp", s.design_wh.and(avg(&temps.bat))), ("power_w", Some(s.sys_power as f64)), ("bat_power_w", s.bat_power_w().map(|w| w as f64)),
+                ("mem_power_w", Some(s.mem_power as f64)), ("cpu_mw", live.then_some(pm.cpu_mw as f64)), ("gpu_mw", live.then_some(pm.gpu_mw as f64)), ("ane_mw", live.then_some(pm.ane_mw as f64)),
+                ("battery_pct", s.battery_pct.map(|p| p as f64)), ("mem_free_pct", Some(s.mem_free_pct as f64)), ("wakeups_per_sec", live.then_some(pm.wakeups)), ("cpu_ms_per_j", cpu_ms_per_j), ("wakeups_per_w", wakeups_per_w),
+                ("charge_input_w", e.charge_input_w), ("charge_overhead_w", e.charge_overhead_w), ("drain_pct_per_hr", e.drain_pct_per_hr), ("est_hours_remaining", e.est_hours_remaining),
+                ("thermal_pressure", e.thermal_level.map(|(_, rank)| rank as f64)),
+            ];
+            metrics.extend(e.derived.iter().map(|(n, v)| (n.as_str(), *v)));
+            e.events.extend(self.alerts.evaluate(&metrics));
+            if let Some(t) = &mut self.trigger {
+                let (recording, event) = t.update(s.mono, &metrics);
+                e.held = !recording;
+                e.events.extend(event);
+            }
+        }
+        if self.needs.dominant {
+            e.dominant = Some(crate::consumers::classify(&crate::consumers::Breakdown {
+                system_w: s.sys_power as f64,
+                display_w: crate::display::screen_power_w(self.backlight, s.bat_power_w(), s.sys_power_raw).map(|w| w as f64),
+                cpu_w: source.rail(Rail::Cpu),
+                gpu_w: source.rail(Rail::Gpu),
+                charging_loss_w: self.adapter.as_ref().and_then(|a| a.loss_w),
+            }));
+        }
+        e.interval_ms = match &mut self.adaptive {
+            Some(a) => a.next(s.charging == Some(false), s.sys_power as f64, temps::avg(&temps.cpu)).0,
+            None => self.interval_ms,
+        };
+        e
+    }
+}
+
+// ---- serialize ----
+
+// Wall time of each stage for `meter_self.stages_ms`
+#[derive(Default, Clone, Copy)]
+pub struct Timings {
+    pub sample: Duration,
+    pub enrich: Duration,
+    pub serialize: Duration,
+}
+
+pub struct Serializer<'a> {
+    rec: Record<'a>,
+    fields: &'a Fields,
+    units: Units,
+    detail: bool,
+    ids: crate::ulid::Generator,
+    // Fragments that never change, encoded once
+    host_json: String,
+    model_json: String,
+    tags: Option<&'a str>,
+    corrected: bool,
+    backlight: bool,
+    rails: Option<crate::power::Rails>,
+    rolling_power: crate::rolling::Series,
+    rolling_cpu_temp: crate::rolling::Series,
+    // With --meter-self: the meter and its fixed `"qos":...,"qos_priority":...` prefix
+    meter: Option<(crate::qos::SelfMeter, String)>,
+}
+
+pub struct SerializeOptions<'a> {
+    pub fields: &'a Fields,
+    pub units: Units,
+    pub detail: bool,
+    pub host: &'a str,
+    pub model: &'a str,
+    pub tags: Option<&'a str>,
+    // A wall-meter correction is applied, so power_raw_w is reported
+    pub corrected: bool,
+    pub backlight: bool,
+    pub rails: Option<crate::power::Rails>,
+    // --meter-self's QoS class and priority
+    pub meter: Option<(crate::qos::Qos, i32)>,
+}
+
+impl<'a> Serializer<'a> {
+    pub fn new(opts: SerializeOptions<'a>) -> Serializer<'a> {
+        Serializer {
+            rec: Record::new(opts.fields), fields: opts.fields, units: opts.units, detail: opts.detail, ids: crate::ulid::Generator::new(),
+            host_json: crate::json_str(opts.host), model_json: crate::json_str(opts.model), tags: opts.tags, corrected: opts.corrected, backlight: opts.backlight,
+            rails: opts.rails, rolling_power: Default::default(), rolling_cpu_temp: Default::default(),
+            meter: opts.meter.map(|(qos, priority)| (crate::qos::SelfMeter::new(), format!("\"qos\":\"{}\",\"qos_priority\":{}", qos.as_str(), priority.clamp(-15, 0)))),
+        }
+    }
+
+    // The sample's JSON line. `temps` is mutable only so the GPU sensors can be sorted in place.
+    pub fn serialize(&mut self, s: &Sample, temps: &mut Temps, pm: &Powermetrics, e: &Enrichment, stages: &Timings) -> &str {
+        let (units, rec) = (self.units, &mut self.rec);
+        let temp = |v: &[(f64, f64)]| units.temp(temps::avg(v));
+        let bat_power = s.bat_power_w();
+        let efficiency = s.design_wh.map(|wh| if s.sys_power > 0.1 { wh / s.sys_power } else { 99.0 });
+        let (cpu_ms_per_j, wakeups_per_w) = pm.efficiency();
+        let cpu_avg = temp(&temps.cpu);
+        rec.clear();
+        rec.add("id", format_args!("\"{}\"", self.ids.next()));
+        rec.add("ts", format_args!("\"{}\"", s.ts));
+        rec.add("seq", s.seq);
+        rec.add("host", &self.host_json);
+        rec.add("model", &self.model_json);
+        rec.add("interval_ms", e.interval_ms);
+        rec.add("cpu_temp", format_args!("{:.1}", cpu_avg));
+        rec.add("cpu_p_temp", json_num(temps::avg_opt(&temps.cpu_p).map(|t| units.temp(t)), 1));
+        rec.add("cpu_e_temp", json_num(temps::avg_opt(&temps.cpu_e).map(|t| units.temp(t)), 1));
+        rec.add("gpu_temp", format_args!("{:.1}", temp(&temps.gpu)));
+        rec.add("gpu_temp_max", json_num(temps::max(&temps.gpu).map(|t| units.temp(t)), 1));
+        if self.fields.wants("gpu_sensors") { rec.add("gpu_sensors", sample::gpu_sensors(&mut temps.gpu_sensors, units)); }
+        rec.add("hottest", sample::hottest(temps, units));
+        rec.add("mem_temp", format_args!("{:.1}", temp(&temps.mem)));
+        rec.add("ssd_temp", format_args!("{:.1}", temp(&temps.ssd)));
+        rec.add("bat_temp", json_num(s.design_wh.map(|_| temp(&temps.bat)), 1));
+        rec.add("power_w", format_args!("{:.2}", s.sys_power));
+        if self.corrected { rec.add("power_raw_w", format_args!("{:.2}", s.sys_power_raw)); }
+        rec.add("bat_power_w", json_num(bat_power.map(|w| w as f64), 2));
+        rec.add("mem_power_w", format_args!("{:.2}", s.mem_power));
+        rec.add("cpu_mw", pm.cpu_mw);
+        rec.add("gpu_mw", pm.gpu_mw);
+        rec.add("ane_mw", pm.ane_mw);
+        rec.add("battery_pct", json_opt(s.battery_pct));
+        rec.add("charging", json_opt(s.charging));
+        rec.add("mem_free_pct", s.mem_free_pct);
+        rec.add("efficiency_hrs", json_num(efficiency.map(|e| e as f64), 1));
+        rec.add("drain_pct_per_hr", json_num(e.drain_pct_per_hr, 1));
+        rec.add("est_hours_remaining", json_num(e.est_hours_remaining, 1));
+        rec.add("screen_w", json_num(crate::display::screen_power_w(self.backlight, bat_power, s.sys_power_raw).map(|w| w as f64), 2));
+        rec.add("wakeups_per_sec", format_args!("{:.0}", pm.wakeups));
+        rec.add("cpu_ms_per_j", json_num(cpu_ms_per_j, 1));
+        rec.add("wakeups_per_w", json_num(wakeups_per_w, 1));
+        rec.add("thermal_pressure", sample::Lazy(|f| match e.thermal_level { Some((name, _)) => write!(f, "\"{}\"", name), None => f.write_str("null") }));
+        rec.add("top_cpu", format_args!("[{}]", pm.top_json));
+        rec.add("high_wakeups", format_args!("[{}]", pm.high_wakeups_json));
+        rec.add("sources", sample::sources(s.at, pm));
+        if self.detail {
+            rec.add("calibration", sample::calibration(temps, units, (s.sys_power_raw, s.sys_power), crate::display::screen_power_raw_w(self.backlight, bat_power, s.sys_power_raw), s.design_wh.is_some()));
+        }
+        if let Some(r) = &self.rails { rec.add("rails", r.to_json()); }
+        if self.fields.wants("rolling") {
+            self.rolling_power.push(s.mono, s.sys_power as f64);
+            self.rolling_cpu_temp.push(s.mono, cpu_avg);
+            rec.add("rolling", format_args!("{{\"power_w\":{},\"cpu_temp\":{}}}", self.rolling_power.windows_json(s.mono, 2), self.rolling_cpu_temp.windows_json(s.mono, 1)));
+        }
+        if !e.derived.is_empty() { rec.add("derived", format!("{{{}}}", crate::derived::to_json(&e.derived))); }
+        rec.add("charge_input_w", json_num(e.charge_input_w, 2));
+        rec.add("charge_overhead_w", json_num(e.charge_overhead_w, 2));
+        rec.add("adapter_w", json_opt(e.adapter_w));
+        if let Some(d) = e.dominant { rec.add("dominant", crate::consumers::to_json(d)); }
+        if !e.events.is_empty() { rec.add("events", format_args!("[{}]", e.events.join(","))); }
+        if let Some((meter, prefix)) = &mut self.meter {
+            let (cpu_ms, rss_kb) = meter.sample();
+            let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+            rec.add("meter_self", format_args!("{{{},\"cpu_ms\":{:.2},\"max_rss_kb\":{},\"stages_ms\":{{\"sample\":{:.2},\"enrich\":{:.2},\"serialize\":{:.2}}}}}",
+                prefix, cpu_ms, rss_kb, ms(stages.sample), ms(stages.enrich), ms(stages.serialize)));
+        }
+        if units != Units::Celsius { rec.add("temp_unit", format_args!("\"{}\"", units.as_str())); }
+        if let Some(t) = self.tags { rec.add("tags", t); }
+        rec.finish()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::alerts::Rule;
+    use crate::mock::Mock;
+    use crate::profile::Family;
+
+    const STARTUP: &str = "{\"type\":\"startup\"}";
+
+    fn profile() -> Profile {
+        Profile::for_family(Family::M4, "Apple M4")
+    }
+
+    fn enricher<'a>(profile: &'a Profile, quiet_hours: Option<crate::quiet::Window>) -> Enricher<'a> {
+        let needs = Needs { temps: true, powermetrics: true, adapter: true, derived: false, dominant: true };
+        Enricher::new(EnrichOptions {
+            profile, excluded: &[], derived: &[], units: Units::Celsius, needs, has_battery: true, backlight: false, quiet_hours, adaptive: false, full_charge: false, powermetrics_fn: None,
+            interval_ms: 1000, revalidate_every: Duration::ZERO,
+        }, Engine::new(vec![Rule::parse("power_w > 0").unwrap()]), None, vec![STARTUP.to_string()])
+    }
+
+    fn serializer<'a>(fields: &'a Fields, units: Units) -> Serializer<'a> {
+        Serializer::new(SerializeOptions {
+            fields, units, detail: false, host: "test", model: crate::mock::MODEL, tags: None, corrected: false, backlight: false, rails: None, meter: None,
+        })
+    }
+
+    #[test]
+    fn sampler_reads_the_source_on_its_clocks() {
+        let profile = profile();
+        let (mut mock, mut temps) = (Mock::new(1, false), Temps::default());
+        let mut sampler = Sampler::new(&profile, Some(70.0), true, true);
+        for seq in 1..=3 {
+            let s = sampler.sample(&mut mock, seq, &mut temps);
+            assert_eq!((s.seq, s.mono, s.at), (seq, mock.now(), mock.wall_now()));
+            assert_eq!((s.design_wh, s.battery_pct, s.charging, s.mem_free_pct), (Some(70.0), Some(100), Some(false), 40));
+            assert!(s.sys_power > 0.0 && s.sys_power == s.sys_power_raw && s.bat_power_w().is_some());
+            assert_eq!(temps.sensors.len(), 12);
+        }
+    }
+
+    #[test]
+    fn enricher_runs_powermetrics_every_fifth_sample_and_raises_events_once() {
+        let profile = profile();
+        let (mut mock, mut temps) = (Mock::new(2, false), Temps::default());
+        let mut sampler = Sampler::new(&profile, Some(70.0), true, true);
+        let mut enricher = enricher(&profile, None);
+        let mut fresh = Vec::new();
+        for seq in 1..=6 {
+            let s = sampler.sample(&mut mock, seq, &mut temps);
+            let e = enricher.enrich(&mut mock, &s, &temps);
+            fresh.push(e.pm_fresh);
+            let alerts: Vec<&String> = e.events.iter().filter(|ev| ev.starts_with("{\"type\":\"alert\"")).collect();
+            if seq == 1 {
+                assert_eq!(e.events[0], STARTUP);
+                assert_eq!(alerts.len(), 1);
+                assert!(alerts[0].contains("\"state\":\"firing\",\"metric\":\"power_w\""), "{}", alerts[0]);
+            } else {
+                assert!(!e.events.iter().any(|ev| ev == STARTUP) && alerts.is_empty(), "{:?}", e.events);
+            }
+            assert_eq!(e.interval_ms, 1000);
+            assert!(e.dominant.is_some() && e.thermal_level.is_some() && !e.quiet);
+        }
+        assert_eq!(fresh, [true, false, false, false, false, true]);
+        assert_eq!(enricher.pm.top.len(), 5);
+        assert_eq!(enricher.pm.clusters.len(), 2);
+    }
+
+    #[test]
+    fn quiet_hours_suspend_powermetrics() {
+        let profile = profile();
+        let (mut mock, mut temps) = (Mock::new(3, false), Temps::default());
+        let mut sampler = Sampler::new(&profile, Some(70.0), true, true);
+        let s = sampler.sample(&mut mock, 1, &mut temps);
+        // The hour from this sample on
+        let window = format!("{:02}:{:02}-{:02}:{:02}", s.ts.hour, s.ts.minute, (s.ts.hour + 1) % 24, s.ts.minute);
+        let mut enricher = enricher(&profile, Some(crate::quiet::Window::parse(&window).unwrap()));
+        let e = enricher.enrich(&mut mock, &s, &temps);
+        assert!(e.quiet && !e.pm_fresh);
+        assert!(e.events.iter().any(|ev| ev.starts_with("{\"type\":\"quiet_hours\",\"state\":\"started\"")), "{:?}", e.events);
+        assert!(enricher.pm.top.is_empty() && enricher.pm.window.is_none());
+    }
+
+    #[test]
+    fn serializer_writes_what_the_stages_gathered() {
+        let profile = profile();
+        let (mut mock, mut temps) = (Mock::new(4, false), Temps::default());
+        let mut sampler = Sampler::new(&profile, Some(70.0), true, true);
+        let mut enricher = enricher(&profile, None);
+        let fields = Fields::parse(None).unwrap();
+        let mut full = serializer(&fields, Units::Celsius);
+        let s = sampler.sample(&mut mock, 1, &mut temps);
+        let e = enricher.enrich(&mut mock, &s, &temps);
+        let cpu_temp = format!("\"cpu_temp\":{:.1},", temps::avg(&temps.cpu));
+        let pm = Arc::clone(&enricher.pm);
+        let line = full.serialize(&s, &mut temps, &pm, &e, &Timings::default()).to_string();
+        assert!(line.starts_with("{\"id\":\"") && line.ends_with('}') && !line.contains('\n'), "{}", line);
+        for want in [
+            "\"seq\":1,", "\"host\":\"test\",", "\"model\":\"Mock14,1\",", "\"interval_ms\":1000,", &cpu_temp, "\"battery_pct\":100,", "\"charging\":false,", "\"mem_free_pct\":40,",
+            &format!("\"cpu_mw\":{},\"gpu_mw\":{},\"ane_mw\":0,", pm.cpu_mw, pm.gpu_mw),
+            &format!("\"top_cpu\":[{{\"name\":{},", sample::Quoted(&pm.top[0].0)),
+            "\"window_ms\":100.0,\"age_ms\":0}", &format!("\"events\":[{},{{\"type\":\"alert\"", STARTUP),
+        ] {
+            assert!(line.contains(want), "{} not in {}", want, line);
+        }
+        assert!(!line.contains("temp_unit") && !line.contains("\"calibration\""), "{}", line);
+
+        // Only the fields asked for, in the units asked for
+        let fields = Fields::parse(Some("cpu_temp,power_w,temp_unit")).unwrap();
+        let mut some = serializer(&fields, Units::Fahrenheit);
+        let s = sampler.sample(&mut mock, 2, &mut temps);
+        let e = enricher.enrich(&mut mock, &s, &temps);
+        let want = format!("{{\"cpu_temp\":{:.1},\"power_w\":{:.2},\"temp_unit\":\"fahrenheit\"}}", Units::Fahrenheit.temp(temps::avg(&temps.cpu)), s.sys_power);
+        assert_eq!(some.serialize(&s, &mut temps, &pm, &e, &Timings::default()), want);
+    }
+}
@@ -0,0 +1,90 @@
+// CPU/GPU power rail selection. Rail keys move between chips (GPU is PP7b on some machines,
+// PP2b on others), so each profile lists candidates in order of preference and the first one
+// that reads a plausible value and tracks the system rail is used.
+
+const SAMPLES: usize = 5;
+const SAMPLE_GAP_MS: u64 = 20;
+
+// Pearson correlation; None when either series is flat (nothing to compare).
+fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len().min(b.len()) as f64;
+    if n < 2.0 { return None; }
+    let (ma, mb) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+    let cov: f64 = a.iter().zip(b).map(|(x, y)| (x - ma) * (y - mb)).sum();
+    let (va, vb): (f64, f64) = (a.iter().map(|x| (x - ma).powi(2)).sum(), b.iter().map(|y| (y - mb).powi(2)).sum());
+    if va < 1e-9 || vb < 1e-9 { None } else { Some(cov / (va * vb).sqrt()) }
+}
+
+// Why a candidate was accepted or rejected, for `profile` output.
+pub struct Check {
+    pub key: &'static str,
+    pub ok: bool,
+    pub reason: String,
+}
+
+// Samples every candidate alongside `system_key` and returns the first that passes:
+// readable, finite, 0..system+5 W, and not anti-correlated with the system rail.
+pub fn select(candidates: &[&'static str], system_key: &str) -> (Option<&'static str>, Vec<Check>) {
+    let read = crate::value::read_f64;
+    let mut system = Vec::new();
+    let mut series: Vec<Vec<Option<f64>>> = vec![Vec::new(); candidates.len()];
+    for i in 0..SAMPLES {
+        if i > 0 { std::thread::sleep(std::time::Duration::from_millis(SAMPLE_GAP_MS)); }
+        system.push(read(system_key).unwrap_or(0.0));
+        for (c, s) in candidates.iter().zip(series.iter_mut()) { s.push(read(c)); }
+    }
+    let system_max = system.iter().cloned().fold(0.0, f64::max);
+
+    let mut checks = Vec::new();
+    let mut chosen = None;
+    for (key, s) in candidates.iter().zip(&series) {
+        let values: Vec<f64> = s.iter().flatten().copied().collect();
+        let reason = if values.len() < s.len() {
+            "not readable".to_string()
+        } else if values.iter().any(|v| *v < 0.0 || (system_max > 0.0 && *v > system_max + 5.0)) {
+            format!("out of range ({:.2} W vs system {:.2} W)", values.iter().cloned().fold(0.0, f64::max), system_max)
+        } else if let Some(r) = correlation(&values, &system).filter(|r| *r < -0.2) {
+            format!("anti-correlated with {} (r={:.2})", system_key, r)
+        } else {
+            String::new()
+        };
+        let ok = reason.is_empty();
+        if ok && chosen.is_none() { chosen = Some(*key); }
+        checks.push(Check { key, ok, reason: if ok { format!("{:.2} W", values.last().copied().unwrap_or(0.0)) } else { reason } });
+    }
+    (chosen, checks)
+}
+
+// The five `power-all` rails with key info resolved up front, so a 1 Hz stream pays one
+// driver call per rail per cycle instead of two. None when AppleSMC can't be opened directly.
+pub struct Rails {
+    raw: crate::smcraw::Raw,
+    rails: [(&'static str, Option<crate::smcraw::Resolved>); 5],
+}
+
+impl Rails {
+    pub fn new(profile: &crate::profile::Profile) -> Option<Rails> {
+        let raw = crate::smcraw::Raw::open().ok()?;
+        let keys = [profile.system_power_key.as_str(), "PHPS", profile.cpu_power_key.as_str(), profile.gpu_power_key.as_str(), profile.battery_power_key.as_str()];
+        let mut resolved = raw.resolve(&keys).into_iter();
+        Some(Rails { rails: ["system", "package", "cpu", "gpu", "battery"].map(|name| (name, resolved.next().flatten())), raw })
+    }
+
+    // (rail, watts) in system/package/cpu/gpu/battery order; None for unreadable rails.
+    pub fn read(&self) -> [(&'static str, Option<f64>); 5] {
+        self.rails.each_ref().map(|(name, r)| {
+            let value = r.as_ref().and_then(|r| self.raw.read_resolved(r, |b| crate::value::SmcValue::from_info(&r.info, b).as_f64()).ok().flatten());
+            (*name, value.and_then(|v| crate::diag::finite(v, name)))
+        })
+    }
+
+    // Read when called, written when formatted
+    pub fn to_json(&self) -> impl std::fmt::Display {
+        let read = self.read();
+        crate::sample::Lazy(move |f| {
+            f.write_str("{")?;
+            for (i, (n, v)) in read.iter().enumerate() { write!(f, "{}\"{}\":{}", if i > 0 { "," } else { "" }, n, crate::json_num(*v, 2))?; }
+            f.write_str("}")
+        })
+    }
+}
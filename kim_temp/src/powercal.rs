@@ -0,0 +1,154 @@
+// Wall-power calibration: `calibrate --reference wattmeter.csv` lines an external USB-C power
+// meter's log up with a recorded stream and fits `meter = scale × PSTR + offset`. The result is
+// stored per machine model and applied to `power_w` from then on; while one is active, samples
+// also carry `power_raw_w`, which is what later calibrations fit against.
+// Stored as `MODEL<TAB>scale<TAB>offset<TAB>pairs<TAB>rfc3339` lines. Without a line for this
+// model, a fleet `power-calibration` file (see fleet.rs) is used.
+
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Correction {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Correction {
+    pub fn apply(&self, raw_w: f64) -> f64 {
+        self.scale * raw_w + self.offset
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config/kim_temp/power-calibration"))
+}
+
+fn load_from(path: Option<PathBuf>, model: &str) -> Option<Correction> {
+    let text = std::fs::read_to_string(path?).ok()?;
+    let f: Vec<&str> = text.lines().find(|l| l.split('\t').next() == Some(model))?.split('\t').collect();
+    Some(Correction { scale: f.get(1)?.parse().ok()?, offset: f.get(2)?.parse().ok()? })
+}
+
+pub fn load(model: &str) -> Option<Correction> {
+    load_from(path(), model).or_else(|| load_from(crate::fleet::file("power-calibration"), model))
+}
+
+// Replaces the model's line (other machines sharing the file keep theirs).
+#[cfg(feature = "csv")]
+pub fn store(model: &str, c: &Correction, pairs: usize) -> Result<PathBuf, String> {
+    let path = path().ok_or("HOME is not set")?;
+    let mut lines: Vec<String> = std::fs::read_to_string(&path).unwrap_or_default().lines()
+        .filter(|l| l.split('\t').next() != Some(model)).map(str::to_string).collect();
+    lines.push(format!("{}\t{}\t{}\t{}\t{}", model, c.scale, c.offset, pairs, crate::clock::local(std::time::SystemTime::now()).rfc3339()));
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?; }
+    std::fs::write(&path, lines.join("\n") + "\n").map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn remove(model: &str) -> Result<(), String> {
+    let Some(path) = path() else { return Ok(()) };
+    let Ok(text) = std::fs::read_to_string(&path) else { return Ok(()) };
+    let kept: String = text.lines().filter(|l| l.split('\t').next() != Some(model)).map(|l| format!("{}\n", l)).collect();
+    std::fs::write(&path, kept).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+#[cfg(feature = "csv")]
+pub use import::fit;
+
+// Reading the two logs needs the CSV/JSON parsing the csv feature brings in.
+#[cfg(feature = "csv")]
+mod import {
+    use super::Correction;
+    use crate::csvlog::parse_row;
+
+    pub struct Fit {
+        pub correction: Correction,
+        pub pairs: usize,
+        pub r2: f64,
+        // Mean absolute error (W) against the meter before and after correction
+        pub mae_before: f64,
+        pub mae_after: f64,
+    }
+
+    // Header cell matching: "Power (W)" → "power (w)"
+    fn find(columns: &[String], names: &[&str]) -> Option<usize> {
+        columns.iter().position(|c| names.iter().any(|n| c == n || c.starts_with(&format!("{}(", n)) || c.starts_with(&format!("{} ", n))))
+    }
+
+    // Unix seconds from an ISO time or a plain number (seconds, or milliseconds when that large).
+    fn time(cell: &str) -> Option<f64> {
+        match cell.trim().parse::<f64>() {
+            Ok(v) if v > 1e11 => Some(v / 1000.0),
+            Ok(v) => Some(v),
+            Err(_) => crate::clock::parse(cell),
+        }
+    }
+
+    // Meter log: a time column and either a power column or voltage and current columns.
+    fn reference(path: &str) -> Result<Vec<(f64, f64)>, String> {
+        let text = std::fs::read_to_string(crate::config::expand_home(path)).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let columns: Vec<String> = parse_row(lines.next().unwrap_or("")).iter().map(|c| c.trim().to_lowercase()).collect();
+        let t = find(&columns, &["time", "timestamp", "ts", "date", "datetime"]).ok_or_else(|| format!("{}: no time column in header {:?}", path, columns))?;
+        let power = find(&columns, &["power", "watts", "w", "p"]);
+        let vi = find(&columns, &["voltage", "volts", "v", "vbus"]).zip(find(&columns, &["current", "amps", "a", "i", "ibus"]));
+        if power.is_none() && vi.is_none() { return Err(format!("{}: no power (or voltage and current) column in header {:?}", path, columns)); }
+        Ok(lines.filter_map(|l| {
+            let row = parse_row(l);
+            let num = |i: usize| row.get(i)?.trim().parse::<f64>().ok();
+            let w = match (power, vi) { (Some(p), _) => num(p)?, (None, Some((v, a))) => num(v)? * num(a)?, _ => return None };
+            Some((time(row.get(t)?)?, w))
+        }).collect())
+    }
+
+    // Tool log: a `stream --log-csv` file or saved JSON lines; `power_raw_w` wins over `power_w`.
+    fn recorded(path: &str) -> Result<Vec<(f64, f64)>, String> {
+        let text = std::fs::read_to_string(crate::config::expand_home(path)).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        if text.trim_start().starts_with('{') {
+            return Ok(text.lines().filter_map(|l| {
+                let v: serde_json::Value = serde_json::from_str(l).ok()?;
+                let w = v.get("power_raw_w").or_else(|| v.get("power_w"))?.as_f64()?;
+                Some((crate::clock::parse(v.get("ts")?.as_str()?)?, w))
+            }).collect());
+        }
+        let mut lines = text.lines();
+        let columns = parse_row(lines.next().unwrap_or(""));
+        let t = columns.iter().position(|c| c == "ts" || c == "timestamp").ok_or_else(|| format!("{}: no ts/timestamp column", path))?;
+        let w = columns.iter().position(|c| c == "power_raw_w").or_else(|| columns.iter().position(|c| c == "power_w")).ok_or_else(|| format!("{}: no power_w column", path))?;
+        Ok(lines.filter_map(|l| {
+            let row = parse_row(l);
+            Some((crate::clock::parse(row.get(t)?)?, row.get(w)?.parse().ok()?))
+        }).collect())
+    }
+
+    // Pairs each recorded sample with the mean meter reading within ±window/2 s of it (meter
+    // times shifted by `lag` s first), then fits by least squares.
+    pub fn fit(reference_path: &str, recorded_path: &str, lag: f64, window: f64) -> Result<Fit, String> {
+        let mut meter = reference(reference_path)?;
+        meter.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let samples = recorded(recorded_path)?;
+        let pairs: Vec<(f64, f64)> = samples.iter().filter_map(|(t, pstr)| {
+            let start = meter.partition_point(|(mt, _)| mt + lag < t - window / 2.0);
+            let near: Vec<f64> = meter[start..].iter().take_while(|(mt, _)| mt + lag <= t + window / 2.0).map(|(_, w)| *w).collect();
+            (!near.is_empty()).then(|| (*pstr, near.iter().sum::<f64>() / near.len() as f64))
+        }).collect();
+        if pairs.len() < 10 {
+            return Err(format!("only {} of {} samples overlap the meter log (need 10); check both clocks, or shift with --lag SECONDS", pairs.len(), samples.len()));
+        }
+        let n = pairs.len() as f64;
+        let (mx, my) = (pairs.iter().map(|p| p.0).sum::<f64>() / n, pairs.iter().map(|p| p.1).sum::<f64>() / n);
+        let sxx: f64 = pairs.iter().map(|(x, _)| (x - mx).powi(2)).sum();
+        let sxy: f64 = pairs.iter().map(|(x, y)| (x - mx) * (y - my)).sum();
+        let syy: f64 = pairs.iter().map(|(_, y)| (y - my).powi(2)).sum();
+        // Flat load: only an offset can be estimated
+        let correction = if sxx < 1e-9 { Correction { scale: 1.0, offset: my - mx } } else { Correction { scale: sxy / sxx, offset: my - sxy / sxx * mx } };
+        let mae = |f: &dyn Fn(f64) -> f64| pairs.iter().map(|(x, y)| (f(*x) - y).abs()).sum::<f64>() / n;
+        Ok(Fit {
+            correction,
+            pairs: pairs.len(),
+            r2: if sxx < 1e-9 || syy < 1e-9 { 0.0 } else { sxy * sxy / (sxx * syy) },
+            mae_before: mae(&|x| x),
+            mae_after: mae(&|x| correction.apply(x)),
+        })
+    }
+}
@@ -0,0 +1,61 @@
+// `--redact-processes hash|label` (or `redact_processes` in the config): process names are
+// replaced as powermetrics output is parsed, so nothing downstream ever sees them: `top_cpu`,
+// `high_wakeups`, the session summary, CSV, templates, webhooks and alert events alike.
+//   hash   proc-1a2b3c4d, FNV-1a of the name keyed with --redact-salt: the same app gets the
+//          same hash on every machine sharing the salt, so fleets can still be aggregated
+//   label  process-1, process-2, ... numbered in order of first appearance in this run
+// Without a salt, hashes of common app names can be recomputed by anyone, so deployments under a
+// privacy policy should set one (and keep it off the central server).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redact { Hash, Label }
+
+impl Redact {
+    pub fn parse(s: &str) -> Result<Redact, String> {
+        match s {
+            "hash" => Ok(Redact::Hash),
+            "label" => Ok(Redact::Label),
+            _ => Err(format!("unknown --redact-processes {} (hash|label)", s)),
+        }
+    }
+}
+
+struct State {
+    mode: Redact,
+    salt: String,
+    labels: Mutex<HashMap<String, usize>>,
+}
+
+static STATE: OnceLock<State> = OnceLock::new();
+
+// Turns redaction on for the rest of the process (the first call wins).
+pub fn enable(mode: Redact, salt: String) {
+    let _ = STATE.set(State { mode, salt, labels: Mutex::default() });
+}
+
+pub fn enabled() -> bool {
+    STATE.get().is_some()
+}
+
+// FNV-1a over salt and name, shortened to 8 hex digits.
+pub fn hash(name: &str, salt: &str) -> String {
+    let h = salt.bytes().chain([0]).chain(name.bytes()).fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    format!("proc-{:08x}", h as u32)
+}
+
+// The name to show for a process.
+pub fn process(name: &str) -> Cow<'_, str> {
+    let Some(state) = STATE.get() else { return Cow::Borrowed(name) };
+    match state.mode {
+        Redact::Hash => Cow::Owned(hash(name, &state.salt)),
+        Redact::Label => {
+            let mut labels = state.labels.lock().unwrap_or_else(|e| e.into_inner());
+            let next = labels.len() + 1;
+            Cow::Owned(format!("process-{}", labels.entry(name.to_string()).or_insert(next)))
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Chip detection and per-model SMC key profiles.
+// Key naming differs between M1/M2/M3/M4, so temperature classification and power rail
+// selection are driven by the detected chip instead of one global list of prefixes.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category { CpuP, CpuE, Gpu, Mem, Ssd, Battery }
+
+impl Category {
+    pub fn is_cpu(self) -> bool { matches!(self, Category::CpuP | Category::CpuE) }
+    // The category as `hottest` names it: the prefix of its `*_temp` field, both core types "cpu"
+    pub fn group(self) -> &'static str {
+        match self { Category::CpuP | Category::CpuE => "cpu", Category::Gpu => "gpu", Category::Mem => "mem", Category::Ssd => "ssd", Category::Battery => "bat" }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family { M1, M2, M3, M4, Unknown }
+
+impl Family {
+    pub fn as_str(self) -> &'static str {
+        match self { Family::M1 => "M1", Family::M2 => "M2", Family::M3 => "M3", Family::M4 => "M4", Family::Unknown => "unknown" }
+    }
+}
+
+// A rule matches a full 4-char key exactly, or a shorter string as a prefix. First match wins.
+type Rule = (&'static str, Category);
+
+const COMMON_RULES: &[Rule] = &[
+    ("TM", Category::Mem), ("Tm", Category::Mem),
+    ("TS", Category::Ssd),
+    ("TB", Category::Battery),
+];
+
+const M1_RULES: &[Rule] = &[
+    ("Tp09", Category::CpuE), ("Tp0T", Category::CpuE),
+    ("Tp", Category::CpuP), ("Tg", Category::Gpu),
+];
+
+const M2_RULES: &[Rule] = &[
+    ("Tp1h", Category::CpuE), ("Tp1t", Category::CpuE), ("Tp1p", Category::CpuE), ("Tp1l", Category::CpuE),
+    ("Tp", Category::CpuP), ("Tg", Category::Gpu),
+];
+
+const M3_RULES: &[Rule] = &[
+    ("Te", Category::CpuE),
+    ("Tf1", Category::Gpu), ("Tf2", Category::Gpu), ("Tf", Category::CpuP),
+    ("Tp", Category::CpuP), ("Tg", Category::Gpu),
+];
+
+const M4_RULES: &[Rule] = &[
+    ("Te", Category::CpuE), ("Tp", Category::CpuP), ("Tg", Category::Gpu),
+];
+
+// Fallback for chips we don't know: the historical prefix heuristics.
+const GENERIC_RULES: &[Rule] = &[
+    ("Te", Category::CpuE), ("Tp", Category::CpuP), ("Tc", Category::CpuP), ("TC", Category::CpuP),
+    ("Tg", Category::Gpu), ("TG", Category::Gpu),
+];
+
+// Sensor quality: die sensors read the silicon directly, proximity sensors sit next to it and lag.
+// Keys without an entry weigh 1.0, so a family with no quality data falls back to a plain mean.
+type Weight = (&'static str, f64);
+
+const COMMON_WEIGHTS: &[Weight] = &[
+    ("Tm0p", 0.5), ("Tm1p", 0.5), ("TMVR", 0.3),
+    ("TS0P", 0.5), ("TB0T", 0.5),
+];
+
+const M1_WEIGHTS: &[Weight] = &[("Tp0T", 0.6), ("Tg0T", 0.6)];
+const M2_WEIGHTS: &[Weight] = &[("Tp1t", 0.6), ("Tg0t", 0.6)];
+const M3_WEIGHTS: &[Weight] = &[("Tf2", 0.8), ("Tp0", 0.6)];
+const M4_WEIGHTS: &[Weight] = &[("Tp0", 0.6), ("Tg0", 0.6)];
+
+// Intel-style proximity keys that the generic rules also accept
+const GENERIC_WEIGHTS: &[Weight] = &[("TC", 0.5), ("TG", 0.5)];
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub chip: String,
+    // Hardware model identifier, e.g. "Mac14,2"
+    pub model: String,
+    pub family: Family,
+    pub p_cores: u32,
+    pub e_cores: u32,
+    pub rules: &'static [Rule],
+    pub weights: &'static [Weight],
+    pub system_power_key: String,
+    pub battery_power_key: String,
+    pub cpu_power_key: String,
+    pub gpu_power_key: String,
+    // Ordered fallbacks for the CPU/GPU rails, validated by power::select
+    pub cpu_power_keys: &'static [&'static str],
+    pub gpu_power_keys: &'static [&'static str],
+    // Wall-meter correction for the system rail, from `calibrate`
+    pub power_correction: Option<crate::powercal::Correction>,
+    // Keys marked by hand in `explore`; they beat the rules above and rail validation
+    pub roles: Vec<(String, crate::roles::Role)>,
+}
+
+const CPU_POWER_KEYS: &[&str] = &["PP0b", "PZD1", "PC0C", "PCPC"];
+const M4_CPU_POWER_KEYS: &[&str] = &["PZD1", "PP0b", "PC0C", "PCPC"];
+const GPU_POWER_KEYS: &[&str] = &["PP7b", "PP2b", "PG0C", "PGTR"];
+const M4_GPU_POWER_KEYS: &[&str] = &["PP2b", "PP7b", "PG0C", "PGTR"];
+
+impl Profile {
+    pub fn for_family(family: Family, chip: &str) -> Profile {
+        let (rules, weights, cpu_power_keys, gpu_power_keys) = match family {
+            Family::M1 => (M1_RULES, M1_WEIGHTS, CPU_POWER_KEYS, GPU_POWER_KEYS),
+            Family::M2 => (M2_RULES, M2_WEIGHTS, CPU_POWER_KEYS, GPU_POWER_KEYS),
+            Family::M3 => (M3_RULES, M3_WEIGHTS, CPU_POWER_KEYS, GPU_POWER_KEYS),
+            Family::M4 => (M4_RULES, M4_WEIGHTS, M4_CPU_POWER_KEYS, M4_GPU_POWER_KEYS),
+            Family::Unknown => (GENERIC_RULES, GENERIC_WEIGHTS, CPU_POWER_KEYS, GPU_POWER_KEYS),
+        };
+        Profile {
+            chip: chip.to_string(), model: String::new(), family, p_cores: 0, e_cores: 0, rules, weights,
+            system_power_key: "PSTR".into(), battery_power_key: "PPBR".into(),
+            cpu_power_key: cpu_power_keys[0].into(), gpu_power_key: gpu_power_keys[0].into(), cpu_power_keys, gpu_power_keys,
+            power_correction: None, roles: Vec::new(),
+        }
+    }
+
+    // System power as measured at the wall when a calibration is stored, else the rail as read.
+    pub fn system_power(&self, raw_w: f32) -> f32 {
+        self.power_correction.map_or(raw_w, |c| c.apply(raw_w as f64) as f32)
+    }
+
+    pub fn classify(&self, key: &str) -> Option<Category> {
+        use crate::roles::Role;
+        match self.roles.iter().find(|(k, _)| k == key).map(|(_, r)| *r) {
+            Some(Role::Temp(c)) => return Some(c),
+            Some(Role::Ignore) => return None,
+            _ => {}
+        }
+        self.rules.iter().chain(COMMON_RULES.iter())
+            .find(|(pat, _)| if pat.len() == 4 { key == *pat } else { key.starts_with(pat) })
+            .map(|(_, cat)| *cat)
+    }
+
+    // Points the rails at the keys marked with a power role.
+    pub fn apply_roles(&mut self) {
+        use crate::roles::Role;
+        for (key, role) in &self.roles {
+            match role {
+                Role::SystemPower => self.system_power_key = key.clone(),
+                Role::BatteryPower => self.battery_power_key = key.clone(),
+                Role::CpuPower => self.cpu_power_key = key.clone(),
+                Role::GpuPower => self.gpu_power_key = key.clone(),
+                Role::Temp(_) | Role::Ignore => {}
+            }
+        }
+    }
+
+    // Same matching as `classify`; 1.0 for keys with no quality entry.
+    pub fn weight(&self, key: &str) -> f64 {
+        self.weights.iter().chain(COMMON_WEIGHTS.iter())
+            .find(|(pat, _)| if pat.len() == 4 { key == *pat } else { key.starts_with(pat) })
+            .map_or(1.0, |(_, w)| *w)
+    }
+}
+
+pub fn sysctl(name: &str) -> Option<String> {
+    crate::subprocess::stdout(std::process::Command::new("sysctl").args(["-n", name]))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+pub fn family_from_brand(brand: &str) -> Family {
+    let model = brand.trim_start_matches("Apple ").split_whitespace().next().unwrap_or("");
+    match model {
+        "M1" => Family::M1,
+        "M2" => Family::M2,
+        "M3" => Family::M3,
+        "M4" => Family::M4,
+        _ => Family::Unknown,
+    }
+}
+
+// Detects the chip via `machdep.cpu.brand_string` and the `hw.perflevel*` core counts.
+pub fn detect() -> Profile {
+    let brand = sysctl("machdep.cpu.brand_string").unwrap_or_default();
+    let mut profile = Profile::for_family(family_from_brand(&brand), &brand);
+    profile.model = sysctl("hw.model").unwrap_or_default();
+    profile.p_cores = sysctl("hw.perflevel0.physicalcpu").and_then(|s| s.parse().ok()).unwrap_or(0);
+    profile.e_cores = sysctl("hw.perflevel1.physicalcpu").and_then(|s| s.parse().ok()).unwrap_or(0);
+    profile.power_correction = crate::powercal::load(&profile.model);
+    profile.roles = crate::roles::load(&profile.model);
+    profile.apply_roles();
+    profile
+}
+
+// GPU core count from the AGX accelerator entry; the number that separates bins of one chip
+// (e.g. M3 Pro with 14 vs 18 GPU cores). Not part of detect() because ioreg is slow.
+pub fn gpu_cores() -> Option<u32> {
+    let out = crate::subprocess::stdout(std::process::Command::new("ioreg").args(["-r", "-c", "AGXAccelerator", "-d", "1"]))?;
+    out.lines().find(|l| l.contains("\"gpu-core-count\""))?.split('=').nth(1)?.trim().parse().ok()
+}
+
+impl Profile {
+    // `chip` metadata: core counts, the bin they imply ("12-core CPU / 16-core GPU"), and
+    // hw.cpufamily, which identifies the die generation across marketing names.
+    pub fn chip_json(&self, gpu_cores: Option<u32>) -> String {
+        let cpu = self.p_cores + self.e_cores;
+        let bin = match gpu_cores { Some(g) => format!("{}-core CPU / {}-core GPU", cpu, g), None => format!("{}-core CPU", cpu) };
+        let cpufamily = sysctl("hw.cpufamily").and_then(|s| s.parse::<i64>().ok()).map(|f| format!("\"0x{:08x}\"", f as u32)).unwrap_or_else(|| "null".to_string());
+        format!("{{\"name\":{},\"p_cores\":{},\"e_cores\":{},\"gpu_cores\":{},\"bin\":{},\"cpufamily\":{}}}",
+            crate::json_str(&self.chip), self.p_cores, self.e_cores, crate::json_opt(gpu_cores), crate::json_str(&bin), cpufamily)
+    }
+}
@@ -0,0 +1,91 @@
+// Scheduling class for the sampling loop, so the monitor never competes with user work,
+// plus the self-metering numbers reported by `--meter-self`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Qos { Background, Utility, Default, UserInitiated }
+
+impl Qos {
+    pub fn parse(s: &str) -> Option<Qos> {
+        match s {
+            "background" => Some(Qos::Background),
+            "utility" => Some(Qos::Utility),
+            "default" => Some(Qos::Default),
+            "user-initiated" => Some(Qos::UserInitiated),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Qos::Background => "background",
+            Qos::Utility => "utility",
+            Qos::Default => "default",
+            Qos::UserInitiated => "user-initiated",
+        }
+    }
+
+    // qos_class_t values from <sys/qos.h>
+    fn class(self) -> u32 {
+        match self {
+            Qos::Background => 0x09,
+            Qos::Utility => 0x11,
+            Qos::Default => 0x15,
+            Qos::UserInitiated => 0x19,
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn pthread_set_qos_class_self_np(qos_class: u32, relative_priority: i32) -> i32;
+}
+
+// QoS classes are Darwin's; elsewhere the thread stays where it is and apply() says so.
+#[cfg(not(target_os = "macos"))]
+unsafe fn pthread_set_qos_class_self_np(_qos_class: u32, _relative_priority: i32) -> i32 {
+    libc::ENOTSUP
+}
+
+// Moves the calling thread into `qos`. `priority` is relative within the class (0 to -15).
+pub fn apply(qos: Qos, priority: i32) -> bool {
+    let priority = priority.clamp(-15, 0);
+    unsafe { pthread_set_qos_class_self_np(qos.class(), priority) == 0 }
+}
+
+// Tracks this process's own CPU time between samples.
+pub struct SelfMeter {
+    last_cpu_us: i64,
+}
+
+fn rusage_self() -> Option<libc::rusage> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } == 0 { Some(usage) } else { None }
+}
+
+// timeval/rusage field widths differ between macOS and other platforms, hence the casts.
+#[allow(clippy::unnecessary_cast)]
+fn cpu_us(u: &libc::rusage) -> i64 {
+    (u.ru_utime.tv_sec as i64 + u.ru_stime.tv_sec as i64) * 1_000_000 + u.ru_utime.tv_usec as i64 + u.ru_stime.tv_usec as i64
+}
+
+impl Default for SelfMeter {
+    fn default() -> Self { SelfMeter::new() }
+}
+
+impl SelfMeter {
+    pub fn new() -> SelfMeter {
+        SelfMeter { last_cpu_us: rusage_self().map(|u| cpu_us(&u)).unwrap_or(0) }
+    }
+
+    // Returns (CPU ms used since the previous call, peak RSS in KB).
+    #[allow(clippy::unnecessary_cast)]
+    pub fn sample(&mut self) -> (f64, i64) {
+        let Some(usage) = rusage_self() else { return (0.0, 0) };
+        let now = cpu_us(&usage);
+        let delta_ms = (now - self.last_cpu_us) as f64 / 1000.0;
+        self.last_cpu_us = now;
+        // ru_maxrss is bytes on macOS, KB elsewhere
+        let rss_kb = if cfg!(target_os = "macos") { usage.ru_maxrss as i64 / 1024 } else { usage.ru_maxrss as i64 };
+        (delta_ms, rss_kb)
+    }
+}
@@ -0,0 +1,43 @@
+// Quiet hours: a daily local-time window (e.g. 23:00-07:00) during which `stream` keeps logging the
+// cheap SMC readings but stops running powermetrics and posting alert notifications, so an
+// overnight log exists without the monitor itself waking the machine. Set with `[quiet_hours]` in
+// the config or `--quiet-hours HH:MM-HH:MM`; a window that ends before it starts spans midnight.
+
+use crate::clock::LocalTime;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    // Minutes since local midnight
+    start: u32,
+    end: u32,
+}
+
+fn minutes(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
+
+impl Window {
+    pub fn new(start: &str, end: &str) -> Result<Window, String> {
+        let parse = |s: &str| minutes(s).ok_or_else(|| format!("invalid quiet hours time {:?} (expected HH:MM)", s));
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end { return Err("quiet hours start and end are the same time".to_string()); }
+        Ok(Window { start, end })
+    }
+
+    // `HH:MM-HH:MM`, as --quiet-hours takes it.
+    pub fn parse(spec: &str) -> Result<Window, String> {
+        let (start, end) = spec.split_once('-').ok_or_else(|| format!("invalid --quiet-hours {:?} (expected e.g. 23:00-07:00)", spec))?;
+        Window::new(start, end)
+    }
+
+    pub fn contains(&self, t: &LocalTime) -> bool {
+        let now = t.hour * 60 + t.minute;
+        if self.start < self.end { (self.start..self.end).contains(&now) } else { now >= self.start || now < self.end }
+    }
+
+    pub fn to_json(self) -> String {
+        format!("\"{:02}:{:02}-{:02}:{:02}\"", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
@@ -0,0 +1,149 @@
+// One `json` sample, collected first and formatted afterwards: `json` does both in one go,
+// `serve` collects once a second and formats per client request with that client's --fields
+// and --units. Temperatures stay in Celsius until formatting; everything that needs the SMC,
+// a subprocess or a notification lookup happens in `collect`.
+
+use crate::config::Units;
+use crate::profile::Profile;
+use crate::sample::{self, Fields, Powermetrics, Record};
+use crate::temps::Sensor;
+use crate::temps::{self, Temps};
+use crate::{json_num, json_opt};
+use std::time::{Duration, SystemTime};
+
+pub struct Reading {
+    smc_at: SystemTime,
+    pub temps: Temps,
+    // The system rail as read (None when the key didn't answer) and after any wall-meter correction
+    system_power: Option<f32>,
+    sys_power_raw: f32,
+    sys_power: f32,
+    corrected: bool,
+    bat_power: f32,
+    mem_power: f32,
+    pm: Powermetrics,
+    // Design capacity (Wh) when the Mac has a battery
+    design_wh: Option<f32>,
+    battery_pct: Option<i32>,
+    charging: Option<bool>,
+    mem_free_pct: i32,
+    backlight: bool,
+    thermal_pressure: Option<&'static str>,
+    chip: Option<String>,
+    dominant: Option<[Option<&'static str>; 2]>,
+    derived: Vec<(String, Option<f64>)>,
+    // `--detail` rails as JSON arrays
+    volts_amps: Option<(String, String)>,
+}
+
+// Reads everything `fields` (and `detail`) needs. `pm` is passed in because its caller decides
+// how often powermetrics runs.
+pub fn collect(profile: &Profile, sensors: &[Sensor], fields: &Fields, detail: bool, pm: Powermetrics, derived: &[crate::derived::Derived]) -> Reading {
+    let needs = |names: &[&str]| fields.wants_any(names);
+    let temps = Temps::collect(sensors, None);
+    let smc_at = SystemTime::now();
+    let system_power = crate::value::read_f64(&profile.system_power_key).map(|w| w as f32);
+    let sys_power_raw = system_power.unwrap_or(0.0);
+    let sys_power = profile.system_power(sys_power_raw);
+    let bat_power = crate::read_w(&profile.battery_power_key);
+    let mem_power = crate::read_w("PHPM");
+    let battery = if needs(sample::BATTERY_FIELDS) { crate::battery::detect() } else { None };
+    let backlight = (fields.wants_any(&["screen_w", "dominant"]) || (detail && fields.wants("calibration"))) && crate::display::has_backlight();
+    let (battery_pct, charging) = match battery { Some(_) if needs(sample::PMSET_FIELDS) => { let (p, c) = crate::battery::pmset_status(); (Some(p), Some(c)) } _ => (None, None) };
+    let bat_power_opt = battery.as_ref().map(|_| bat_power);
+    let dominant = fields.wants("dominant").then(|| crate::consumers::classify(&crate::consumers::Breakdown {
+        system_w: sys_power as f64,
+        display_w: crate::display::screen_power_w(backlight, bat_power_opt, sys_power_raw).map(|w| w as f64),
+        cpu_w: crate::value::read_f64(&profile.cpu_power_key),
+        gpu_w: crate::value::read_f64(&profile.gpu_power_key),
+        charging_loss_w: battery.as_ref().and_then(|_| crate::battery::adapter()).and_then(|a| a.loss_w),
+    }));
+    let volts_amps = (detail && needs(&["volts", "amps"])).then(|| {
+        let raw = crate::smcraw::Raw::open().ok();
+        let json = |q| raw.as_ref().map(|raw| crate::electrical::to_json(&crate::electrical::known(raw, q), q)).unwrap_or_else(|| "null".to_string());
+        (json(crate::electrical::Quantity::Volts), json(crate::electrical::Quantity::Amps))
+    });
+    Reading {
+        smc_at, temps, system_power, sys_power_raw, sys_power, corrected: profile.power_correction.is_some(), bat_power, mem_power, pm,
+        design_wh: battery.map(|b| b.design_wh), battery_pct, charging,
+        mem_free_pct: if fields.wants("mem_free_pct") { sample::mem_free_pct() } else { 0 },
+        backlight,
+        thermal_pressure: if fields.wants("thermal_pressure") { crate::thermal::Pressure::open().and_then(|p| p.level()).map(|(name, _)| name) } else { None },
+        chip: fields.wants("chip").then(|| profile.chip_json(crate::profile::gpu_cores())),
+        dominant,
+        derived: if fields.wants("derived") { derived.iter().map(|m| (m.name.clone(), m.eval(&mut crate::value::read_f64))).collect() } else { Vec::new() },
+        volts_amps,
+    }
+}
+
+impl Reading {
+    // Whether the Mac has a battery (a `battery` request needs one).
+    pub fn has_battery(&self) -> bool {
+        self.design_wh.is_some()
+    }
+
+    // Battery percentage and whether it's charging, when pmset was asked.
+    pub fn battery(&self) -> Option<(i32, bool)> {
+        self.battery_pct.zip(self.charging)
+    }
+
+    // `power`: the corrected system rail, None when it couldn't be read.
+    pub fn power_w(&self) -> Option<f32> {
+        self.system_power.map(|_| self.sys_power)
+    }
+
+    // The `json` line. `tags` is the already-encoded tags object; `cache_age` is set when `serve`
+    // answers from its cache.
+    pub fn json(&mut self, fields: &Fields, units: Units, detail: bool, tags: Option<&str>, cache_age: Option<Duration>) -> String {
+        let temps = &mut self.temps;
+        let temp = |v: &[(f64, f64)]| units.temp(temps::avg(v));
+        let bat_power = self.design_wh.map(|_| self.bat_power);
+        let efficiency = self.design_wh.map(|wh| if self.sys_power > 0.1 { wh / self.sys_power } else { 99.0 });
+        let mut rec = Record::new(fields);
+        if let Some(chip) = &self.chip { rec.add("chip", chip); }
+        rec.add("cpu_temp", format_args!("{:.1}", temp(&temps.cpu)));
+        rec.add("cpu_p_temp", json_num(temps::avg_opt(&temps.cpu_p).map(|t| units.temp(t)), 1));
+        rec.add("cpu_e_temp", json_num(temps::avg_opt(&temps.cpu_e).map(|t| units.temp(t)), 1));
+        rec.add("gpu_temp", format_args!("{:.1}", temp(&temps.gpu)));
+        rec.add("gpu_temp_max", json_num(temps::max(&temps.gpu).map(|t| units.temp(t)), 1));
+        if fields.wants("gpu_sensors") { rec.add("gpu_sensors", sample::gpu_sensors(&mut temps.gpu_sensors, units)); }
+        rec.add("hottest", sample::hottest(temps, units));
+        rec.add("mem_temp", format_args!("{:.1}", temp(&temps.mem)));
+        rec.add("ssd_temp", format_args!("{:.1}", temp(&temps.ssd)));
+        rec.add("bat_temp", json_num(self.design_wh.map(|_| temp(&temps.bat)), 1));
+        rec.add("power_w", format_args!("{:.2}", self.sys_power));
+        if self.corrected { rec.add("power_raw_w", format_args!("{:.2}", self.sys_power_raw)); }
+        rec.add("bat_power_w", json_num(bat_power.map(|w| w as f64), 2));
+        rec.add("mem_power_w", format_args!("{:.2}", self.mem_power));
+        rec.add("cpu_mw", self.pm.cpu_mw);
+        rec.add("gpu_mw", self.pm.gpu_mw);
+        rec.add("ane_mw", self.pm.ane_mw);
+        rec.add("battery_pct", json_opt(self.battery_pct));
+        rec.add("charging", json_opt(self.charging));
+        rec.add("mem_free_pct", self.mem_free_pct);
+        rec.add("efficiency_hrs", json_num(efficiency.map(|e| e as f64), 1));
+        rec.add("screen_w", json_num(crate::display::screen_power_w(self.backlight, bat_power, self.sys_power_raw).map(|w| w as f64), 2));
+        rec.add("wakeups_per_sec", format_args!("{:.0}", self.pm.wakeups));
+        let (cpu_ms_per_j, wakeups_per_w) = self.pm.efficiency();
+        rec.add("cpu_ms_per_j", json_num(cpu_ms_per_j, 1));
+        rec.add("wakeups_per_w", json_num(wakeups_per_w, 1));
+        if fields.wants("thermal_pressure") { rec.add("thermal_pressure", json_opt(self.thermal_pressure.map(|name| format!("\"{}\"", name)))); }
+        rec.add("top_cpu", format_args!("[{}]", self.pm.top_json));
+        rec.add("high_wakeups", format_args!("[{}]", self.pm.high_wakeups_json));
+        if let Some(d) = self.dominant { rec.add("dominant", crate::consumers::to_json(d)); }
+        rec.add("sources", sample::sources(self.smc_at, &self.pm));
+        if let Some(age) = cache_age { rec.add("cache_age_ms", age.as_millis()); }
+        if detail {
+            rec.add("sensors", format_args!("[{}]", temps.sensors.iter().map(|(k, t, w, _)| format!("{{\"key\":\"{}\",\"name\":\"{}\",\"temp\":{:.1},\"weight\":{:.2}}}", k, crate::keydb::describe(k).unwrap_or(""), units.temp(*t), w)).collect::<Vec<_>>().join(",")));
+            if let Some((volts, amps)) = &self.volts_amps {
+                rec.add("volts", volts);
+                rec.add("amps", amps);
+            }
+            rec.add("calibration", sample::calibration(temps, units, (self.sys_power_raw, self.sys_power), crate::display::screen_power_raw_w(self.backlight, bat_power, self.sys_power_raw), self.design_wh.is_some()));
+        }
+        if !self.derived.is_empty() { rec.add("derived", format_args!("{{{}}}", crate::derived::to_json(&self.derived))); }
+        if units != Units::Celsius { rec.add("temp_unit", format_args!("\"{}\"", units.as_str())); }
+        if let Some(t) = tags { rec.add("tags", t); }
+        rec.finish().to_string()
+    }
+}
@@ -0,0 +1,211 @@
+// `stream --record FILE.kimrec` and `replay FILE.kimrec` (feature "record"): a recording holds
+// what the serialize stage is given (see pipeline.rs), not the JSON it made: each sample's rails
+// and pmset state, every temperature reading with its weight, the powermetrics result and the
+// enrichment (events, derived metrics, drain, adapter, thermal pressure). Replay feeds that
+// through the real Serializer, so --fields, --units, --template-file, [output] files and
+// --log-csv all apply as if the Mac were sampling now, and a weird reading from a bug report can
+// be reproduced, or a formatter tested, without the machine. Not recorded: `rails`
+// (--power-detail reads them while formatting) and `meter_self`.
+//
+// Format: JSON lines; a header `{"kimrec":1,"host":...,"model":...,"corrected":...,"backlight":...}`,
+// then one object per sample.
+
+use crate::pipeline::{Enrichment, Sample};
+use crate::sample::{Cluster, Powermetrics};
+use crate::temps::Temps;
+use crate::{json_opt, json_str};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const VERSION: u64 = 1;
+
+pub struct Header {
+    pub host: String,
+    pub model: String,
+    // A wall-meter correction was applied, so power_raw_w is reported
+    pub corrected: bool,
+    pub backlight: bool,
+}
+
+// A float as JSON; shortest round-trip form, so replay gets the recorded value back exactly.
+fn num(v: f64) -> String {
+    if v.is_finite() { v.to_string() } else { "null".to_string() }
+}
+
+fn ms(t: SystemTime) -> String {
+    num(t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64() * 1000.0)
+}
+
+fn array<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+    format!("[{}]", items.iter().map(f).collect::<Vec<_>>().join(","))
+}
+
+pub struct Recorder {
+    path: String,
+    file: Option<File>,
+    // Monotonic time is recorded as ms since the first sample
+    start: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn create(path: &str, h: &Header) -> Result<Recorder, String> {
+        let mut file = File::create(crate::config::expand_home(path)).map_err(|e| format!("--record {}: {}", path, e))?;
+        writeln!(file, "{{\"kimrec\":{},\"host\":{},\"model\":{},\"corrected\":{},\"backlight\":{}}}", VERSION, json_str(&h.host), json_str(&h.model), h.corrected, h.backlight)
+            .map_err(|e| format!("--record {}: {}", path, e))?;
+        Ok(Recorder { path: path.to_string(), file: Some(file), start: None })
+    }
+
+    // A write error is reported once and ends the recording; the stream goes on.
+    pub fn write(&mut self, s: &Sample, temps: &Temps, pm: &Powermetrics, e: &Enrichment) {
+        let Some(file) = &mut self.file else { return };
+        let start = *self.start.get_or_insert(s.mono);
+        let pairs = |v: &Vec<(f64, f64)>| array(v, |(t, w)| format!("[{},{}]", num(*t), num(*w)));
+        let temps_json = format!("{{\"cpu\":{},\"cpu_p\":{},\"cpu_e\":{},\"gpu\":{},\"mem\":{},\"ssd\":{},\"bat\":{},\"gpu_sensors\":{},\"sensors\":{}}}",
+            pairs(&temps.cpu), pairs(&temps.cpu_p), pairs(&temps.cpu_e), pairs(&temps.gpu), pairs(&temps.mem), pairs(&temps.ssd), pairs(&temps.bat),
+            array(&temps.gpu_sensors, |(k, t)| format!("[{},{}]", json_str(k), num(*t))),
+            array(&temps.sensors, |(k, t, w, g)| format!("[{},{},{},{}]", json_str(k), num(*t), num(*w), g.map_or("null".to_string(), json_str))));
+        let named = |v: &Vec<(String, f64)>| array(v, |(n, x)| format!("[{},{}]", json_str(n), num(*x)));
+        let pm_json = format!("{{\"cpu_mw\":{},\"gpu_mw\":{},\"ane_mw\":{},\"wakeups\":{},\"cpu_ms\":{},\"top\":{},\"top_json\":{},\"high_wakeups\":{},\"high_wakeups_json\":{},\"window\":{},\"clusters\":{}}}",
+            pm.cpu_mw, pm.gpu_mw, pm.ane_mw, num(pm.wakeups), num(pm.cpu_ms), named(&pm.top), json_str(&pm.top_json), named(&pm.high_wakeups), json_str(&pm.high_wakeups_json),
+            pm.window.map_or("null".to_string(), |(at, len)| format!("[{},{}]", ms(at), num(len))),
+            array(&pm.clusters, |c| format!("[{},{},{}]", json_str(&c.name), num(c.freq_mhz), num(c.active_pct))));
+        let opt = |v: Option<f64>| v.map_or("null".to_string(), num);
+        let enrich_json = format!("{{\"pm_fresh\":{},\"quiet\":{},\"interval_ms\":{},\"events\":[{}],\"derived\":{},\"thermal\":{},\"drain_pct_per_hr\":{},\"est_hours_remaining\":{},\"charge_input_w\":{},\"charge_overhead_w\":{},\"adapter_w\":{},\"dominant\":{}}}",
+            e.pm_fresh, e.quiet, e.interval_ms, e.events.join(","), array(&e.derived, |(n, v)| format!("[{},{}]", json_str(n), opt(*v))),
+            json_opt(e.thermal_level.map(|(_, rank)| rank)), opt(e.drain_pct_per_hr), opt(e.est_hours_remaining), opt(e.charge_input_w), opt(e.charge_overhead_w),
+            json_opt(e.adapter_w), json_opt(e.dominant.map(crate::consumers::to_json)));
+        let line = format!("{{\"seq\":{},\"mono_ms\":{},\"at_ms\":{},\"utc_offset\":{},\"sys_power_raw\":{},\"sys_power\":{},\"bat_power\":{},\"mem_power\":{},\"design_wh\":{},\"battery_pct\":{},\"charging\":{},\"mem_free_pct\":{},\"temps\":{},\"pm\":{},\"enrich\":{}}}\n",
+            s.seq, num(s.mono.duration_since(start).as_secs_f64() * 1000.0), ms(s.at), s.ts.utc_offset, s.sys_power_raw, s.sys_power, s.bat_power, s.mem_power,
+            json_opt(s.design_wh), json_opt(s.battery_pct), json_opt(s.charging), s.mem_free_pct, temps_json, pm_json, enrich_json);
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            crate::diag::warn!("recording to {} stopped: {}", self.path, err);
+            self.file = None;
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(f) = &self.file { let _ = f.sync_data(); }
+    }
+}
+
+// One recorded sample, ready for the serializer
+pub struct Frame {
+    pub sample: Sample,
+    pub temps: Temps,
+    pub pm: Powermetrics,
+    pub enrichment: Enrichment,
+}
+
+pub struct Reader {
+    lines: std::io::Lines<BufReader<File>>,
+    // Recorded monotonic offsets are replayed from here
+    base: Instant,
+    line_no: usize,
+}
+
+pub fn open(path: &str) -> Result<(Header, Reader), String> {
+    let file = File::open(crate::config::expand_home(path)).map_err(|e| format!("{}: {}", path, e))?;
+    let mut lines = BufReader::new(file).lines();
+    let first = lines.next().and_then(|l| l.ok()).ok_or_else(|| format!("{}: empty recording", path))?;
+    let h: Value = serde_json::from_str(&first).map_err(|e| format!("{}: not a kim_temp recording: {}", path, e))?;
+    match h.get("kimrec").and_then(Value::as_u64) {
+        Some(VERSION) => {}
+        Some(v) => return Err(format!("{}: recording format {} is newer than this build reads ({})", path, v, VERSION)),
+        None => return Err(format!("{}: not a kim_temp recording", path)),
+    }
+    let text = |k: &str| h.get(k).and_then(Value::as_str).unwrap_or_default().to_string();
+    let flag = |k: &str| h.get(k).and_then(Value::as_bool).unwrap_or(false);
+    Ok((Header { host: text("host"), model: text("model"), corrected: flag("corrected"), backlight: flag("backlight") }, Reader { lines, base: Instant::now(), line_no: 1 }))
+}
+
+fn f(v: &Value, k: &str) -> Option<f64> {
+    v.get(k).and_then(Value::as_f64)
+}
+
+fn list<'a>(v: &'a Value, k: &str) -> impl Iterator<Item = &'a [Value]> {
+    v.get(k).and_then(Value::as_array).into_iter().flatten().filter_map(|x| x.as_array().map(Vec::as_slice))
+}
+
+fn pairs(v: &Value, k: &str) -> Vec<(f64, f64)> {
+    list(v, k).filter_map(|p| Some((p.first()?.as_f64()?, p.get(1)?.as_f64()?))).collect()
+}
+
+fn named(v: &Value, k: &str) -> Vec<(String, f64)> {
+    list(v, k).filter_map(|p| Some((p.first()?.as_str()?.to_string(), p.get(1)?.as_f64()?))).collect()
+}
+
+// A recorded sensor's category group, as the 'static name Temps carries
+fn group(name: &str) -> Option<&'static str> {
+    ["cpu", "gpu", "mem", "ssd", "bat"].into_iter().find(|g| *g == name)
+}
+
+fn at(unix_ms: f64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs_f64(unix_ms.max(0.0) / 1000.0)
+}
+
+impl Reader {
+    fn frame(&self, line: &str) -> Result<Frame, String> {
+        let v: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        let (t, pm, e) = (v.get("temps").ok_or("no temps")?, v.get("pm").ok_or("no pm")?, v.get("enrich").ok_or("no enrich")?);
+        let at_time = at(f(&v, "at_ms").ok_or("no at_ms")?);
+        let sample = Sample {
+            seq: v.get("seq").and_then(Value::as_u64).ok_or("no seq")?,
+            mono: self.base + Duration::from_secs_f64(f(&v, "mono_ms").unwrap_or(0.0).max(0.0) / 1000.0),
+            at: at_time,
+            ts: crate::clock::at_offset(at_time, v.get("utc_offset").and_then(Value::as_i64).unwrap_or(0)),
+            sys_power_raw: f(&v, "sys_power_raw").unwrap_or(0.0) as f32,
+            sys_power: f(&v, "sys_power").unwrap_or(0.0) as f32,
+            bat_power: f(&v, "bat_power").unwrap_or(0.0) as f32,
+            mem_power: f(&v, "mem_power").unwrap_or(0.0) as f32,
+            design_wh: f(&v, "design_wh").map(|w| w as f32),
+            battery_pct: v.get("battery_pct").and_then(Value::as_i64).map(|p| p as i32),
+            charging: v.get("charging").and_then(Value::as_bool),
+            mem_free_pct: v.get("mem_free_pct").and_then(Value::as_i64).unwrap_or(0) as i32,
+        };
+        let temps = Temps {
+            cpu: pairs(t, "cpu"), cpu_p: pairs(t, "cpu_p"), cpu_e: pairs(t, "cpu_e"), gpu: pairs(t, "gpu"), mem: pairs(t, "mem"), ssd: pairs(t, "ssd"), bat: pairs(t, "bat"),
+            gpu_sensors: named(t, "gpu_sensors"),
+            // The group was added later; older recordings read as unclassified
+            sensors: list(t, "sensors").filter_map(|s| Some((s.first()?.as_str()?.to_string(), s.get(1)?.as_f64()?, s.get(2)?.as_f64()?, s.get(3).and_then(Value::as_str).and_then(group)))).collect(),
+        };
+        let int = |k: &str| pm.get(k).and_then(Value::as_i64).unwrap_or(0) as i32;
+        let text = |k: &str| pm.get(k).and_then(Value::as_str).unwrap_or_default().to_string();
+        let pm = Powermetrics {
+            cpu_mw: int("cpu_mw"), gpu_mw: int("gpu_mw"), ane_mw: int("ane_mw"),
+            wakeups: f(pm, "wakeups").unwrap_or(0.0), cpu_ms: f(pm, "cpu_ms").unwrap_or(0.0),
+            top: named(pm, "top"), top_json: text("top_json"), high_wakeups: named(pm, "high_wakeups"), high_wakeups_json: text("high_wakeups_json"),
+            window: pm.get("window").and_then(Value::as_array).and_then(|w| Some((at(w.first()?.as_f64()?), w.get(1)?.as_f64()?))),
+            clusters: list(pm, "clusters").filter_map(|c| Some(Cluster { name: c.first()?.as_str()?.to_string(), freq_mhz: c.get(1)?.as_f64()?, active_pct: c.get(2)?.as_f64()? })).collect(),
+        };
+        let enrichment = Enrichment {
+            pm_fresh: e.get("pm_fresh").and_then(Value::as_bool).unwrap_or(false),
+            quiet: e.get("quiet").and_then(Value::as_bool).unwrap_or(false),
+            interval_ms: e.get("interval_ms").and_then(Value::as_u64).unwrap_or(1000),
+            events: e.get("events").and_then(Value::as_array).into_iter().flatten().map(Value::to_string).collect(),
+            derived: list(e, "derived").filter_map(|d| Some((d.first()?.as_str()?.to_string(), d.get(1)?.as_f64()))).collect(),
+            thermal_level: e.get("thermal").and_then(Value::as_u64).map(crate::thermal::level),
+            drain_pct_per_hr: f(e, "drain_pct_per_hr"), est_hours_remaining: f(e, "est_hours_remaining"),
+            charge_input_w: f(e, "charge_input_w"), charge_overhead_w: f(e, "charge_overhead_w"),
+            adapter_w: e.get("adapter_w").and_then(Value::as_u64).map(|w| w as u32),
+            dominant: e.get("dominant").and_then(Value::as_array).map(|a| {
+                let mut labels = a.iter().filter_map(Value::as_str).filter_map(crate::consumers::label);
+                [labels.next(), labels.next()]
+            }),
+            ..Default::default()
+        };
+        Ok(Frame { sample, temps, pm, enrichment })
+    }
+}
+
+impl Iterator for Reader {
+    type Item = Result<Frame, String>;
+
+    // A line that doesn't parse is an error naming its line number; replay reports it and goes on.
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        self.line_no += 1;
+        Some(line.map_err(|e| e.to_string()).and_then(|l| self.frame(&l)).map_err(|e| format!("line {}: {}", self.line_no, e)))
+    }
+}
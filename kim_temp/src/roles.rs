@@ -0,0 +1,88 @@
+// Key roles marked by hand (in `explore`) for keys the built-in profiles get wrong or don't know:
+// a power role makes that key the rail, a temperature role files the sensor under that
+// category, `ignore` keeps a sensor out of every average. Stored per machine model as
+// `MODEL<TAB>KEY<TAB>role` lines in ~/.config/kim_temp/roles; `[power_keys]` in the config still wins.
+// A fleet `roles` file (see fleet.rs) is read first, so this machine's own marks override it.
+
+use crate::profile::Category;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    SystemPower,
+    BatteryPower,
+    CpuPower,
+    GpuPower,
+    Temp(Category),
+    Ignore,
+}
+
+// In the order `explore` cycles through them
+pub const ALL: &[Role] = &[
+    Role::SystemPower, Role::BatteryPower, Role::CpuPower, Role::GpuPower,
+    Role::Temp(Category::CpuP), Role::Temp(Category::CpuE), Role::Temp(Category::Gpu),
+    Role::Temp(Category::Mem), Role::Temp(Category::Ssd), Role::Temp(Category::Battery),
+    Role::Ignore,
+];
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::SystemPower => "system_power",
+            Role::BatteryPower => "battery_power",
+            Role::CpuPower => "cpu_power",
+            Role::GpuPower => "gpu_power",
+            Role::Temp(Category::CpuP) => "cpu_p_temp",
+            Role::Temp(Category::CpuE) => "cpu_e_temp",
+            Role::Temp(Category::Gpu) => "gpu_temp",
+            Role::Temp(Category::Mem) => "mem_temp",
+            Role::Temp(Category::Ssd) => "ssd_temp",
+            Role::Temp(Category::Battery) => "bat_temp",
+            Role::Ignore => "ignore",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Role> {
+        ALL.iter().copied().find(|r| r.as_str() == s)
+    }
+
+    // The next role for `explore`'s cycle key; None (unmarked) after the last.
+    pub fn next(current: Option<Role>) -> Option<Role> {
+        match current {
+            None => ALL.first().copied(),
+            Some(r) => ALL.iter().position(|a| *a == r).and_then(|i| ALL.get(i + 1)).copied(),
+        }
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config/kim_temp/roles"))
+}
+
+fn load_from(path: Option<PathBuf>, model: &str) -> Vec<(String, Role)> {
+    let Some(text) = path.and_then(|p| std::fs::read_to_string(p).ok()) else { return Vec::new() };
+    text.lines().filter_map(|l| {
+        let mut f = l.split('\t');
+        if f.next() != Some(model) { return None; }
+        let key = f.next().filter(|k| k.len() == 4)?;
+        Some((key.to_string(), Role::parse(f.next()?)?))
+    }).collect()
+}
+
+pub fn load(model: &str) -> Vec<(String, Role)> {
+    let own = load_from(path(), model);
+    let mut roles: Vec<(String, Role)> = load_from(crate::fleet::file("roles"), model).into_iter().filter(|(k, _)| !own.iter().any(|(o, _)| o == k)).collect();
+    roles.extend(own);
+    roles
+}
+
+// Replaces every line for `model`; other machines sharing the file keep theirs.
+pub fn save(model: &str, roles: &[(String, Role)]) -> Result<PathBuf, String> {
+    let path = path().ok_or("HOME is not set")?;
+    let mut lines: Vec<String> = std::fs::read_to_string(&path).unwrap_or_default().lines()
+        .filter(|l| l.split('\t').next() != Some(model)).map(str::to_string).collect();
+    lines.extend(roles.iter().map(|(key, role)| format!("{}\t{}\t{}", model, key, role.as_str())));
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?; }
+    std::fs::write(&path, lines.iter().map(|l| format!("{}\n", l)).collect::<String>()).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    Ok(path)
+}
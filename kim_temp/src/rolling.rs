@@ -0,0 +1,58 @@
+// Rolling 1 and 5 minute windows over stream samples, so every line carries avg/min/max/p95
+// and consumers don't each have to keep their own history.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const WINDOWS: [(&str, Duration); 2] = [("1m", Duration::from_secs(60)), ("5m", Duration::from_secs(300))];
+
+// Samples of one metric, kept for the longest window.
+#[derive(Default)]
+pub struct Series {
+    samples: VecDeque<(Instant, f64)>,
+    // The window being summarised, sorted; kept so a sample's summary allocates nothing
+    sorted: Vec<f64>,
+}
+
+// (avg, min, max, p95, n) of one window; None when it has no samples.
+type Summary = Option<(f64, f64, f64, f64, usize)>;
+
+impl Series {
+    // Non-finite values are left out (they'd print as NaN, which isn't JSON).
+    pub fn push(&mut self, now: Instant, value: f64) {
+        if let Some(v) = crate::diag::finite(value, "rolling window sample") { self.samples.push_back((now, v)); }
+        let longest = WINDOWS[WINDOWS.len() - 1].1;
+        while self.samples.front().is_some_and(|(t, _)| now.duration_since(*t) > longest) {
+            self.samples.pop_front();
+        }
+    }
+
+    // The samples of the last `span`.
+    fn window(&mut self, now: Instant, span: Duration) -> Summary {
+        let values = &mut self.sorted;
+        values.clear();
+        values.extend(self.samples.iter().filter(|(t, _)| now.duration_since(*t) <= span).map(|(_, v)| *v));
+        if values.is_empty() { return None; }
+        values.sort_by(f64::total_cmp);
+        let n = values.len();
+        // Nearest-rank percentile
+        let p95 = values[((0.95 * n as f64).ceil() as usize).clamp(1, n) - 1];
+        Some((values.iter().sum::<f64>() / n as f64, values[0], values[n - 1], p95, n))
+    }
+
+    // {"1m":{"avg":..,"min":..,"max":..,"p95":..,"n":..},"5m":{..}}, a window with no samples null.
+    pub fn windows_json(&mut self, now: Instant, prec: usize) -> impl std::fmt::Display {
+        let summaries = WINDOWS.map(|(name, span)| (name, self.window(now, span)));
+        crate::sample::Lazy(move |f| {
+            f.write_str("{")?;
+            for (i, (name, s)) in summaries.iter().enumerate() {
+                write!(f, "{}\"{}\":", if i > 0 { "," } else { "" }, name)?;
+                match s {
+                    Some((avg, min, max, p95, n)) => write!(f, "{{\"avg\":{:.p$},\"min\":{:.p$},\"max\":{:.p$},\"p95\":{:.p$},\"n\":{}}}", avg, min, max, p95, n, p = prec)?,
+                    None => f.write_str("null")?,
+                }
+            }
+            f.write_str("}")
+        })
+    }
+}
@@ -0,0 +1,364 @@
+// Collectors shared by the json and stream modes, and `--fields` selection: a field that is
+// not requested is neither emitted nor, where possible, collected (no powermetrics run when no
+// power breakdown is wanted, no VM statistics without mem_free_pct, no temperature sweep without temps).
+
+use std::fmt::{self, Write};
+
+// Defined in the library so the output and `kim_temp::snapshot::Snapshot` share one list
+pub use kim_temp::snapshot::FIELDS;
+
+// Field groups that share one collector
+pub const TEMP_FIELDS: &[&str] = &["cpu_temp", "cpu_p_temp", "cpu_e_temp", "gpu_temp", "gpu_temp_max", "gpu_sensors", "hottest", "mem_temp", "ssd_temp", "bat_temp", "sensors", "calibration", "rolling"];
+pub const BATTERY_FIELDS: &[&str] = &["bat_temp", "bat_power_w", "battery_pct", "charging", "efficiency_hrs", "screen_w", "charge_input_w", "charge_overhead_w", "adapter_w", "dominant", "events", "calibration", "drain_pct_per_hr", "est_hours_remaining"];
+pub const PMSET_FIELDS: &[&str] = &["battery_pct", "charging", "drain_pct_per_hr", "est_hours_remaining"];
+pub const ADAPTER_FIELDS: &[&str] = &["charge_input_w", "charge_overhead_w", "adapter_w", "dominant", "events"];
+pub const POWERMETRICS_FIELDS: &[&str] = &["cpu_mw", "gpu_mw", "ane_mw", "wakeups_per_sec", "cpu_ms_per_j", "wakeups_per_w", "top_cpu", "high_wakeups"];
+
+pub struct Fields {
+    only: Option<Vec<String>>,
+}
+
+impl Fields {
+    // `cpu_temp,power_w`; None means every field.
+    pub fn parse(spec: Option<&str>) -> Result<Fields, String> {
+        let Some(spec) = spec else { return Ok(Fields { only: None }) };
+        let names: Vec<String> = spec.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+        if let Some(bad) = names.iter().find(|n| !FIELDS.contains(&n.as_str())) {
+            return Err(format!("unknown field {:?} (available: {})", bad, FIELDS.join(",")));
+        }
+        Ok(Fields { only: Some(names) })
+    }
+
+    pub fn wants(&self, name: &str) -> bool {
+        self.only.as_ref().is_none_or(|o| o.iter().any(|n| n == name))
+    }
+
+    pub fn wants_any(&self, names: &[&str]) -> bool {
+        names.iter().any(|n| self.wants(n))
+    }
+}
+
+// A JSON object assembled field by field, skipping fields that weren't selected. Values are
+// formatted straight into one buffer, which `clear` keeps for the next sample.
+pub struct Record<'a> {
+    fields: &'a Fields,
+    out: String,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(fields: &'a Fields) -> Record<'a> {
+        Record { fields, out: String::from("{") }
+    }
+
+    pub fn clear(&mut self) {
+        self.out.clear();
+        self.out.push('{');
+    }
+
+    // `value` is already-encoded JSON.
+    pub fn add(&mut self, name: &str, value: impl fmt::Display) {
+        if !self.fields.wants(name) { return; }
+        if self.out.len() > 1 { self.out.push(','); }
+        let _ = write!(self.out, "\"{}\":{}", name, value);
+    }
+
+    pub fn finish(&mut self) -> &str {
+        self.out.push('}');
+        &self.out
+    }
+}
+
+// Display through a closure, so a composite value is written into the Record as it is formatted
+// instead of being built as a String first.
+pub struct Lazy<F: Fn(&mut fmt::Formatter) -> fmt::Result>(pub F);
+
+impl<F: Fn(&mut fmt::Formatter) -> fmt::Result> fmt::Display for Lazy<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (self.0)(f)
+    }
+}
+
+// `json_num` without the String: a number with `prec` decimals, or null.
+pub struct Num(pub Option<f64>, pub usize);
+
+impl fmt::Display for Num {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 { Some(v) => write!(f, "{:.*}", self.1, v), None => f.write_str("null") }
+    }
+}
+
+// `json_opt` without the String: the value, or null.
+pub struct Opt<T>(pub Option<T>);
+
+impl<T: fmt::Display> fmt::Display for Opt<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 { Some(v) => write!(f, "{}", v), None => f.write_str("null") }
+    }
+}
+
+// `json_str` without the String: quoted and escaped as it is written.
+pub struct Quoted<'a>(pub &'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+// `--detail`: every value the tool adjusts, before and after. Temperatures are the plain sensor
+// mean vs the profile/jitter-weighted mean; power_w is the system rail vs the wall-meter corrected
+// value (equal without a `calibrate` result); screen_w is the rail difference vs the clamped estimate.
+// A calibration regression shows up as the two columns drifting apart in logs.
+pub fn calibration(temps: &crate::temps::Temps, units: crate::config::Units, power_w: (f32, f32), screen_raw_w: Option<f32>, has_battery: bool) -> impl fmt::Display + '_ {
+    use crate::temps::{avg, plain_avg};
+    Lazy(move |f| {
+        let pair = |f: &mut fmt::Formatter, name: &str, raw: Option<f64>, calibrated: Option<f64>, prec: usize| {
+            write!(f, "\"{}\":{{\"raw\":{},\"calibrated\":{}}},", name, Num(raw, prec), Num(calibrated, prec))
+        };
+        f.write_str("{")?;
+        let categories = [("cpu_temp", &temps.cpu), ("gpu_temp", &temps.gpu), ("mem_temp", &temps.mem), ("ssd_temp", &temps.ssd), ("bat_temp", &temps.bat)];
+        for (name, v) in categories.into_iter().filter(|(name, _)| has_battery || *name != "bat_temp") {
+            pair(f, name, Some(units.temp(plain_avg(v))), Some(units.temp(avg(v))), 1)?;
+        }
+        pair(f, "power_w", Some(power_w.0 as f64), Some(power_w.1 as f64), 2)?;
+        let screen = screen_raw_w.map(|w| w as f64);
+        write!(f, "\"screen_w\":{{\"raw\":{},\"calibrated\":{}}}}}", Num(screen, 2), Num(screen.map(|w| w.max(0.0)), 2))
+    })
+}
+
+// When each source was actually sampled. SMC reads are instantaneous; powermetrics averages over
+// a ~100 ms window that may be several stream cycles old (it is cached between runs), so
+// `age_ms` is the gap from the end of that window to the SMC reads of this record.
+pub fn sources(smc_at: std::time::SystemTime, pm: &Powermetrics) -> impl fmt::Display + '_ {
+    Lazy(move |f| {
+        write!(f, "{{\"smc\":{{\"ts\":\"{}\",\"window_ms\":0}},\"powermetrics\":", crate::clock::local(smc_at))?;
+        match pm.window {
+            Some((start, ms)) => {
+                let end = start + std::time::Duration::from_secs_f64(ms / 1000.0);
+                let age_ms = match smc_at.duration_since(end) { Ok(d) => d.as_secs_f64() * 1000.0, Err(e) => -e.duration().as_secs_f64() * 1000.0 };
+                write!(f, "{{\"ts\":\"{}\",\"window_ms\":{:.1},\"age_ms\":{:.0}}}}}", crate::clock::local(start), ms, age_ms)
+            }
+            None => f.write_str("null}"),
+        }
+    })
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Powermetrics {
+    pub cpu_mw: i32,
+    pub gpu_mw: i32,
+    pub ane_mw: i32,
+    pub wakeups: f64,
+    // CPU time of every task (ms per second of the window), excluded processes included
+    pub cpu_ms: f64,
+    pub top_json: String,
+    // (name, CPU ms/s) behind top_json
+    pub top: Vec<(String, f64)>,
+    pub high_wakeups_json: String,
+    // (name, wakeups/s) behind high_wakeups_json
+    pub high_wakeups: Vec<(String, f64)>,
+    // Start and length (ms) of the measured window; None when powermetrics didn't run
+    pub window: Option<(std::time::SystemTime, f64)>,
+    // Per CPU cluster ("E", "P0", "P1", ...) from the cpu_power sampler
+    pub clusters: Vec<Cluster>,
+}
+
+impl Powermetrics {
+    // Work per energy over the powermetrics window: (CPU ms per joule, wakeups per watt), with
+    // CPU + GPU + ANE power from the same window so numerator and denominator cover the same
+    // ~100 ms. Unlike absolute power these barely move with ambient temperature or the display,
+    // so a regression in the software shows up as a drop. None without a reading or below 50 mW.
+    pub fn efficiency(&self) -> (Option<f64>, Option<f64>) {
+        let soc_w = (self.cpu_mw + self.gpu_mw + self.ane_mw) as f64 / 1000.0;
+        if self.window.is_none() || soc_w < 0.05 { return (None, None); }
+        (Some(self.cpu_ms / soc_w), Some(self.wakeups / soc_w))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Cluster {
+    pub name: String,
+    pub freq_mhz: f64,
+    pub active_pct: f64,
+}
+
+impl Cluster {
+    pub fn is_performance(&self) -> bool {
+        self.name.starts_with('P')
+    }
+}
+
+// "P0-Cluster HW active frequency: 3204 MHz" / "P0-Cluster HW active residency:  87.12% (...)"
+fn clusters(output: &str) -> Vec<Cluster> {
+    let mut out: Vec<Cluster> = Vec::new();
+    for line in output.lines() {
+        let Some((name, rest)) = line.trim().split_once("-Cluster HW active ") else { continue };
+        let Some(value) = rest.split_once(':').and_then(|(_, v)| v.split_whitespace().next()).and_then(|v| v.trim_end_matches('%').parse::<f64>().ok()).and_then(|v| crate::diag::finite(v, "powermetrics cluster")) else { continue };
+        let i = match out.iter().position(|c| c.name == name) { Some(i) => i, None => { out.push(Cluster { name: name.to_string(), ..Default::default() }); out.len() - 1 } };
+        if rest.starts_with("frequency") { out[i].freq_mhz = value; } else if rest.starts_with("residency") { out[i].active_pct = value; }
+    }
+    out
+}
+
+fn power_line(output: &str, label: &str) -> i32 {
+    output.lines().find(|l| l.contains(label)).and_then(|l| l.split_whitespace().find_map(|s| s.parse::<f64>().ok())).and_then(|v| crate::diag::finite(v, label)).map(|v| v as i32).unwrap_or(0)
+}
+
+// The exact arguments, so `setup-sudo` can allow this command line and nothing else
+pub const POWERMETRICS_ARGS: &[&str] = &["-n", "1", "-i", "100", "--samplers", "cpu_power,tasks"];
+
+// Raw output of one 100ms powermetrics sample and when it ended. As root it runs directly;
+// otherwise through `sudo -n`, which needs the rule `setup-sudo` installs (without one sudo
+// would ask for a password nobody can type, as stdin isn't passed on).
+pub fn powermetrics_output() -> Option<(String, std::time::SystemTime)> {
+    let root = unsafe { libc::geteuid() } == 0;
+    let mut cmd = std::process::Command::new(if root { "powermetrics" } else { "sudo" });
+    if !root { cmd.args(["-n", "powermetrics"]); }
+    cmd.args(POWERMETRICS_ARGS);
+    // Tracked while it runs, so stopping `stream` stops it too (see session.rs); a run that hangs
+    // (sudo waiting on something) is stopped at the subprocess timeout
+    let child = crate::subprocess::spawn(&mut cmd).map_err(|e| crate::diag::debug!("cannot run powermetrics: {}", e)).ok()?;
+    crate::session::track_child(child.id());
+    let output = crate::subprocess::wait(child, "powermetrics");
+    crate::session::untrack_child();
+    let output = output.ok().filter(|o| o.status.success() || { crate::diag::debug!("powermetrics exited with {}: {}", o.status, String::from_utf8_lossy(&o.stderr).trim()); false })
+        .and_then(|o| String::from_utf8(o.stdout).ok())?;
+    Some((output, std::time::SystemTime::now()))
+}
+
+// One 100ms powermetrics sample: from a running `serve` (the root helper) when there is one,
+// else run here. Without either the fields stay empty, and the first failure says so on stderr.
+pub fn powermetrics(excluded: &[&str]) -> Powermetrics {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    match crate::serve::powermetrics_output().or_else(powermetrics_output) {
+        Some((output, ended)) => parse_powermetrics(&output, ended, excluded),
+        None => {
+            WARNED.call_once(|| crate::diag::warn!("powermetrics didn't run, so CPU/GPU/ANE power, wakeups and processes are empty; install the root helper (`sudo kim_temp install-daemon --serve`) or the sudo rule (`sudo kim_temp setup-sudo`)"));
+            Powermetrics::default()
+        }
+    }
+}
+
+pub fn parse_powermetrics(pm_output: &str, ended: std::time::SystemTime, excluded: &[&str]) -> Powermetrics {
+    // "*** Sampled system activity (...) (102.35ms elapsed) ***"; the window closed just before the process exited
+    let window = pm_output.lines().find(|l| l.contains("Sampled system activity"))
+        .and_then(|l| l.rsplit_once("ms elapsed")).and_then(|(head, _)| head.rsplit('(').next()?.trim().parse::<f64>().ok())
+        .map(|ms| (ended - std::time::Duration::from_secs_f64(ms / 1000.0), ms));
+
+    let (mut total_wakeups, mut total_cpu_ms) = (0.0, 0.0);
+    // Names borrowed from the output unless --redact-processes rewrites them
+    let mut processes: Vec<(std::borrow::Cow<str>, f64, f64)> = Vec::new();
+    let mut in_tasks = false;
+    for line in pm_output.lines() {
+        if line.starts_with("Name") { in_tasks = true; continue; }
+        if line.starts_with("ALL_TASKS") || line.starts_with("CPU Power") { break; }
+        if in_tasks && !line.trim().is_empty() {
+            // The first eight columns, without collecting every line's words
+            let mut parts = [""; 8];
+            let columns = parts.iter_mut().zip(line.split_whitespace()).map(|(slot, word)| *slot = word).count();
+            if columns == 8 && parts[1].parse::<i32>().is_ok() {
+                // "nan" parses as a float; such a column counts as 0 rather than poisoning the totals
+                let cpu_ms: f64 = parts[2].parse().ok().and_then(|v| crate::diag::finite(v, "powermetrics cpu_ms")).unwrap_or(0.0);
+                let wakeups: f64 = parts[6].parse().ok().and_then(|v| crate::diag::finite(v, "powermetrics wakeups")).unwrap_or(0.0);
+                total_wakeups += wakeups;
+                total_cpu_ms += cpu_ms;
+                if !excluded.contains(&parts[0]) {
+                    processes.push((crate::privacy::process(parts[0]), cpu_ms, wakeups));
+                }
+            }
+        }
+    }
+    processes.sort_by(|a, b| b.1.total_cmp(&a.1));
+    // One String per list, written into as it goes
+    let to_json = |list: &mut dyn Iterator<Item = &(std::borrow::Cow<str>, f64, f64)>| {
+        let mut out = String::new();
+        for (i, (n, c, w)) in list.enumerate() {
+            let _ = write!(out, "{}{{\"name\":{},\"cpu_ms\":{:.1},\"wakeups\":{:.1}}}", if i > 0 { "," } else { "" }, Quoted(n), c, w);
+        }
+        out
+    };
+    Powermetrics {
+        cpu_mw: power_line(pm_output, "CPU Power:"),
+        gpu_mw: power_line(pm_output, "GPU Power:"),
+        ane_mw: power_line(pm_output, "ANE Power:"),
+        wakeups: total_wakeups,
+        cpu_ms: total_cpu_ms,
+        top_json: to_json(&mut processes.iter().take(5)),
+        top: processes.iter().take(5).map(|(n, c, _)| (n.to_string(), *c)).collect(),
+        high_wakeups_json: to_json(&mut processes.iter().filter(|(_, _, w)| *w > 50.0).take(5)),
+        high_wakeups: processes.iter().filter(|(_, _, w)| *w > 50.0).take(5).map(|(n, _, w)| (n.to_string(), *w)).collect(),
+        window,
+        clusters: clusters(pm_output),
+    }
+}
+
+// `hottest`: {"key","temp","category"} for the hottest classified sensor, null without one.
+pub fn hottest(temps: &crate::temps::Temps, units: crate::config::Units) -> impl fmt::Display + '_ {
+    Lazy(move |f| match crate::temps::hottest(temps) {
+        Some((k, t, group)) => write!(f, "{{\"key\":{},\"temp\":{:.1},\"category\":\"{}\"}}", Quoted(k), units.temp(t), group),
+        None => f.write_str("null"),
+    })
+}
+
+// `gpu_sensors`: every GPU temperature key with its name and reading, hottest first (sorted in
+// place, which `Temps::refill` doesn't mind).
+pub fn gpu_sensors(sensors: &mut [(String, f64)], units: crate::config::Units) -> impl fmt::Display + '_ {
+    sensors.sort_by(|a, b| b.1.total_cmp(&a.1));
+    Lazy(move |f| {
+        f.write_str("[")?;
+        for (i, (k, t)) in sensors.iter().enumerate() {
+            write!(f, "{}{{\"key\":\"{}\",\"name\":\"{}\",\"temp\":{:.1}}}", if i > 0 { "," } else { "" }, k, crate::keydb::describe(k).unwrap_or(""), units.temp(*t))?;
+        }
+        f.write_str("]")
+    })
+}
+
+// Free + inactive + speculative pages (the counts vm_stat prints), as a percentage of RAM.
+pub fn mem_free_pct() -> i32 {
+    // vm_statistics64 as 38 naturals: free_count is [0], inactive_count [2], speculative_count [23]
+    let mut info = [0u32; HOST_VM_INFO64_COUNT as usize];
+    let mut count = HOST_VM_INFO64_COUNT;
+    if unsafe { host_statistics64(mach_host_self(), HOST_VM_INFO64, info.as_mut_ptr(), &mut count) } != 0 { return 0; }
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+    let (mut total_bytes, mut len) = (0u64, std::mem::size_of::<u64>());
+    if unsafe { sysctlbyname(c"hw.memsize".as_ptr(), &mut total_bytes as *mut u64 as *mut libc::c_void, &mut len, std::ptr::null_mut(), 0) } != 0 || total_bytes == 0 { return 0; }
+    let free_bytes = (info[0] as u64 + info[2] as u64 + info[23] as u64) * page_size;
+    ((free_bytes as f64 / total_bytes as f64) * 100.0) as i32
+}
+
+// host_statistics64 and sysctlbyname, declared here like the other mach calls: asking the kernel
+// directly instead of running vm_stat keeps a stream sample free of subprocesses.
+const HOST_VM_INFO64: i32 = 4;
+const HOST_VM_INFO64_COUNT: u32 = 38;
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn mach_host_self() -> u32;
+    fn host_statistics64(host: u32, flavor: i32, info: *mut u32, count: *mut u32) -> i32;
+    fn sysctlbyname(name: *const libc::c_char, oldp: *mut libc::c_void, oldlenp: *mut usize, newp: *mut libc::c_void, newlen: usize) -> libc::c_int;
+}
+
+// Elsewhere host_statistics64 fails (KERN_FAILURE) and mem_free_pct reports 0, as on any error.
+#[cfg(not(target_os = "macos"))]
+unsafe fn mach_host_self() -> u32 {
+    0
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn host_statistics64(_host: u32, _flavor: i32, _info: *mut u32, _count: *mut u32) -> i32 {
+    5
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn sysctlbyname(_name: *const libc::c_char, _oldp: *mut libc::c_void, _oldlenp: *mut usize, _newp: *mut libc::c_void, _newlen: usize) -> libc::c_int {
+    -1
+}
@@ -0,0 +1,112 @@
+// `scan-safety`: a developer survey of every SMC key on this machine, to keep the decoder's type
+// table (value.rs) complete as new hardware ships. Each key's declaration (type, size, attributes)
+// is fetched first; its value is only read when the declaration allows a read, and then only as
+// the declared number of bytes of the declared type, never as an assumed float. How the decoder
+// handles each type/size pair is worked out from the decoder itself, so the report can't drift
+// from it. The report lists every pair seen with its key count and read results, then every key
+// whose declaration or read failed; `--json` prints the same as one object to attach to an issue.
+
+use crate::smcraw::{self, Raw};
+use crate::value::SmcValue;
+use std::collections::BTreeMap;
+
+const EXAMPLES: usize = 5;
+
+// What SmcValue::decode makes of a type/size pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decoder {
+    Decoded,
+    // Not in the decoder's table: the value comes out as raw bytes
+    UnknownType,
+    // Fewer bytes than the type needs: raw bytes too
+    TooShort,
+    // More bytes than a fixed-size type uses: the rest is ignored
+    TooLong,
+    // {xxx struct types, kept as raw bytes on purpose
+    Struct,
+}
+
+impl Decoder {
+    fn of(data_type: &str, size: u32) -> Decoder {
+        let decodes = |n: usize| !matches!(SmcValue::decode(data_type, &vec![0u8; n]), SmcValue::Bytes(_));
+        let size = size as usize;
+        if data_type.starts_with('{') { return Decoder::Struct; }
+        if !decodes(32) { return Decoder::UnknownType; }
+        if !decodes(size) { return Decoder::TooShort; }
+        // Types that decode from no bytes at all (ch8*, hex_) take any length
+        if !decodes(0) && size > 1 && decodes(size - 1) { return Decoder::TooLong; }
+        Decoder::Decoded
+    }
+
+    fn as_str(self) -> &'static str {
+        match self { Decoder::Decoded => "ok", Decoder::UnknownType => "unknown-type", Decoder::TooShort => "too-short", Decoder::TooLong => "too-long", Decoder::Struct => "struct" }
+    }
+}
+
+#[derive(Default)]
+struct TypeStats {
+    keys: u32,
+    read_ok: u32,
+    read_failed: u32,
+    // Declared without the read attribute, so never read
+    not_read: u32,
+    examples: Vec<String>,
+}
+
+pub struct Report {
+    key_count: usize,
+    skipped: Vec<String>,
+    types: BTreeMap<(String, u32), TypeStats>,
+    // (key, stage, error); stage is "info" or "read"
+    failures: Vec<(String, &'static str, String)>,
+}
+
+pub fn run(raw: &Raw) -> Result<Report, String> {
+    let scan = raw.keys()?;
+    let mut report = Report { key_count: scan.keys.len(), skipped: scan.skipped, types: BTreeMap::new(), failures: Vec::new() };
+    for key in &scan.keys {
+        let info = match smcraw::guarded(key, || raw.info(key)) {
+            Ok(i) => i,
+            Err(e) => { report.failures.push((key.clone(), "info", e)); continue; }
+        };
+        let stats = report.types.entry((info.data_type.clone(), info.size)).or_default();
+        stats.keys += 1;
+        if stats.examples.len() < EXAMPLES { stats.examples.push(key.clone()); }
+        if !info.attribute_names().contains(&"read") { stats.not_read += 1; continue; }
+        // read_with refuses sizes and types no read can hold before touching the driver
+        match smcraw::guarded(key, || raw.read_with(key, SmcValue::decode)) {
+            Ok(_) => stats.read_ok += 1,
+            Err(e) => { stats.read_failed += 1; report.failures.push((key.clone(), "read", e)); }
+        }
+    }
+    Ok(report)
+}
+
+impl Report {
+    fn gaps(&self) -> usize {
+        self.types.keys().filter(|(t, s)| !matches!(Decoder::of(t, *s), Decoder::Decoded | Decoder::Struct)).count()
+    }
+
+    pub fn text(&self) -> String {
+        let mut out = format!("{} keys, {} type/size pairs, {} the decoder doesn't handle\n", self.key_count, self.types.len(), self.gaps());
+        for e in &self.skipped { out.push_str(&format!("skipped: {}\n", e)); }
+        out.push_str(&format!("\n{:<6} {:>4} {:>5} {:>5} {:>6} {:>8}  {:<12}  examples\n", "type", "size", "keys", "read", "failed", "not read", "decoder"));
+        for ((t, size), s) in &self.types {
+            out.push_str(&format!("{:<6} {:>4} {:>5} {:>5} {:>6} {:>8}  {:<12}  {}\n", format!("{:?}", t), size, s.keys, s.read_ok, s.read_failed, s.not_read, Decoder::of(t, *size).as_str(), s.examples.join(" ")));
+        }
+        if !self.failures.is_empty() {
+            out.push_str(&format!("\n{} failures:\n", self.failures.len()));
+            for (key, stage, e) in &self.failures { out.push_str(&format!("{}  {:<4}  {}\n", key, stage, e)); }
+        }
+        out
+    }
+
+    pub fn to_json(&self, model: &str, chip: &str) -> String {
+        let strs = |v: &[String]| v.iter().map(|s| crate::json_str(s)).collect::<Vec<_>>().join(",");
+        let types = self.types.iter().map(|((t, size), s)| format!("{{\"data_type\":{},\"size\":{},\"decoder\":\"{}\",\"keys\":{},\"read_ok\":{},\"read_failed\":{},\"not_read\":{},\"examples\":[{}]}}",
+            crate::json_str(t), size, Decoder::of(t, *size).as_str(), s.keys, s.read_ok, s.read_failed, s.not_read, strs(&s.examples))).collect::<Vec<_>>().join(",");
+        let failures = self.failures.iter().map(|(k, stage, e)| format!("{{\"key\":{},\"stage\":\"{}\",\"error\":{}}}", crate::json_str(k), stage, crate::json_str(e))).collect::<Vec<_>>().join(",");
+        format!("{{\"type\":\"scan_safety\",\"version\":\"{}\",\"model\":{},\"chip\":{},\"keys\":{},\"skipped\":[{}],\"types\":[{}],\"failures\":[{}]}}",
+            env!("CARGO_PKG_VERSION"), crate::json_str(model), crate::json_str(chip), self.key_count, strs(&self.skipped), types, failures)
+    }
+}
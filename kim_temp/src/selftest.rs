@@ -0,0 +1,104 @@
+// Startup cross-check of redundant power sources, run once when `stream` starts so a broken
+// profile shows up in the first sample instead of after hours of logging:
+//   system rail (PSTR) >= SoC power from powermetrics (CPU + GPU + ANE)
+//   on battery: battery rail (PPBR) ~ gas-gauge discharge (voltage × current), and >= system rail
+// Long-running streams repeat it periodically together with a re-check of the CPU/GPU rails
+// (revalidate), since macOS updates have moved keys under running profiles.
+
+use crate::json_num;
+use crate::profile::Profile;
+use crate::sample::Powermetrics;
+
+const READS: usize = 5;
+const READ_GAP_MS: u64 = 40;
+// Disagreement allowed between two sources: whichever is larger of an absolute and a relative margin
+const TOLERANCE_W: f64 = 1.5;
+const TOLERANCE_PCT: f64 = 0.20;
+
+fn disagree(a: f64, b: f64) -> bool {
+    (a - b).abs() > TOLERANCE_W.max(TOLERANCE_PCT * a.abs().max(b.abs()))
+}
+
+// Averages a few reads of `key`, since the rails are sampled at slightly different moments.
+fn read_avg(key: &str) -> Option<f64> {
+    let mut values = Vec::new();
+    for i in 0..READS {
+        if i > 0 { std::thread::sleep(std::time::Duration::from_millis(READ_GAP_MS)); }
+        values.extend(crate::value::read_f64(key));
+    }
+    if values.is_empty() { None } else { Some(values.iter().sum::<f64>() / values.len() as f64) }
+}
+
+// Returns a `self_test` event; every disagreement is also printed to stderr.
+// `pm` is None when the caller can't (or wasn't asked to) run sudo powermetrics.
+pub fn run(profile: &Profile, has_battery: bool, pm: Option<&Powermetrics>) -> String {
+    check(profile, has_battery, pm).0
+}
+
+// The self-test event and whether it passed.
+fn check(profile: &Profile, has_battery: bool, pm: Option<&Powermetrics>) -> (String, bool) {
+    let soc_w = pm.map(|pm| (pm.cpu_mw + pm.gpu_mw + pm.ane_mw) as f64 / 1000.0).filter(|w| *w > 0.0);
+    let system_w = read_avg(&profile.system_power_key);
+    let discharging = has_battery && !crate::battery::pmset_status().1;
+    let battery_w = if discharging { read_avg(&profile.battery_power_key) } else { None };
+    let gauge_w = if discharging { crate::battery::discharge_w() } else { None };
+
+    let mut warnings = Vec::new();
+    if let (Some(sys), Some(soc)) = (system_w, soc_w) {
+        if soc > sys && disagree(sys, soc) {
+            warnings.push(format!("{} reads {:.2} W but CPU+GPU+ANE alone draw {:.2} W; the system power key is probably wrong for this machine", profile.system_power_key, sys, soc));
+        }
+    }
+    if let (Some(bat), Some(gauge)) = (battery_w, gauge_w) {
+        if disagree(bat, gauge) {
+            warnings.push(format!("{} reads {:.2} W but the battery gauge reports {:.2} W of discharge", profile.battery_power_key, bat, gauge));
+        }
+    }
+    if let (Some(sys), Some(bat)) = (system_w, battery_w) {
+        if sys > bat && disagree(sys, bat) {
+            warnings.push(format!("{} ({:.2} W) is above the battery draw {} ({:.2} W) while on battery", profile.system_power_key, sys, profile.battery_power_key, bat));
+        }
+    }
+    if system_w.is_none() { warnings.push(format!("{} is not readable", profile.system_power_key)); }
+    for w in &warnings { crate::diag::warn!("self-test: {}", w); }
+
+    // Gauge minus battery rail: what a calibration would have to add to the rail
+    let offset = battery_w.zip(gauge_w).map(|(b, g)| g - b);
+    (format!("{{\"type\":\"self_test\",\"ok\":{},\"system_w\":{},\"soc_w\":{},\"battery_w\":{},\"gauge_w\":{},\"battery_offset_w\":{},\"warnings\":[{}]}}",
+        warnings.is_empty(), json_num(system_w, 2), json_num(soc_w, 2), json_num(battery_w, 2), json_num(gauge_w, 2), json_num(offset, 2),
+        warnings.iter().map(|w| crate::json_str(w)).collect::<Vec<_>>().join(",")), warnings.is_empty())
+}
+
+// Periodic re-check: the self-test plus, for the CPU and GPU rails, whether validation still
+// picks the key in use and whether it agrees with powermetrics' own CPU/GPU power.
+// Returns a `profile_check` event; `ok` is false on any drift.
+pub fn revalidate(profile: &Profile, has_battery: bool, pm: Option<&Powermetrics>) -> String {
+    let (self_test, self_test_ok) = check(profile, has_battery, pm);
+    let mut drift = !self_test_ok;
+    let mut rails = Vec::new();
+    for (rail, in_use, candidates, reference_mw) in [
+        ("cpu", &profile.cpu_power_key, profile.cpu_power_keys, pm.map(|p| p.cpu_mw)),
+        ("gpu", &profile.gpu_power_key, profile.gpu_power_keys, pm.map(|p| p.gpu_mw)),
+    ] {
+        let (selected, checks) = crate::power::select(candidates, &profile.system_power_key);
+        let value_w = read_avg(in_use);
+        let reference_w = reference_mw.map(|mw| mw as f64 / 1000.0);
+        let mut problems = Vec::new();
+        match checks.iter().find(|c| c.key == in_use.as_str()) {
+            Some(c) if !c.ok => problems.push(format!("{} now fails validation: {}", in_use, c.reason)),
+            _ => {}
+        }
+        if let Some(k) = selected.filter(|k| *k != in_use.as_str()) { problems.push(format!("validation now picks {} instead of {}", k, in_use)); }
+        if let (Some(v), Some(r)) = (value_w, reference_w) {
+            if disagree(v, r) { problems.push(format!("{} reads {:.2} W but powermetrics reports {:.2} W", in_use, v, r)); }
+        }
+        for p in &problems { crate::diag::warn!("profile check: {} rail: {}", rail, p); }
+        drift |= !problems.is_empty();
+        rails.push(format!("{{\"rail\":\"{}\",\"key\":\"{}\",\"value_w\":{},\"reference_w\":{},\"selected\":{},\"problems\":[{}]}}",
+            rail, in_use, json_num(value_w, 2), json_num(reference_w, 2), selected.map(|k| format!("\"{}\"", k)).unwrap_or_else(|| "null".to_string()),
+            problems.iter().map(|p| crate::json_str(p)).collect::<Vec<_>>().join(",")));
+    }
+    if drift { crate::diag::warn!("power keys may have drifted; run `kim_temp profile` to re-check, and set [power_keys] in the config if needed"); }
+    format!("{{\"type\":\"profile_check\",\"ok\":{},\"rails\":[{}],\"self_test\":{}{}}}", !drift, rails.join(","), self_test,
+        if drift { ",\"suggest\":\"kim_temp profile\"" } else { "" })
+}
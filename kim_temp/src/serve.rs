@@ -0,0 +1,374 @@
+// `serve`: one sampler process owns the SMC connection and the powermetrics feed, and `cpu`,
+// `gpu`, `battery`, `memory`, `ssd`, `power` and `json` invocations ask it over a unix socket
+// instead of opening the SMC themselves, so any number of status-bar widgets polling every
+// second share one sampler. Run it as root (powermetrics without a password, and /var/run is
+// root's). The protocol only reads, but its answers include every user's process names and
+// wakeups, so the socket is root's and one group's (--socket-group, by default the Mac's admins);
+// anyone else can't connect and reads the SMC locally, as without a server:
+//   request  one line, the client's mode and flags: `json --fields cpu_temp,power_w --units fahrenheit`
+//   reply    `ok AGE_MS`, a newline and exactly what the mode would print; or `err MESSAGE`
+// `powermetrics` is answered with the raw output of a powermetrics run (at most a TTL old), so
+// `json` and `stream` get their power and process fields from a root server instead of `sudo`,
+// and parse it with their own excluded processes. `install-daemon --serve` runs the server as a
+// LaunchDaemon.
+// The server samples on demand: a request reuses the cached sample while it is younger than the
+// TTL, so ten clients polling at 1 Hz cost one SMC sweep a second, and none while nobody asks.
+// AGE_MS is how old the answered sample is (`json` also carries it as `cache_age_ms`).
+// A client falls back to reading the SMC itself when nothing listens on the socket, the server
+// can't answer (`battery` on a desktop, no sample yet), a flag isn't one the server handles, or
+// --no-daemon is given, so a widget works the same with or without the server.
+// `--ws ADDR` also pushes the samples to WebSocket clients (ws.rs), each asking with the same
+// request, written as a URL. `--http ADDR` answers polling clients with JSON (see `rest`), and
+// `--grpc ADDR` serves the same snapshots to generated clients (grpc.rs).
+
+use crate::config::Units;
+use crate::http::{self, Params, Request};
+use crate::profile::Profile;
+use crate::reading::{self, Reading};
+use crate::sample::{self, Fields};
+use crate::temps;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_SOCKET: &str = "/var/run/kim_temp.sock";
+// Whose users' widgets may connect: `staff` for every local user
+pub const DEFAULT_GROUP: &str = "admin";
+// The modes the server answers, and the flags it understands (each value-taking one with its value)
+pub const MODES: &[&str] = &["cpu", "gpu", "battery", "memory", "ssd", "power", "json"];
+const FLAGS: &[&str] = &["--split", "--detail"];
+const VALUE_FLAGS: &[&str] = &["--fields", "--units"];
+const TIMEOUT: Duration = Duration::from_secs(2);
+// What `--http`'s /power answers with; /temps and /battery use the stream's own groups
+const POWER_FIELDS: &[&str] = &["power_w", "power_raw_w", "bat_power_w", "mem_power_w", "cpu_mw", "gpu_mw", "ane_mw", "screen_w", "dominant"];
+
+// The network listeners besides the unix socket
+#[derive(Default)]
+pub struct Listeners {
+    pub ws: Option<crate::ws::Options>,
+    pub http: Option<Http>,
+    #[cfg(feature = "grpc")]
+    pub grpc: Option<std::net::SocketAddr>,
+}
+
+pub struct Http {
+    pub addr: std::net::SocketAddr,
+    // Some: every request needs `Authorization: Bearer TOKEN`
+    pub token: Option<String>,
+    // --http-origin: browser origins allowed to read the answers besides the local ones; "*" for any
+    pub origins: Vec<String>,
+}
+
+// `--socket`, else $KIM_TEMP_SOCKET, else DEFAULT_SOCKET.
+pub fn socket_path(flag: Option<&str>) -> PathBuf {
+    flag.map(PathBuf::from).or_else(|| std::env::var_os("KIM_TEMP_SOCKET").map(PathBuf::from)).unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET))
+}
+
+// Client side: what to print, or None when the request has to be served locally. `--socket` and
+// its value are left out of the request.
+pub fn query(path: &Path, mode: &str, flags: &[String], units: Units) -> Option<String> {
+    let mut request: Vec<&str> = vec![mode];
+    let mut words = flags.iter().map(String::as_str);
+    while let Some(w) = words.next() {
+        match w {
+            "--socket" => { words.next(); }
+            w if VALUE_FLAGS.contains(&w) => { request.push(w); request.push(words.next()?); }
+            w if w.starts_with("--") && !FLAGS.contains(&w) => return None,
+            w => request.push(w),
+        }
+    }
+    if !MODES.contains(request.first()?) { return None; }
+    let agg = temps::aggregation();
+    if agg.method != temps::Method::Mean || agg.reject_sigma.is_some() { return None; }
+    // The client's config decides the unit, not the server's
+    let units_flag = ["--units", units.as_str()];
+    if !request.contains(&"--units") { request.extend(units_flag); }
+    ask(path, &request.join(" ")).map(|(_, text)| text)
+}
+
+// Raw powermetrics output from the server and when that run ended, or None without a server.
+pub fn powermetrics_output() -> Option<(String, SystemTime)> {
+    let (age, text) = ask(&socket_path(None), "powermetrics")?;
+    Some((text, SystemTime::now() - age))
+}
+
+// One request and the answered text with its age; None for no server or an `err` reply.
+fn ask(path: &Path, request: &str) -> Option<(Duration, String)> {
+    // No server is the usual case, so only trace says so
+    let mut stream = UnixStream::connect(path).map_err(|e| crate::diag::trace!("no server on {}: {}", path.display(), e)).ok()?;
+    let mut reply = String::new();
+    let exchange = stream.set_read_timeout(Some(TIMEOUT)).and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .and_then(|_| writeln!(stream, "{}", request)).and_then(|_| stream.read_to_string(&mut reply));
+    if let Err(e) = exchange { crate::diag::debug!("server on {} stopped answering {:?}, sampling here: {}", path.display(), request, e); return None; }
+    let (status, text) = reply.split_once('\n')?;
+    let Some(age) = status.strip_prefix("ok ").and_then(|age| age.parse::<u64>().ok()) else {
+        crate::diag::debug!("server on {} answered {:?} with {:?}", path.display(), request, status);
+        return None;
+    };
+    Some((Duration::from_millis(age), text.to_string()))
+}
+
+// What a server holds between requests: the last sample and the last powermetrics output
+// (with when that run ended), each with when it was taken
+#[derive(Default)]
+struct Cache {
+    reading: Option<(Instant, Reading)>,
+    pm: Option<(Instant, String, SystemTime)>,
+}
+
+struct Sampler {
+    profile: Profile,
+    sensors: Vec<crate::temps::Sensor>,
+    derived: Vec<crate::derived::Derived>,
+    excluded: Vec<String>,
+    // Everything, so any client's --fields and --detail can be answered
+    fields: Fields,
+    ttl: Duration,
+    cache: Mutex<Cache>,
+}
+
+impl Sampler {
+    // Runs powermetrics here (never through another server) when the cached output is older
+    // than `max_age`. A failed run leaves nothing cached, so the next request tries again.
+    fn refresh_pm(&self, cache: &mut Cache, max_age: Duration) {
+        if cache.pm.as_ref().is_none_or(|(at, _, _)| at.elapsed() >= max_age) {
+            cache.pm = sample::powermetrics_output().map(|(output, ended)| (Instant::now(), output, ended));
+        }
+    }
+
+    // The cached sample and its age, sampling first when it is older than the TTL. The lock is
+    // held while sampling, so clients arriving meanwhile wait for that sample instead of
+    // starting their own. powermetrics is rerun at most every 5 TTLs, as `stream` does every
+    // 5th sample.
+    fn latest(&self) -> Result<(std::sync::MutexGuard<'_, Cache>, Duration), String> {
+        let mut cache = self.cache.lock().map_err(|_| "a sample panicked".to_string())?;
+        if cache.reading.as_ref().is_none_or(|(at, _)| at.elapsed() >= self.ttl) {
+            self.refresh_pm(&mut cache, self.ttl * 5);
+            let excluded: Vec<&str> = self.excluded.iter().map(String::as_str).collect();
+            let pm = cache.pm.as_ref().map(|(_, output, ended)| sample::parse_powermetrics(output, *ended, &excluded)).unwrap_or_default();
+            cache.reading = Some((Instant::now(), reading::collect(&self.profile, &self.sensors, &self.fields, true, pm, &self.derived)));
+        }
+        let age = cache.reading.as_ref().map(|(at, _)| at.elapsed()).unwrap_or_default();
+        Ok((cache, age))
+    }
+}
+
+// The reply text for one request line, and the age of the sample it came from.
+fn respond(request: &str, sampler: &Sampler) -> Result<(String, Duration), String> {
+    let words: Vec<&str> = request.split_whitespace().collect();
+    if words.first() == Some(&"powermetrics") {
+        let mut cache = sampler.cache.lock().map_err(|_| "a sample panicked".to_string())?;
+        sampler.refresh_pm(&mut cache, sampler.ttl);
+        let (_, output, ended) = cache.pm.as_ref().ok_or("powermetrics didn't run")?;
+        return Ok((output.clone(), SystemTime::now().duration_since(*ended).unwrap_or_default()));
+    }
+    let value = |name: &str| words.windows(2).find(|w| w[0] == name).map(|w| w[1]);
+    let units = match value("--units") {
+        Some(u) => Units::parse(u).ok_or_else(|| format!("unknown units {}", u))?,
+        None => Units::Celsius,
+    };
+    let (mut cache, age) = sampler.latest()?;
+    let Some((_, r)) = cache.reading.as_mut() else { return Err("no sample".to_string()) };
+    // Unweighted, as the local modes print (a server always aggregates by the default mean; a
+    // client with --agg or --reject-sigma samples locally, see `query`)
+    let avg = |v: &[(f64, f64)]| temps::plain(v).map_or("N/A".to_string(), |t| format!("{:.1}", units.temp(t)));
+    let text = match words.first().copied() {
+        Some("cpu") if words.contains(&"--split") => format!("P {} E {}", avg(&r.temps.cpu_p), avg(&r.temps.cpu_e)),
+        // Without classified CPU sensors, every temperature sensor (as `cpu` does locally)
+        Some("cpu") if r.temps.cpu.is_empty() => avg(&r.temps.sensors.iter().map(|(_, t, _, _)| (*t, 1.0)).collect::<Vec<_>>()),
+        Some("cpu") => avg(&r.temps.cpu),
+        Some("gpu") => avg(&r.temps.gpu),
+        Some("battery") if !r.has_battery() => return Err("no battery".to_string()),
+        Some("battery") => avg(&r.temps.bat),
+        Some("memory") => avg(&r.temps.mem),
+        Some("ssd") => avg(&r.temps.ssd),
+        Some("power") => r.power_w().map_or("N/A".to_string(), |w| format!("{:.2}", w)),
+        Some("json") => {
+            let fields = Fields::parse(value("--fields"))?;
+            r.json(&fields, units, words.contains(&"--detail"), None, Some(age))
+        }
+        _ => return Err(format!("unsupported request {:?}", request)),
+    };
+    Ok((text + "\n", age))
+}
+
+// A network client's mode and URL parameters as a request line, checked before anything is
+// sampled: `json` with `?fields=cpu_temp,power_w&detail` is `json --fields cpu_temp,power_w --detail`.
+fn request_line(mode: &str, params: &[(String, Option<String>)]) -> Result<String, String> {
+    if !MODES.contains(&mode) { return Err(format!("unknown mode {:?} (one of {})", mode, MODES.join(", "))); }
+    let mut request = mode.to_string();
+    for (name, value) in params {
+        let flag = format!("--{}", name);
+        match value.as_deref() {
+            Some(v) if VALUE_FLAGS.contains(&flag.as_str()) && !v.is_empty() && !v.contains(char::is_whitespace) => {
+                if flag == "--fields" { Fields::parse(Some(v))?; }
+                if flag == "--units" { Units::parse(v).ok_or_else(|| format!("unknown units {}", v))?; }
+                request += &format!(" {} {}", flag, v);
+            }
+            None if FLAGS.contains(&flag.as_str()) => request += &format!(" {}", flag),
+            _ => return Err(format!("unsupported parameter {:?}", value.as_ref().map_or(name.clone(), |v| format!("{}={}", name, v)))),
+        }
+    }
+    Ok(request)
+}
+
+// A WebSocket client's mode is its path: `/json?fields=cpu_temp`, and `/` alone is `json`. Its
+// token, checked at the handshake, isn't a parameter of the request.
+fn subscription(request: &Request) -> Result<String, String> {
+    let mut params = request.params();
+    params.retain(|(n, _)| n != "access_token");
+    request_line(match request.path().trim_matches('/') { "" => "json", mode => mode }, &params)
+}
+
+// An `--http` request's parameters once it may be answered: a GET with the token, if there is
+// one, as a bearer header or as `?access_token=` (for EventSource, which can't send headers).
+fn authorize(request: &Request, token: Option<&str>) -> Result<Params, (&'static str, String)> {
+    let mut params = request.params();
+    if !http::bearer(request, &mut params, token) { return Err(("401 Unauthorized", "missing or wrong bearer token".to_string())); }
+    if request.method != "GET" { return Err(("405 Method Not Allowed", "only GET".to_string())); }
+    Ok(params)
+}
+
+// `--http`: JSON bodies, errors as `{"error":"..."}` with a matching status.
+//   /snapshot   what `json` prints (every field, or ?fields=), a `Snapshot` in the library
+//   /temps      its temperature fields; /power its power fields; /battery its battery fields
+//               (404 without a battery). These and /snapshot take ?units= and ?detail.
+//   /keys/KEY   one SMC key read now, uncached: {"key","type","size","value","bytes"}
+//   /events     /snapshot every TTL as server-sent events (see `events`)
+fn rest(path: &str, mut params: Params, sampler: &Sampler) -> Result<String, (&'static str, String)> {
+    let group = match path {
+        "/snapshot" => None,
+        "/temps" => Some(sample::TEMP_FIELDS),
+        "/power" => Some(POWER_FIELDS),
+        "/battery" => Some(sample::BATTERY_FIELDS),
+        path => return match path.strip_prefix("/keys/") {
+            Some(key) => key_json(&crate::http::decode(key)),
+            None => Err(("404 Not Found", "endpoints: /snapshot, /temps, /power, /battery, /keys/KEY, /events".to_string())),
+        },
+    };
+    if let Some(group) = group {
+        if params.iter().any(|(n, _)| n == "fields") { return Err(("400 Bad Request", "only /snapshot takes fields".to_string())); }
+        params.push(("fields".to_string(), Some([group, &["temp_unit", "cache_age_ms"]].concat().join(","))));
+    }
+    let line = request_line("json", &params).map_err(|e| ("400 Bad Request", e))?;
+    if group == Some(sample::BATTERY_FIELDS) {
+        let (cache, _) = sampler.latest().map_err(|e| ("503 Service Unavailable", e))?;
+        if !cache.reading.as_ref().is_some_and(|(_, r)| r.has_battery()) { return Err(("404 Not Found", "no battery".to_string())); }
+    }
+    respond(&line, sampler).map(|(text, _)| text).map_err(|e| ("503 Service Unavailable", e))
+}
+
+// One SMC key read now, for /keys/KEY and gRPC's ReadKey; errors with an HTTP status.
+pub fn read_key(key: &str) -> Result<(crate::smcraw::KeyInfo, Vec<u8>), (&'static str, String)> {
+    if key.len() != 4 || !key.is_ascii() { return Err(("400 Bad Request", format!("{:?} isn't a 4-character SMC key", key))); }
+    let raw = crate::smcraw::shared().map_err(|e| ("503 Service Unavailable", e))?;
+    crate::smcraw::guarded(key, || raw.read(key)).map_err(|e| ("404 Not Found", e))
+}
+
+fn key_json(key: &str) -> Result<String, (&'static str, String)> {
+    let (info, bytes) = read_key(key)?;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("{{\"key\":{},\"type\":{},\"size\":{},\"value\":{},\"bytes\":\"{}\"}}\n", crate::json_str(key), crate::json_str(info.data_type.trim()), info.size,
+        crate::value::SmcValue::from_info(&info, &bytes).to_json(), hex))
+}
+
+// The snapshot every TTL, one `data:` line each, until the client goes away; a sample that fails
+// is an `error` event instead. An EventSource reconnects by itself after a dropped connection.
+fn events(mut conn: TcpStream, sampler: &Sampler, cors: &str, line: &str) {
+    let _ = conn.set_write_timeout(Some(TIMEOUT));
+    let head = format!("HTTP/1.1 200 OK\r\n{}Content-Type: text/event-stream\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n", cors);
+    if conn.write_all(head.as_bytes()).is_err() { return; }
+    let mut event = String::new();
+    loop {
+        let started = Instant::now();
+        event.clear();
+        match respond(line, sampler) {
+            Ok((text, _)) => event += &format!("data: {}\n\n", text.trim_end()),
+            Err(e) => event += &format!("event: error\ndata: {}\n\n", crate::json_str(&e)),
+        }
+        if conn.write_all(event.as_bytes()).is_err() { return; }
+        std::thread::sleep(sampler.ttl.saturating_sub(started.elapsed()));
+    }
+}
+
+fn answer_http(mut conn: TcpStream, sampler: &Sampler, access: &Http) {
+    let Some(request) = Request::read(&conn) else { return };
+    // A browser page may read the answer only when its origin comes back in this header
+    let cors = request.header("origin").filter(|o| http::allowed_origin(o, &access.origins)).map_or(String::new(), |o| format!("Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n", o));
+    let host = request.header("host");
+    let answer = if http::allowed_host(host, access.addr) { authorize(&request, access.token.as_deref()) } else { Err(("403 Forbidden", format!("host {} isn't this server", host.unwrap_or("")))) };
+    let answer = answer.and_then(|params| match request.path() {
+        "/events" => request_line("json", &params).map(Err).map_err(|e| ("400 Bad Request", e)),
+        path => rest(path, params, sampler).map(Ok),
+    });
+    match answer {
+        Ok(Ok(body)) => http::reply(&mut conn, "200 OK", &cors, "application/json", &body),
+        Ok(Err(line)) => events(conn, sampler, &cors, &line),
+        Err((status, e)) => {
+            let challenge = if status.starts_with("401") { "WWW-Authenticate: Bearer\r\n" } else { "" };
+            http::reply(&mut conn, status, &(cors + challenge), "application/json", &format!("{{\"error\":{}}}\n", crate::json_str(&e)));
+        }
+    }
+}
+
+fn answer(conn: UnixStream, sampler: &Sampler) {
+    let _ = conn.set_read_timeout(Some(TIMEOUT));
+    let _ = conn.set_write_timeout(Some(TIMEOUT));
+    let mut line = String::new();
+    if BufReader::new(&conn).read_line(&mut line).is_err() { return; }
+    let reply = match respond(line.trim(), sampler) { Ok((text, age)) => format!("ok {}\n{}", age.as_millis(), text), Err(e) => format!("err {}\n", e) };
+    let _ = (&conn).write_all(reply.as_bytes());
+}
+
+fn group_id(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    (!group.is_null()).then(|| unsafe { (*group).gr_gid })
+}
+
+// The server's socket, for root and `group` only. Fails when it can't be bound or another server
+// holds it.
+pub fn bind(path: &Path, group: &str) -> Result<UnixListener, String> {
+    if UnixStream::connect(path).is_ok() { return Err(format!("another server is already listening on {}", path.display())); }
+    // A socket file left by a server that died
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path).map_err(|e| format!("cannot listen on {}: {}", path.display(), e))?;
+    use std::os::unix::fs::PermissionsExt;
+    let gid = group_id(group).ok_or_else(|| format!("no group {:?} (--socket-group)", group))?;
+    std::os::unix::fs::chown(path, None, Some(gid)).map_err(|e| format!("cannot give {} to group {}: {}", path.display(), group, e))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660)).map_err(|e| format!("cannot make {} accessible: {}", path.display(), e))?;
+    eprintln!("serve: listening on {} for root and group {}", path.display(), group);
+    Ok(listener)
+}
+
+// Answers clients until killed, sampling for them at most once per `ttl`.
+pub fn run(listener: UnixListener, profile: Profile, sensors: Vec<crate::temps::Sensor>, derived: Vec<crate::derived::Derived>, excluded: Vec<String>, ttl: Duration, listeners: Listeners) -> Result<(), String> {
+    let sampler = Arc::new(Sampler { profile, sensors, derived, excluded, fields: Fields::parse(None)?, ttl, cache: Mutex::default() });
+    eprintln!("serve: samples cached for {} ms", ttl.as_millis());
+    if let Some(ws) = listeners.ws {
+        let (sampler, addr, open) = (Arc::clone(&sampler), ws.addr, ws.token.is_none());
+        crate::ws::spawn(ws, ttl, move |request| respond(&subscription(request)?, &sampler).map(|(text, _)| text))?;
+        if open && !addr.ip().is_loopback() { crate::diag::warn!("serve --ws {} has no token: anyone who can reach it reads this Mac's sensors and processes (set KIM_TEMP_WS_TOKEN)", addr); }
+    }
+    if let Some(access) = listeners.http {
+        let (sampler, addr, open) = (Arc::clone(&sampler), access.addr, access.token.is_none());
+        http::listen(addr, move |conn| answer_http(conn, &sampler, &access))?;
+        eprintln!("serve: HTTP on http://{}/{}", addr, if open { "" } else { " (bearer token required)" });
+        if open && !addr.ip().is_loopback() { crate::diag::warn!("serve --http {} has no token: anyone who can reach it reads this Mac's sensors and processes (set KIM_TEMP_HTTP_TOKEN)", addr); }
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = listeners.grpc {
+        let sampler = Arc::clone(&sampler);
+        crate::grpc::spawn(addr, ttl, move |params| rest("/snapshot", params, &sampler))?;
+        if !addr.ip().is_loopback() { crate::diag::warn!("serve --grpc {} has no authentication: anyone who can reach it reads this Mac's sensors and processes", addr); }
+    }
+    for conn in listener.incoming() {
+        let Ok(conn) = conn else { continue };
+        let sampler = Arc::clone(&sampler);
+        std::thread::spawn(move || answer(conn, &sampler));
+    }
+    Ok(())
+}
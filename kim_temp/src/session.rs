@@ -0,0 +1,150 @@
+// End-of-session summary for `stream`: Ctrl-C (or SIGTERM) finishes the current sample, prints
+// one summary object and exits 0 instead of killing the loop mid-write.
+
+use crate::json_num;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+// The powermetrics (or sudo) child running right now, 0 for none. Ctrl-C reaches it through the
+// terminal's process group, but launchd's SIGTERM only reaches us, and an immediate exit would
+// leave it running as an orphan.
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+pub fn track_child(pid: u32) { CHILD.store(pid as i32, Ordering::SeqCst); }
+pub fn untrack_child() { CHILD.store(0, Ordering::SeqCst); }
+
+// sudo relays SIGTERM to the powermetrics under it
+fn stop_child() {
+    let pid = CHILD.swap(0, Ordering::SeqCst);
+    if pid > 0 { unsafe { libc::kill(pid, libc::SIGTERM) }; }
+}
+
+// First signal stops the running child and asks the loop to stop; a second one exits immediately
+// (still resuming anything the escalation stage stopped).
+extern "C" fn on_signal(sig: libc::c_int) {
+    stop_child();
+    if SIGNAL.swap(sig, Ordering::SeqCst) != 0 {
+        crate::escalation::resume_stopped();
+        unsafe { libc::_exit(128 + sig) };
+    }
+}
+
+// Replaces the escalation exit guard for SIGINT/SIGTERM; the stream loop resumes stopped
+// processes itself once it sees the signal.
+pub fn install() {
+    for sig in [libc::SIGINT, libc::SIGTERM] {
+        unsafe { libc::signal(sig, on_signal as *const () as libc::sighandler_t) };
+    }
+}
+
+// Ends the session as a signal would, e.g. SIGPIPE once stdout's reader is gone.
+pub fn stop(sig: i32) {
+    let _ = SIGNAL.compare_exchange(0, sig, Ordering::SeqCst, Ordering::SeqCst);
+}
+
+pub fn interrupted() -> Option<i32> {
+    Some(SIGNAL.load(Ordering::SeqCst)).filter(|s| *s != 0)
+}
+
+// Sleeps in short slices so a signal ends the wait promptly (std's sleep restarts on EINTR).
+pub fn sleep(total: Duration) {
+    let end = Instant::now() + total;
+    while interrupted().is_none() {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() { break; }
+        std::thread::sleep(left.min(Duration::from_millis(50)));
+    }
+}
+
+#[derive(Default)]
+struct Stat {
+    sum: f64,
+    n: u32,
+    peak: Option<f64>,
+}
+
+impl Stat {
+    fn add(&mut self, v: f64) {
+        let Some(v) = crate::diag::finite(v, "summary sample") else { return };
+        self.sum += v;
+        self.n += 1;
+        self.peak = Some(self.peak.map_or(v, |p| p.max(v)));
+    }
+
+    fn to_json(&self, prec: usize) -> String {
+        let avg = if self.n > 0 { Some(self.sum / self.n as f64) } else { None };
+        format!("{{\"avg\":{},\"peak\":{}}}", json_num(avg, prec), json_num(self.peak, prec))
+    }
+}
+
+pub struct Summary {
+    start: Instant,
+    last: Option<Instant>,
+    samples: u64,
+    energy_j: f64,
+    power: Stat,
+    cpu_temp: Stat,
+    gpu_temp: Stat,
+    battery_start: Option<i32>,
+    battery_end: Option<i32>,
+    // process -> (wakeups/s summed over powermetrics samples, samples seen in)
+    wakeups: HashMap<String, (f64, u32)>,
+    pm_samples: u32,
+    // Dropped by --drop-on-backpressure
+    dropped: u64,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary {
+            start: Instant::now(), last: None, samples: 0, energy_j: 0.0,
+            power: Stat::default(), cpu_temp: Stat::default(), gpu_temp: Stat::default(),
+            battery_start: None, battery_end: None, wakeups: HashMap::new(), pm_samples: 0, dropped: 0,
+        }
+    }
+
+    // Energy integrates system power over the time since the previous sample.
+    pub fn sample(&mut self, power_w: f64, cpu_temp: Option<f64>, gpu_temp: Option<f64>, battery_pct: Option<i32>) {
+        let now = Instant::now();
+        if let Some(last) = self.last.filter(|_| power_w.is_finite()) { self.energy_j += power_w * now.duration_since(last).as_secs_f64(); }
+        self.last = Some(now);
+        self.samples += 1;
+        self.power.add(power_w);
+        if let Some(t) = cpu_temp { self.cpu_temp.add(t); }
+        if let Some(t) = gpu_temp { self.gpu_temp.add(t); }
+        if let Some(p) = battery_pct {
+            self.battery_start.get_or_insert(p);
+            self.battery_end = Some(p);
+        }
+    }
+
+    // Called once per fresh powermetrics run (not for the cached copies in between).
+    pub fn wakeups(&mut self, offenders: &[(String, f64)]) {
+        self.pm_samples += 1;
+        for (name, w) in offenders {
+            let e = self.wakeups.entry(name.clone()).or_insert((0.0, 0));
+            e.0 += w;
+            e.1 += 1;
+        }
+    }
+
+    pub fn dropped(&mut self, samples: u64) {
+        self.dropped = samples;
+    }
+
+    pub fn to_json(&self, signal: i32) -> String {
+        let battery = match (self.battery_start, self.battery_end) {
+            (Some(a), Some(b)) => format!("{{\"start\":{},\"end\":{},\"delta\":{}}}", a, b, b - a),
+            _ => "null".to_string(),
+        };
+        // Averaged over every powermetrics sample, so a process that spiked once ranks below a steady offender
+        let mut worst: Vec<(&String, f64, u32)> = self.wakeups.iter().map(|(n, (sum, seen))| (n, sum / self.pm_samples.max(1) as f64, *seen)).collect();
+        worst.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let worst_json = worst.iter().take(5).map(|(n, avg, seen)| format!("{{\"name\":{},\"avg_wakeups\":{:.1},\"samples\":{}}}", crate::json_str(n), avg, seen)).collect::<Vec<_>>().join(",");
+        format!("{{\"type\":\"summary\",\"signal\":{},\"samples\":{},\"duration_s\":{:.1},\"power_w\":{},\"cpu_temp\":{},\"gpu_temp\":{},\"energy_j\":{:.1},\"energy_wh\":{:.4},\"battery_pct\":{},\"dropped_samples\":{},\"worst_wakeups\":[{}]}}",
+            signal, self.samples, self.start.elapsed().as_secs_f64(), self.power.to_json(2), self.cpu_temp.to_json(1), self.gpu_temp.to_json(1),
+            self.energy_j, self.energy_j / 3600.0, battery, self.dropped, worst_json)
+    }
+}
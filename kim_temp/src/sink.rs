@@ -0,0 +1,127 @@
+// Where json/stream lines go: stdout (raw, or through --template-file) plus any files
+// listed under [output] in the config, which always get the raw JSON.
+// When the reader of stdout goes away (`stream | head -5`), the write fails with EPIPE: stdout is
+// closed for good and a running stream ends as if interrupted, summary and all, instead of
+// panicking. With --drop-on-backpressure a stdout pipe is made non-blocking, and a sample that
+// doesn't fit because the reader has stopped reading is dropped instead of stalling the sampler.
+
+use std::fs::File;
+use std::io::Write;
+
+pub struct Sinks {
+    files: Vec<(String, File)>,
+    #[cfg(feature = "templates")]
+    template: Option<crate::template::Template>,
+    stdout_closed: bool,
+    backpressure: Option<Backpressure>,
+}
+
+// A non-blocking stdout that only ever writes whole lines
+#[derive(Default)]
+struct Backpressure {
+    // The rest of a line a full pipe cut short; finished before any new line starts
+    pending: Vec<u8>,
+    dropped: u64,
+}
+
+// write(2) on stdout; a full non-blocking pipe writes what fits, possibly nothing.
+fn write_some(b: &[u8]) -> std::io::Result<usize> {
+    if b.is_empty() { return Ok(0); }
+    let n = unsafe { libc::write(libc::STDOUT_FILENO, b.as_ptr() as *const libc::c_void, b.len()) };
+    if n >= 0 { return Ok(n as usize); }
+    let e = std::io::Error::last_os_error();
+    match e.kind() { std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => Ok(0), _ => Err(e) }
+}
+
+impl Backpressure {
+    fn write(&mut self, parts: &[&[u8]]) -> std::io::Result<()> {
+        if !self.pending.is_empty() {
+            let n = write_some(&self.pending)?;
+            self.pending.drain(..n);
+            if !self.pending.is_empty() { self.dropped += 1; return Ok(()); }
+        }
+        for (i, part) in parts.iter().enumerate() {
+            let n = write_some(part)?;
+            if n < part.len() {
+                self.pending.extend_from_slice(&part[n..]);
+                for rest in &parts[i + 1..] { self.pending.extend_from_slice(rest); }
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sinks {
+    // Opens every path for appending; a path that can't be opened is reported and skipped.
+    pub fn open(paths: &[String]) -> Sinks {
+        let mut files = Vec::new();
+        for p in paths {
+            match std::fs::OpenOptions::new().create(true).append(true).open(crate::config::expand_home(p)) {
+                Ok(f) => files.push((p.clone(), f)),
+                Err(e) => crate::diag::warn!("cannot open output file {}: {}", p, e),
+            }
+        }
+        Sinks { files, #[cfg(feature = "templates")] template: None, stdout_closed: false, backpressure: None }
+    }
+
+    #[cfg(feature = "templates")]
+    pub fn set_template(&mut self, template: crate::template::Template) {
+        self.template = Some(template);
+    }
+
+    // Only for pipes and sockets: a terminal or file never pushes back, and O_NONBLOCK on a
+    // terminal would also land on the shell sharing it.
+    pub fn drop_on_backpressure(&mut self) {
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(libc::STDOUT_FILENO, &mut st) } != 0 { return; }
+        let kind = st.st_mode & libc::S_IFMT;
+        if kind != libc::S_IFIFO && kind != libc::S_IFSOCK { return; }
+        let _ = std::io::stdout().flush();
+        let flags = unsafe { libc::fcntl(libc::STDOUT_FILENO, libc::F_GETFL) };
+        if flags >= 0 && unsafe { libc::fcntl(libc::STDOUT_FILENO, libc::F_SETFL, flags | libc::O_NONBLOCK) } == 0 {
+            self.backpressure = Some(Backpressure::default());
+        }
+    }
+
+    // Samples --drop-on-backpressure has dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.backpressure.as_ref().map_or(0, |b| b.dropped)
+    }
+
+    fn stdout(&mut self, parts: &[&[u8]]) {
+        if self.stdout_closed { return; }
+        let result = match &mut self.backpressure {
+            Some(b) => b.write(parts),
+            None => { let mut out = std::io::stdout().lock(); parts.iter().try_for_each(|p| out.write_all(p)).and_then(|_| out.flush()) }
+        };
+        if let Err(e) = result {
+            self.stdout_closed = true;
+            if e.kind() != std::io::ErrorKind::BrokenPipe { crate::diag::warn!("cannot write to stdout, stopping: {}", e); }
+            crate::session::stop(libc::SIGPIPE);
+        }
+    }
+
+    pub fn emit(&mut self, line: &str) {
+        #[cfg(feature = "templates")]
+        match self.template.as_ref().map(|t| t.render(line)) {
+            Some(Some(text)) => self.stdout(&[text.trim_end_matches('\n').as_bytes(), b"\n"]),
+            Some(None) => {}
+            None => self.stdout(&[line.as_bytes(), b"\n"]),
+        }
+        #[cfg(not(feature = "templates"))]
+        self.stdout(&[line.as_bytes(), b"\n"]);
+        self.files.retain_mut(|(p, f)| match writeln!(f, "{}", line) {
+            Ok(()) => true,
+            Err(e) => { crate::diag::warn!("dropping output file {}: {}", p, e); false }
+        });
+    }
+
+    // At shutdown: the output files are synced to disk, so the last samples survive a power-off.
+    pub fn flush(&mut self) {
+        if self.backpressure.is_none() && !self.stdout_closed { let _ = std::io::stdout().flush(); }
+        for (p, f) in &mut self.files {
+            if let Err(e) = f.sync_data() { crate::diag::warn!("cannot flush output file {}: {}", p, e); }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+// Direct AppleSMC access: key enumeration, a key's type/size/attributes, its raw bytes (decoded
+// in value.rs), and writing (journaled, see journal.rs). Talks to the driver through IOKit only
+// (the struct layout of the classic smc.c), so the core path stays free of non-Apple dependencies.
+// Every read is checked against the key's declared type and size before its bytes are touched,
+// and scans skip keys that fail instead of stopping. Writing needs root. Other systems have no
+// AppleSMC: open() fails there, so the crate builds (and its parsers and formatters run) on any Unix.
+
+use std::collections::HashMap;
+#[cfg(target_os = "macos")]
+use std::os::raw::c_char;
+use std::os::raw::c_void;
+use std::sync::{Mutex, OnceLock};
+
+#[cfg(target_os = "macos")]
+#[allow(non_upper_case_globals)]
+extern "C" {
+    static mach_task_self_: u32;
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOServiceMatching(name: *const c_char) -> *mut c_void;
+    fn IOServiceGetMatchingService(main_port: u32, matching: *mut c_void) -> u32;
+    fn IOServiceOpen(service: u32, owning_task: u32, kind: u32, connect: *mut u32) -> i32;
+    fn IOServiceClose(connect: u32) -> i32;
+    fn IOObjectRelease(object: u32) -> i32;
+    fn IOConnectCallStructMethod(connect: u32, selector: u32, input: *const c_void, input_size: usize, output: *mut c_void, output_size: *mut usize) -> i32;
+}
+
+// Without a Raw these are never reached; they exist so the calls below compile everywhere.
+#[cfg(not(target_os = "macos"))]
+#[allow(non_snake_case)]
+unsafe fn IOServiceClose(_connect: u32) -> i32 {
+    0
+}
+
+#[cfg(not(target_os = "macos"))]
+#[allow(non_snake_case)]
+unsafe fn IOConnectCallStructMethod(_connect: u32, _selector: u32, _input: *const c_void, _input_size: usize, _output: *mut c_void, _output_size: *mut usize) -> i32 {
+    IO_RETURN_UNSUPPORTED
+}
+
+const KERNEL_INDEX_SMC: u32 = 2;
+const CMD_READ_BYTES: u8 = 5;
+const CMD_WRITE_BYTES: u8 = 6;
+const CMD_READ_INDEX: u8 = 8;
+const CMD_READ_KEYINFO: u8 = 9;
+const SMC_KEY_NOT_FOUND: u8 = 132;
+const IO_RETURN_NOT_PRIVILEGED: i32 = 0xe00002c1_u32 as i32;
+#[cfg(not(target_os = "macos"))]
+const IO_RETURN_UNSUPPORTED: i32 = 0xe00002c7_u32 as i32;
+
+#[derive(Default, Clone, Copy)]
+#[repr(C)]
+struct Version { major: u8, minor: u8, build: u8, reserved: u8, release: u16 }
+
+#[derive(Default, Clone, Copy)]
+#[repr(C)]
+struct PLimitData { version: u16, length: u16, cpu_plimit: u32, gpu_plimit: u32, mem_plimit: u32 }
+
+#[derive(Default, Clone, Copy)]
+#[repr(C)]
+struct KeyInfoData { data_size: u32, data_type: u32, data_attributes: u8 }
+
+// SMCKeyData_t, 80 bytes
+#[derive(Default, Clone, Copy)]
+#[repr(C)]
+struct Param {
+    key: u32,
+    vers: Version,
+    p_limit_data: PLimitData,
+    key_info: KeyInfoData,
+    result: u8,
+    status: u8,
+    data8: u8,
+    data32: u32,
+    bytes: [u8; 32],
+}
+
+fn fourcc(s: &str) -> Result<u32, String> {
+    let b = s.as_bytes();
+    if b.len() != 4 || !s.is_ascii() { return Err(format!("invalid SMC key {:?} (expected 4 ASCII characters)", s)); }
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn fourcc_str(v: u32) -> String {
+    String::from_utf8_lossy(&v.to_be_bytes()).to_string()
+}
+
+// Key names and data types are four printable ASCII characters; anything else is a garbled reply.
+fn printable(v: u32) -> bool {
+    v.to_be_bytes().iter().all(|b| (0x20..0x7f).contains(b))
+}
+
+// Runs `f` and turns a panic into an error naming `key`: the last line of defence for scans, where
+// one bad key must not take the whole sweep down with it.
+pub fn guarded<T>(key: &str, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|p| {
+        let msg = p.downcast_ref::<&str>().map(|s| s.to_string()).or_else(|| p.downcast_ref::<String>().cloned()).unwrap_or_default();
+        Err(format!("reading SMC key {} panicked: {}", key, msg))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyInfo {
+    pub size: u32,
+    // e.g. "flt ", "ui8 ", "sp78"
+    pub data_type: String,
+    pub attributes: u8,
+}
+
+// Attribute bits as the SMC firmware declares them (VirtualSMC's SMC_KEY_ATTRIBUTE_* names);
+// 0x20 has no known meaning.
+const ATTRIBUTES: &[(u8, &str)] = &[
+    (0x80, "read"), (0x40, "write"), (0x20, "0x20"), (0x10, "function"),
+    (0x08, "const"), (0x04, "atomic"), (0x02, "priv-read"), (0x01, "priv-write"),
+];
+
+// How many bytes a read returns; an error for a size or type no read can be decoded from.
+fn readable(key: &str, size: u32, data_type: &str) -> Result<usize, String> {
+    if data_type.len() != 4 || !data_type.bytes().all(|b| (0x20..0x7f).contains(&b)) { return Err(format!("SMC key {} has an unreadable type {:?}", key, data_type)); }
+    match size {
+        1..=32 => Ok(size as usize),
+        n => Err(format!("SMC key {} declares {} bytes of {:?} (a read holds 1 to 32)", key, n, data_type)),
+    }
+}
+
+impl KeyInfo {
+    fn readable(&self, key: &str) -> Result<usize, String> {
+        readable(key, self.size, &self.data_type)
+    }
+
+    // e.g. ["read", "write", "function"]
+    pub fn attribute_names(&self) -> Vec<&'static str> {
+        ATTRIBUTES.iter().filter(|(bit, _)| self.attributes & bit != 0).map(|(_, name)| *name).collect()
+    }
+}
+
+// KeyInfo as the cache holds it: plain numbers, so a cached lookup copies instead of allocating.
+#[derive(Clone, Copy)]
+struct Decl {
+    size: u32,
+    data_type: [u8; 4],
+    attributes: u8,
+}
+
+pub struct Raw {
+    conn: u32,
+    // Key info by name; a key's type and size never change while the machine is up
+    infos: Mutex<HashMap<String, Decl>>,
+}
+
+// One connection for the whole process, used by value::read.
+pub fn shared() -> Result<&'static Raw, String> {
+    static SHARED: OnceLock<Result<Raw, String>> = OnceLock::new();
+    SHARED.get_or_init(Raw::open).as_ref().map_err(|e| e.clone())
+}
+
+// Every key the SMC reports, in its order, plus one message per index that couldn't be read.
+pub struct Scan {
+    pub keys: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+// A key with its info already fetched, for reading the same keys every cycle.
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub name: String,
+    code: u32,
+    pub info: KeyInfo,
+}
+
+impl Raw {
+    #[cfg(target_os = "macos")]
+    pub fn open() -> Result<Raw, String> {
+        let service = unsafe { IOServiceGetMatchingService(0, IOServiceMatching(c"AppleSMC".as_ptr())) };
+        if service == 0 { return Err("AppleSMC driver not found".to_string()); }
+        let mut conn = 0;
+        let kr = unsafe { IOServiceOpen(service, mach_task_self_, 0, &mut conn) };
+        unsafe { IOObjectRelease(service) };
+        if kr != 0 { return Err(format!("cannot open AppleSMC (IOKit error {:#x})", kr)); }
+        Ok(Raw { conn, infos: Mutex::new(HashMap::new()) })
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn open() -> Result<Raw, String> {
+        Err("AppleSMC driver not found: the SMC is only on macOS (stream can run with --backend mock)".to_string())
+    }
+
+    fn call(&self, key: &str, input: &mut Param) -> Result<Param, String> {
+        input.key = fourcc(key)?;
+        self.call_code(key, input)
+    }
+
+    fn call_code(&self, key: &str, input: &Param) -> Result<Param, String> {
+        let mut output = Param::default();
+        self.call_into(key, input, &mut output)?;
+        Ok(output)
+    }
+
+    // `call_code` into a reply struct the caller keeps.
+    fn call_into(&self, key: &str, input: &Param, output: &mut Param) -> Result<(), String> {
+        let mut output_size = std::mem::size_of::<Param>();
+        let kr = unsafe {
+            IOConnectCallStructMethod(self.conn, KERNEL_INDEX_SMC, input as *const Param as *const c_void, std::mem::size_of::<Param>(),
+                output as *mut Param as *mut c_void, &mut output_size)
+        };
+        match (kr, output.result) {
+            (0, 0) => Ok(()),
+            (0, SMC_KEY_NOT_FOUND) => Err(format!("SMC key {} not found", key)),
+            (IO_RETURN_NOT_PRIVILEGED, _) => Err(format!("not privileged to access SMC key {} (run with sudo)", key)),
+            (kr, r) => Err(format!("SMC call for {} failed (IOKit {:#x}, SMC result {})", key, kr, r)),
+        }
+    }
+
+    fn decl(&self, key: &str) -> Result<Decl, String> {
+        if let Some(d) = self.infos.lock().ok().and_then(|m| m.get(key).copied()) { return Ok(d); }
+        let out = self.call(key, &mut Param { data8: CMD_READ_KEYINFO, ..Default::default() })?;
+        let d = Decl { size: out.key_info.data_size, data_type: out.key_info.data_type.to_be_bytes(), attributes: out.key_info.data_attributes };
+        if let Ok(mut m) = self.infos.lock() { m.insert(key.to_string(), d); }
+        Ok(d)
+    }
+
+    pub fn info(&self, key: &str) -> Result<KeyInfo, String> {
+        let d = self.decl(key)?;
+        Ok(KeyInfo { size: d.size, data_type: String::from_utf8_lossy(&d.data_type).to_string(), attributes: d.attributes })
+    }
+
+    // Two driver calls the first time a key is read (info, then bytes), one after that.
+    pub fn read(&self, key: &str) -> Result<(KeyInfo, Vec<u8>), String> {
+        let bytes = self.read_with(key, |_, b| b.to_vec())?;
+        Ok((self.info(key)?, bytes))
+    }
+
+    // `read` without allocating: `f` gets the key's data type and its bytes straight from the
+    // driver's reply. The per-sample paths read through this.
+    pub fn read_with<T>(&self, key: &str, f: impl FnOnce(&str, &[u8]) -> T) -> Result<T, String> {
+        let d = self.decl(key)?;
+        let data_type = std::str::from_utf8(&d.data_type).unwrap_or("");
+        let len = readable(key, d.size, data_type)?;
+        let mut input = Param { data8: CMD_READ_BYTES, ..Default::default() };
+        input.key_info.data_size = d.size;
+        let out = self.call(key, &mut input)?;
+        Ok(f(data_type, &out.bytes[..len]))
+    }
+
+    // How many keys the SMC says it has (#KEY, big-endian ui32).
+    pub fn key_count(&self) -> Result<u32, String> {
+        let (_, b) = self.read("#KEY")?;
+        b.get(..4).and_then(|b| b.try_into().ok()).map(u32::from_be_bytes).ok_or_else(|| "SMC key count #KEY is shorter than 4 bytes".to_string())
+    }
+
+    // The key at `index` in the SMC's own order.
+    pub fn key_at(&self, index: u32) -> Result<String, String> {
+        let out = self.call_code("#KEY", &Param { data8: CMD_READ_INDEX, data32: index, ..Default::default() })?;
+        if !printable(out.key) { return Err(format!("SMC key index {} returned an unprintable name {:#010x}", index, out.key)); }
+        Ok(fourcc_str(out.key))
+    }
+
+    // Every key, skipping indices that fail rather than losing every key after the first bad one.
+    pub fn keys(&self) -> Result<Scan, String> {
+        let count = self.key_count()?;
+        let mut scan = Scan { keys: Vec::with_capacity(count as usize), skipped: Vec::new() };
+        for i in 0..count {
+            match guarded(&format!("#{}", i), || self.key_at(i)) {
+                Ok(key) => scan.keys.push(key),
+                Err(e) => scan.skipped.push(e),
+            }
+        }
+        Ok(scan)
+    }
+
+    // Looks up each key's info once; None for keys this machine doesn't have or can't read.
+    pub fn resolve(&self, keys: &[&str]) -> Vec<Option<Resolved>> {
+        keys.iter().map(|k| guarded(k, || {
+            let info = self.info(k)?;
+            info.readable(k)?;
+            Ok(Resolved { name: k.to_string(), code: fourcc(k)?, info })
+        }).map_err(|e| crate::diag::debug!("leaving out {}: {}", k, e)).ok()).collect()
+    }
+
+    // One driver call per key and no lookup or allocation; `f` gets the bytes.
+    pub fn read_resolved<T>(&self, r: &Resolved, f: impl FnOnce(&[u8]) -> T) -> Result<T, String> {
+        let len = r.info.readable(&r.name)?;
+        let mut input = Param { key: r.code, data8: CMD_READ_BYTES, ..Default::default() };
+        input.key_info.data_size = r.info.size;
+        let out = self.call_code(&r.name, &input)?;
+        Ok(f(&out.bytes[..len]))
+    }
+
+    // `read_resolved` over a whole sweep in one pass: one request and one reply struct are reused
+    // for every key instead of built per read. The driver takes one key per call, so this is
+    // still a call per key, but nothing else is. `f` gets each key's position in `keys` and its
+    // bytes, or why it couldn't be read; a key that panics is an error for that key alone.
+    pub fn read_batch<'k>(&self, keys: impl IntoIterator<Item = &'k Resolved>, mut f: impl FnMut(usize, Result<&[u8], String>)) {
+        let (mut input, mut output) = (Param { data8: CMD_READ_BYTES, ..Default::default() }, Param::default());
+        for (i, r) in keys.into_iter().enumerate() {
+            let read = guarded(&r.name, || {
+                let len = r.info.readable(&r.name)?;
+                (input.key, input.key_info.data_size) = (r.code, r.info.size);
+                self.call_into(&r.name, &input, &mut output)?;
+                Ok(len)
+            });
+            f(i, read.map(|len| &output.bytes[..len]));
+        }
+    }
+
+    // Saves the key's current bytes to the undo journal, then writes.
+    pub fn write(&self, key: &str, bytes: &[u8]) -> Result<(), String> {
+        let (_, original) = self.read(key)?;
+        crate::journal::record(key, &original)?;
+        self.write_unjournaled(key, bytes)
+    }
+
+    // `bytes` must match the key's size exactly; the SMC rejects anything else anyway.
+    pub fn write_unjournaled(&self, key: &str, bytes: &[u8]) -> Result<(), String> {
+        let info = self.info(key)?;
+        if bytes.len() != info.size as usize {
+            return Err(format!("{} takes {} byte(s) of type {:?}, got {}", key, info.size, info.data_type, bytes.len()));
+        }
+        let mut input = Param { data8: CMD_WRITE_BYTES, ..Default::default() };
+        input.key_info.data_size = info.size;
+        input.bytes[..bytes.len()].copy_from_slice(bytes);
+        self.call(key, &mut input).map(|_| ())
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        unsafe { IOServiceClose(self.conn) };
+    }
+}
@@ -0,0 +1,111 @@
+// One `json`/`stream` sample as a typed value, for tools that post-process recorded streams.
+// FIELDS is the list the binary emits from (`--fields` validates against it), so the names
+// here and in the output can't drift apart. Field names are stable: they are only ever added,
+// never renamed or removed within a major version.
+//
+// Round trip: `Snapshot::from_json(&s.to_json()) == Ok(s)` for every snapshot. Reading a
+// recorded line keeps every value; a field that was null and one that was not selected both
+// read as None, and fields this version doesn't know (from a newer binary) are kept in `extra`.
+
+pub const FIELDS: &[&str] = &[
+    "id", "ts", "seq", "host", "model", "chip", "interval_ms",
+    "cpu_temp", "cpu_p_temp", "cpu_e_temp", "gpu_temp", "gpu_temp_max", "gpu_sensors", "hottest", "mem_temp", "ssd_temp", "bat_temp",
+    "power_w", "power_raw_w", "bat_power_w", "mem_power_w", "cpu_mw", "gpu_mw", "ane_mw",
+    "battery_pct", "charging", "mem_free_pct", "efficiency_hrs", "screen_w", "wakeups_per_sec", "cpu_ms_per_j", "wakeups_per_w", "thermal_pressure",
+    "drain_pct_per_hr", "est_hours_remaining",
+    "top_cpu", "high_wakeups", "charge_input_w", "charge_overhead_w", "adapter_w", "dominant",
+    "sensors", "volts", "amps", "calibration", "rolling", "sources", "cache_age_ms", "rails", "derived", "events", "meter_self", "temp_unit", "tags",
+];
+
+#[cfg(feature = "snapshot")]
+pub use typed::Snapshot;
+
+#[cfg(feature = "snapshot")]
+mod typed {
+    use serde::{Deserialize, Serialize};
+    use serde_json::{Map, Value};
+
+    // Temperatures are in `temp_unit` (Celsius when absent), power in W unless the name says mW.
+    // Nested objects and arrays stay JSON values; their layout is described in the README.
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    #[non_exhaustive]
+    pub struct Snapshot {
+        #[serde(skip_serializing_if = "Option::is_none")] pub id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub ts: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub seq: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub host: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub model: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub chip: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub interval_ms: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub cpu_temp: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub cpu_p_temp: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub cpu_e_temp: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub gpu_temp: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub gpu_temp_max: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub gpu_sensors: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub hottest: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub mem_temp: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub ssd_temp: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub bat_temp: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub power_w: Option<f64>,
+        // The system rail before the wall-meter correction; only present while one is applied
+        #[serde(skip_serializing_if = "Option::is_none")] pub power_raw_w: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub bat_power_w: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub mem_power_w: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub cpu_mw: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub gpu_mw: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub ane_mw: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub battery_pct: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub charging: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub mem_free_pct: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub efficiency_hrs: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub screen_w: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub wakeups_per_sec: Option<f64>,
+        // Software efficiency over the powermetrics window, per joule / watt of CPU + GPU + ANE power
+        #[serde(skip_serializing_if = "Option::is_none")] pub cpu_ms_per_j: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub wakeups_per_w: Option<f64>,
+        // nominal | fair | serious | critical
+        #[serde(skip_serializing_if = "Option::is_none")] pub thermal_pressure: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub drain_pct_per_hr: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub est_hours_remaining: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub top_cpu: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub high_wakeups: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub charge_input_w: Option<f64>,
+        // Adapter input minus system load minus battery charge power, while charging
+        #[serde(skip_serializing_if = "Option::is_none")] pub charge_overhead_w: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub adapter_w: Option<u32>,
+        // Largest consumers: display | cpu | gpu | charging_losses | unknown_residual
+        #[serde(skip_serializing_if = "Option::is_none")] pub dominant: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub sensors: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub volts: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub amps: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub calibration: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub rolling: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub sources: Option<Value>,
+        // Age of the `serve` cache entry a client was answered from
+        #[serde(skip_serializing_if = "Option::is_none")] pub cache_age_ms: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub rails: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub derived: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub events: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub meter_self: Option<Value>,
+        // "fahrenheit" when temperatures aren't Celsius
+        #[serde(skip_serializing_if = "Option::is_none")] pub temp_unit: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] pub tags: Option<Map<String, Value>>,
+        // Fields not listed above, kept as-is
+        #[serde(flatten)]
+        pub extra: Map<String, Value>,
+    }
+
+    impl Snapshot {
+        // One line of `json`/`stream` output (or a `follow` line).
+        pub fn from_json(line: &str) -> Result<Snapshot, String> {
+            serde_json::from_str(line).map_err(|e| format!("invalid snapshot: {}", e))
+        }
+
+        // A single line, in the binary's field order.
+        pub fn to_json(&self) -> String {
+            serde_json::to_string(self).unwrap_or_default()
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// `soak` (feature "soak", not in default builds): hours of simulated `stream` samples in a few
+// minutes, against the mock machine (mock.rs) instead of the SMC, with its faults injected the
+// way an always-on fleet machine meets them (key_gone, timeout, clock_jump, sleep_gap).
+// The samples go through the same stages as the stream loop (temperature weighting, drain,
+// throttle, alerts, adaptive interval, and the pipeline's real Serializer) and every line must parse as a
+// Snapshot with no unknown fields, a strictly increasing seq and id, and a ts. A panic in any
+// stage, a schema violation, or peak RSS growing more than LEAK_KB after the first simulated hour
+// fails the run (exit 1). The mock feeds the stages directly; it does not exercise the IOKit path.
+
+use crate::config::Units;
+use crate::mock::Mock;
+use crate::pipeline::{Enrichment, Sample, Serializer, Timings};
+use crate::sample::{self, Powermetrics};
+use crate::source::{Rail, SensorSource};
+use crate::json_num;
+use kim_temp::snapshot::Snapshot;
+use std::time::Duration;
+
+// Peak RSS growth allowed once the buffers and rolling windows have filled
+const LEAK_KB: i64 = 8 * 1024;
+const WARMUP: Duration = Duration::from_secs(3600);
+
+// A schema or ordering violation in one line, or None.
+fn check(line: &str, last_seq: &mut u64, last_id: &mut String) -> Option<String> {
+    let snap = match Snapshot::from_json(line) { Ok(s) => s, Err(e) => return Some(e) };
+    if !snap.extra.is_empty() { return Some(format!("unknown fields {:?}", snap.extra.keys().collect::<Vec<_>>())); }
+    let (Some(seq), Some(id), Some(ts)) = (snap.seq, snap.id, snap.ts) else { return Some("missing id, ts or seq".to_string()) };
+    if crate::clock::parse(&ts).is_none() { return Some(format!("unparseable ts {:?}", ts)); }
+    if seq <= *last_seq { return Some(format!("seq {} after {}", seq, last_seq)); }
+    if id <= *last_id { return Some(format!("id {} after {}", id, last_id)); }
+    *last_seq = seq;
+    *last_id = id;
+    None
+}
+
+pub struct Report {
+    pub samples: u64,
+    pub panics: u64,
+    pub violations: u64,
+    pub rss_growth_kb: i64,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.panics == 0 && self.violations == 0 && self.rss_growth_kb <= LEAK_KB
+    }
+}
+
+// Runs `hours` of simulated samples; every violation is printed to stderr as it happens, and a
+// progress line every simulated hour.
+pub fn run(hours: f64, seed: u64, units: Units) -> Report {
+    let mut mock = Mock::new(seed, true);
+    let fields = sample::Fields::parse(None).unwrap_or_else(|e| { eprintln!("{}", e); std::process::exit(2); });
+    let mut temps = crate::temps::Temps::default();
+    let mut noise = crate::temps::Noise::default();
+    let mut drain = crate::battery::Drain::default();
+    let mut throttle = crate::throttle::Detector::default();
+    let mut adaptive = crate::adaptive::Controller::default();
+    let mut alert_engine = crate::alerts::Engine::new(["cpu_temp > 90 for 30s", "power_w >= 50", "battery_pct < 10"].iter().filter_map(|w| crate::alerts::Rule::parse(w).ok()).collect());
+    let derived_metrics: Vec<crate::derived::Derived> = ["soc_w = PSTR - PPBR"].iter().filter_map(|d| crate::derived::Derived::parse(d).ok()).collect();
+    let mut summary = crate::session::Summary::new();
+    let mut serializer = Serializer::new(crate::pipeline::SerializeOptions {
+        fields: &fields, units, detail: true, host: "soak", model: crate::mock::MODEL, tags: None, corrected: false, backlight: false, rails: None,
+        meter: Some((crate::qos::Qos::Utility, 0)),
+    });
+    // Peak RSS for the leak check, apart from the serializer's meter
+    let mut meter = crate::qos::SelfMeter::new();
+    let mut cached_pm = Powermetrics::default();
+    let mut cached_pmset: Option<(i32, bool)> = None;
+    let (mut last_seq, mut last_id) = (0u64, String::new());
+    let mut report = Report { samples: 0, panics: 0, violations: 0, rss_growth_kb: 0 };
+    let mut baseline_rss: Option<i64> = None;
+    let mut next_ms = 1000u64;
+    let mut hour = 0u64;
+    let until = Duration::from_secs_f64(hours * 3600.0);
+
+    while mock.mono < until && crate::session::interrupted().is_none() {
+        mock.advance(Duration::from_millis(next_ms));
+        report.samples += 1;
+        let cycle_count = report.samples;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let now = mock.now();
+            let sampled_at = mock.wall_now();
+            let mut rail = |r| mock.rail(r).map_or(0.0, |w| w as f32);
+            let (sys_power, bat_power, mem_power) = (rail(Rail::System), rail(Rail::Battery), rail(Rail::Memory));
+            mock.temps(&mut temps, &mut noise);
+            let cpu_avg = units.temp(crate::temps::avg(&temps.cpu));
+            if cached_pmset.is_none() || cycle_count % 5 == 1 { cached_pmset = Some(mock.pmset()); }
+            let s = Sample {
+                seq: cycle_count, mono: now, at: sampled_at, ts: crate::clock::local(sampled_at), sys_power_raw: sys_power, sys_power, bat_power, mem_power,
+                design_wh: mock.battery().map(|b| b.design_wh), battery_pct: cached_pmset.map(|(p, _)| p), charging: cached_pmset.map(|(_, c)| c), mem_free_pct: mock.mem_free_pct(),
+            };
+            // The enrich stage, with the mock standing in for powermetrics and thermal pressure
+            let mut e = Enrichment { pm_fresh: cycle_count % 5 == 1, thermal_level: mock.thermal_level(), ..Default::default() };
+            if let (Some(p), Some(c)) = (s.battery_pct, s.charging) { (e.drain_pct_per_hr, e.est_hours_remaining) = drain.observe(now, p, c); }
+            if e.pm_fresh {
+                cached_pm = mock.powermetrics(&[]);
+                summary.wakeups(&cached_pm.high_wakeups);
+            }
+            let context = || format!("\"cpu_temp\":{},\"power_w\":{:.2},\"top_cpu\":[{}]", json_num(Some(cpu_avg), 1), sys_power, cached_pm.top_json);
+            e.events.extend(throttle.update(e.thermal_level, e.pm_fresh.then_some(cached_pm.clusters.as_slice()), context));
+            e.derived = derived_metrics.iter().map(|m| {
+                (m.name.clone(), m.eval(&mut |key: &str| match key { "PSTR" => Some(sys_power as f64), "PPBR" => Some(bat_power as f64), _ => None }))
+            }).collect();
+            let metrics: Vec<(&str, Option<f64>)> = vec![("cpu_temp", Some(cpu_avg)), ("power_w", Some(sys_power as f64)), ("battery_pct", s.battery_pct.map(|p| p as f64))];
+            e.events.extend(alert_engine.evaluate(&metrics));
+            e.interval_ms = adaptive.next(s.charging == Some(false), sys_power as f64, crate::temps::avg(&temps.cpu)).0;
+            next_ms = e.interval_ms;
+
+            summary.sample(sys_power as f64, Some(cpu_avg), Some(units.temp(crate::temps::avg(&temps.gpu))), s.battery_pct);
+            let (_, rss_kb) = meter.sample();
+            let line = serializer.serialize(&s, &mut temps, &cached_pm, &e, &Timings::default());
+            (check(line, &mut last_seq, &mut last_id).map(|v| format!("{}: {}", v, line)), rss_kb)
+        }));
+        match result {
+            Err(_) => report.panics += 1,
+            Ok((violation, rss_kb)) => {
+                if let Some(v) = violation {
+                    report.violations += 1;
+                    eprintln!("soak: sample {}: {}", cycle_count, v);
+                }
+                if mock.mono >= WARMUP { report.rss_growth_kb = rss_kb - *baseline_rss.get_or_insert(rss_kb); }
+            }
+        }
+        if mock.mono.as_secs() / 3600 > hour {
+            hour = mock.mono.as_secs() / 3600;
+            let f = &mock.faults;
+            eprintln!("soak: {}h simulated, {} samples, {} panics, {} violations, rss +{} KB; faults: {} key_gone, {} all_gone, {} timeout, {} clock_jump, {} sleep_gap",
+                hour, report.samples, report.panics, report.violations, report.rss_growth_kb, f.key_gone, f.all_gone, f.timeout, f.clock_jump, f.sleep_gap);
+        }
+    }
+    let closing = summary.to_json(0);
+    if let Err(e) = serde_json::from_str::<serde_json::Value>(&closing) {
+        report.violations += 1;
+        eprintln!("soak: summary line: {}: {}", e, closing);
+    }
+    report
+}
@@ -0,0 +1,131 @@
+// Where `stream` reads the machine from. The sample and enrich stages (pipeline.rs) see the SMC,
+// powermetrics and the battery only through a SensorSource, so the same stages run against a Mac
+// (`--backend smc`, the default on macOS), a Linux machine's sysfs (`--backend linux`, the
+// default there, see linux.rs) or a simulated one (`--backend mock`, feature "mock", see
+// mock.rs) that needs no hardware and reads the same for the same --seed.
+
+use crate::battery::{Adapter, Battery, Health};
+use crate::profile::Profile;
+use crate::sample::Powermetrics;
+use crate::temps::{Noise, Sensor, Temps};
+use std::time::{Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rail {
+    // Before any wall-meter correction
+    System,
+    Battery,
+    Memory,
+    Cpu,
+    Gpu,
+}
+
+pub trait SensorSource {
+    // Once at the start of every sample, before its reads
+    fn refresh(&mut self) {}
+    // When this sample is taken, on both clocks; a simulated source keeps clocks of its own
+    fn clock(&self) -> (Instant, SystemTime) { (Instant::now(), SystemTime::now()) }
+    // A power rail in W; None when it can't be read
+    fn rail(&mut self, rail: Rail) -> Option<f64>;
+    // Any SMC key by name, for derived metrics
+    fn key(&mut self, key: &str) -> Option<f64>;
+    // This sample's temperatures into `temps`, whose buffers are reused
+    fn temps(&mut self, temps: &mut Temps, noise: &mut Noise);
+    // None without a battery
+    fn battery(&mut self) -> Option<Battery>;
+    // Battery percentage and whether it's charging
+    fn pmset(&mut self) -> (i32, bool);
+    // None when no adapter is connected
+    fn adapter(&mut self) -> Option<Adapter>;
+    fn health(&mut self) -> Option<Health>;
+    fn powermetrics(&mut self, excluded: &[&str]) -> Powermetrics;
+    // powermetrics when it needs nothing from the source and is slow enough to run on its own
+    // thread (pipeline::Slow); None keeps it in `powermetrics`, inline
+    fn powermetrics_fn(&self) -> Option<fn(&[&str]) -> Powermetrics> { None }
+    fn thermal_level(&mut self) -> Option<(&'static str, u8)>;
+    fn mem_free_pct(&mut self) -> i32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Smc,
+    Linux,
+    Mock,
+}
+
+impl Backend {
+    pub fn parse(name: &str) -> Option<Backend> {
+        match name {
+            "smc" => Some(Backend::Smc),
+            "linux" => Some(Backend::Linux),
+            "mock" => Some(Backend::Mock),
+            _ => None,
+        }
+    }
+
+    // This system's own hardware
+    pub fn native() -> Backend {
+        if cfg!(target_os = "linux") { Backend::Linux } else { Backend::Smc }
+    }
+}
+
+// The Mac itself: SMC keys through `value`, powermetrics, pmset and ioreg.
+pub struct Smc<'a> {
+    profile: &'a Profile,
+    sensors: Vec<Sensor>,
+    pressure: Option<crate::thermal::Pressure>,
+}
+
+impl<'a> Smc<'a> {
+    // `sensors`: the temperature sensors to sweep (empty when nothing needs temperatures).
+    pub fn new(profile: &'a Profile, sensors: Vec<Sensor>) -> Smc<'a> {
+        Smc { profile, sensors, pressure: crate::thermal::Pressure::open() }
+    }
+}
+
+impl SensorSource for Smc<'_> {
+    fn rail(&mut self, rail: Rail) -> Option<f64> {
+        let p = self.profile;
+        crate::value::read_f64(match rail { Rail::System => &p.system_power_key, Rail::Battery => &p.battery_power_key, Rail::Memory => "PHPM", Rail::Cpu => &p.cpu_power_key, Rail::Gpu => &p.gpu_power_key })
+    }
+
+    fn key(&mut self, key: &str) -> Option<f64> {
+        crate::value::read_f64(key)
+    }
+
+    fn temps(&mut self, temps: &mut Temps, noise: &mut Noise) {
+        temps.refill(&self.sensors, Some(noise));
+    }
+
+    fn battery(&mut self) -> Option<Battery> {
+        crate::battery::detect()
+    }
+
+    fn pmset(&mut self) -> (i32, bool) {
+        crate::battery::pmset_status()
+    }
+
+    fn adapter(&mut self) -> Option<Adapter> {
+        crate::battery::adapter()
+    }
+
+    fn health(&mut self) -> Option<Health> {
+        crate::battery::health()
+    }
+
+    fn powermetrics(&mut self, excluded: &[&str]) -> Powermetrics {
+        crate::sample::powermetrics(excluded)
+    }
+
+    fn powermetrics_fn(&self) -> Option<fn(&[&str]) -> Powermetrics> {
+        Some(crate::sample::powermetrics)
+    }
+
+    fn thermal_level(&mut self) -> Option<(&'static str, u8)> {
+        self.pressure.as_ref().and_then(|p| p.level())
+    }
+
+    fn mem_free_pct(&mut self) -> i32 {
+        crate::sample::mem_free_pct()
+    }
+}
@@ -0,0 +1,94 @@
+// The helper tools a sample runs (powermetrics, pmset, ioreg, sysctl, ps) get a deadline: a
+// `sudo powermetrics` stuck on a password prompt or a wedged ioreg would otherwise hold the
+// stream loop forever. Past it the child gets SIGTERM (which sudo relays to the command under
+// it), then SIGKILL a second later, and the caller sees a failed run: the same empty values it
+// already falls back to. Default 5 s; --subprocess-timeout SECS or `subprocess_timeout_secs`.
+
+use crate::diag::debug;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+// Between SIGTERM and SIGKILL
+const GRACE: Duration = Duration::from_secs(1);
+
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT.as_millis() as u64);
+
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT_MS.store(timeout.as_millis().max(1) as u64, Ordering::Relaxed);
+}
+
+pub fn timeout() -> Duration {
+    Duration::from_millis(TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+// Starts `cmd` with stdout and stderr captured (stdin closed), for `wait`.
+pub fn spawn(cmd: &mut Command) -> std::io::Result<Child> {
+    cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+}
+
+// `child.wait_with_output()` with the deadline: both pipes are drained as they fill, and a child
+// still running at the deadline is stopped. Err says which.
+pub fn wait(mut child: Child, program: &str) -> Result<Output, String> {
+    let deadline = Instant::now() + timeout();
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let (mut stdout, mut stderr) = (child.stdout.take(), child.stderr.take());
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() { return Err(stop(child, program)); }
+        let mut polled: Vec<libc::pollfd> = [stdout.as_ref().map(|p| p.as_raw_fd()), stderr.as_ref().map(|p| p.as_raw_fd())]
+            .into_iter().flatten().map(|fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 }).collect();
+        if polled.is_empty() { break; }
+        // An interrupted poll leaves every revents 0 and simply goes round again
+        unsafe { libc::poll(polled.as_mut_ptr(), polled.len() as libc::nfds_t, left.as_millis().min(i32::MAX as u128) as i32) };
+        // A readable or hung-up pipe: read what's there, and drop it at end of file
+        let ready = |fd: Option<i32>| fd.is_some_and(|fd| polled.iter().any(|p| p.fd == fd && p.revents != 0));
+        if ready(stdout.as_ref().map(|p| p.as_raw_fd())) && !drain(stdout.as_mut().map(|p| p as &mut dyn Read), &mut out) { stdout = None; }
+        if ready(stderr.as_ref().map(|p| p.as_raw_fd())) && !drain(stderr.as_mut().map(|p| p as &mut dyn Read), &mut err) { stderr = None; }
+    }
+    // Both pipes closed; the exit itself follows at once, but is held to the same deadline
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Ok(Output { status, stdout: out, stderr: err }),
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(5)),
+            Ok(None) => return Err(stop(child, program)),
+            Err(e) => return Err(format!("cannot wait for {}: {}", program, e)),
+        }
+    }
+}
+
+// One read into `buf`; false once the pipe is closed.
+fn drain(pipe: Option<&mut dyn Read>, buf: &mut Vec<u8>) -> bool {
+    let mut chunk = [0u8; 8192];
+    match pipe.map(|p| p.read(&mut chunk)) {
+        Some(Ok(n)) if n > 0 => { buf.extend_from_slice(&chunk[..n]); true }
+        _ => false,
+    }
+}
+
+// SIGTERM, then SIGKILL after GRACE; reaps the child either way. Logged as a warning, as the
+// sample it held up is missing what the child would have read.
+fn stop(mut child: Child, program: &str) -> String {
+    unsafe { libc::kill(child.id() as i32, libc::SIGTERM) };
+    let end = Instant::now() + GRACE;
+    let exited = |child: &mut Child| child.try_wait().ok().flatten().is_some();
+    while !exited(&mut child) && Instant::now() < end { std::thread::sleep(Duration::from_millis(20)); }
+    if !exited(&mut child) { let _ = child.kill(); let _ = child.wait(); }
+    let e = format!("{} timed out after {:.1} s and was stopped", program, timeout().as_secs_f64());
+    crate::diag::warn!("{}", e);
+    e
+}
+
+// A subprocess's stdout (lossily decoded); None when it can't start or times out. A tool that
+// can't start or exits non-zero is logged at debug, since callers fall back to empty values
+// without a word.
+pub fn stdout(cmd: &mut Command) -> Option<String> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let child = spawn(cmd).map_err(|e| debug!("cannot run {}: {}", program, e)).ok()?;
+    let o = wait(child, &program).ok()?;
+    if !o.status.success() { debug!("{} exited with {}: {}", program, o.status, String::from_utf8_lossy(&o.stderr).trim()); }
+    Some(String::from_utf8_lossy(&o.stdout).into_owned())
+}
@@ -0,0 +1,90 @@
+// `setup-sudo`: installs the sudoers rule that lets `json`/`stream` run powermetrics without a
+// password, for the user who ran `sudo kim_temp setup-sudo` (or --user). The rule allows exactly
+// the command line kim_temp runs (/usr/bin/powermetrics with sample::POWERMETRICS_ARGS), not
+// powermetrics with any arguments. It is written to a temporary file, checked with
+// `visudo -c -f`, and only then moved to /etc/sudoers.d/kim_temp (0440, root:wheel), so a bad
+// rule can never lock sudo out. One line per user; `--remove` takes the user's line out again
+// and deletes the file once it is empty.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+const FILE: &str = "/etc/sudoers.d/kim_temp";
+const POWERMETRICS: &str = "/usr/bin/powermetrics";
+// The rule file the README used to suggest by hand
+const LEGACY: &str = "/etc/sudoers.d/kim_metrics";
+
+// Short names only: anything else could smuggle sudoers syntax into the rule
+fn valid_user(user: &str) -> bool {
+    !user.is_empty() && user.len() <= 64 && !user.starts_with('-') && user.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+// `USER ALL=(root) NOPASSWD: /usr/bin/powermetrics ARGS`, with sudoers' special characters escaped.
+pub fn rule(user: &str) -> String {
+    let args: Vec<String> = crate::sample::POWERMETRICS_ARGS.iter().map(|a| a.chars().flat_map(|c| match c { ',' | ':' | '=' | '\\' => vec!['\\', c], c => vec![c] }).collect()).collect();
+    format!("{} ALL=(root) NOPASSWD: {} {}", user, POWERMETRICS, args.join(" "))
+}
+
+// The user the rule is for: --user, else whoever ran sudo.
+fn target(user: Option<&str>) -> Result<String, String> {
+    let user = user.map(str::to_string).or_else(|| std::env::var("SUDO_USER").ok()).ok_or("run it with sudo from your own account, or name the account with --user")?;
+    if !valid_user(&user) { return Err(format!("{:?} is not a user name this can write a rule for", user)); }
+    if user == "root" { return Err("root runs powermetrics without a rule; name the account that runs kim_temp with --user".to_string()); }
+    Ok(user)
+}
+
+fn lines(user: &str) -> Vec<String> {
+    std::fs::read_to_string(FILE).unwrap_or_default().lines()
+        .filter(|l| l.split_whitespace().next() != Some(user)).map(str::to_string).collect()
+}
+
+// Writes `lines` to FILE through a visudo-checked temporary file; no lines removes FILE.
+fn replace(lines: &[String]) -> Result<(), String> {
+    if lines.iter().all(|l| l.starts_with('#')) {
+        return match std::fs::remove_file(FILE) { Ok(()) => Ok(()), Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()), Err(e) => Err(format!("cannot remove {}: {}", FILE, e)) };
+    }
+    let tmp = format!("{}.tmp", FILE);
+    std::fs::write(&tmp, lines.join("\n") + "\n").map_err(|e| format!("cannot write {}: {}", tmp, e))?;
+    std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o440)).map_err(|e| format!("cannot set permissions on {}: {}", tmp, e))?;
+    let check = Command::new("visudo").args(["-c", "-f", &tmp]).output();
+    let ok = match &check {
+        Ok(out) if out.status.success() => Ok(()),
+        Ok(out) => Err(format!("visudo rejected the rule, nothing was changed: {}", String::from_utf8_lossy(&out.stderr).trim())),
+        Err(e) => Err(format!("cannot run visudo, nothing was changed: {}", e)),
+    };
+    if let Err(e) = ok { let _ = std::fs::remove_file(&tmp); return Err(e); }
+    std::fs::rename(&tmp, FILE).map_err(|e| format!("cannot install {}: {}", FILE, e))
+}
+
+fn root_check() -> Result<(), String> {
+    if unsafe { libc::geteuid() } == 0 { Ok(()) } else { Err("changing /etc/sudoers.d needs root: run it with sudo".to_string()) }
+}
+
+// Adds (or replaces) the user's rule. Returns what was done.
+pub fn install(user: Option<&str>) -> Result<Vec<String>, String> {
+    root_check()?;
+    let user = target(user)?;
+    let mut out = lines(&user);
+    if out.is_empty() { out.push("# Written by `kim_temp setup-sudo`; undo with `sudo kim_temp setup-sudo --remove`".to_string()); }
+    out.push(rule(&user));
+    replace(&out)?;
+    let mut report = vec![format!("Installed in {}: {}", FILE, rule(&user))];
+    // sudoers.d is only read when /etc/sudoers includes it (macOS does by default)
+    let main = std::fs::read_to_string("/etc/sudoers").unwrap_or_default();
+    if !main.lines().any(|l| l.trim_start().starts_with("#includedir") || l.trim_start().starts_with("@includedir")) {
+        report.push("Warning: /etc/sudoers has no #includedir line, so sudo won't read the rule".to_string());
+    }
+    if Path::new(LEGACY).exists() { report.push(format!("{} (the older hand-written rule) is still there; it allows powermetrics with any arguments, so remove it once this works", LEGACY)); }
+    Ok(report)
+}
+
+// Removes the user's rule, and the file with the last one.
+pub fn remove(user: Option<&str>) -> Result<String, String> {
+    root_check()?;
+    let user = target(user)?;
+    let before = std::fs::read_to_string(FILE).map_err(|_| format!("{} does not exist; nothing to remove", FILE))?;
+    if !before.lines().any(|l| l.split_whitespace().next() == Some(user.as_str())) { return Err(format!("{} has no rule for {}", FILE, user)); }
+    replace(&lines(&user))?;
+    Ok(format!("Removed the rule for {} from {}", user, FILE))
+}
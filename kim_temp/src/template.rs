@@ -0,0 +1,26 @@
+// `--template-file`: renders each json/stream/measure record through a Jinja template
+// (minijinja) instead of printing raw JSON, for status bars, MOTD banners and the like.
+// Every field of the record is a template variable, e.g. `{{ cpu_temp }}°C {{ power_w }}W`.
+
+use minijinja::Environment;
+
+const NAME: &str = "output";
+
+pub struct Template {
+    env: Environment<'static>,
+}
+
+impl Template {
+    pub fn load(path: &str) -> Result<Template, String> {
+        let source = std::fs::read_to_string(crate::config::expand_home(path)).map_err(|e| format!("cannot read template {}: {}", path, e))?;
+        let mut env = Environment::new();
+        env.add_template_owned(NAME, source).map_err(|e| format!("invalid template {}: {}", path, e))?;
+        Ok(Template { env })
+    }
+
+    // Renders one JSON record; a record that fails to render is reported and skipped.
+    pub fn render(&self, json: &str) -> Option<String> {
+        let ctx: serde_json::Value = serde_json::from_str(json).map_err(|e| crate::diag::warn!("template input is not JSON: {}", e)).ok()?;
+        self.env.get_template(NAME).and_then(|t| t.render(ctx)).map_err(|e| crate::diag::warn!("template render failed: {}", e)).ok()
+    }
+}
@@ -0,0 +1,328 @@
+// Temperature sweep shared by the json/stream/env modes.
+// Each reading carries a weight from the chip profile (die sensors over proximity sensors);
+// in stream mode the weight is further reduced for sensors that jitter between samples.
+
+use crate::profile::{Category, Profile};
+use crate::smcraw::Resolved;
+use crate::value::SmcValue;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+#[derive(Debug, Default, Clone)]
+pub struct Temps {
+    // (temperature, weight) per category
+    pub cpu: Vec<(f64, f64)>,
+    // The same CPU readings split by core type (`cpu` holds both)
+    pub cpu_p: Vec<(f64, f64)>,
+    pub cpu_e: Vec<(f64, f64)>,
+    // Each GPU sensor by key; Max/Ultra chips have one or more per GPU cluster
+    pub gpu_sensors: Vec<(String, f64)>,
+    pub gpu: Vec<(f64, f64)>,
+    pub mem: Vec<(f64, f64)>,
+    pub ssd: Vec<(f64, f64)>,
+    pub bat: Vec<(f64, f64)>,
+    // Every valid temperature key with its reading, weight and category group ("cpu", "gpu",
+    // "mem", "ssd", "bat"; None when unclassified), in SMC order (used by --detail and `hottest`)
+    pub sensors: Vec<(String, f64, f64, Option<&'static str>)>,
+}
+
+// A temperature key classified once, when the sensor list is built: matching a key against the
+// profile's roles and prefix rules (and its weights) every sweep is most of a sweep's CPU time
+// that isn't the driver call itself.
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub key: Resolved,
+    pub category: Option<Category>,
+    pub weight: f64,
+}
+
+// The keys a sweep reads, each classified by `profile`, in SMC order. Keys that aren't `T*` or
+// whose type can't hold a temperature are dropped here rather than skipped every sweep.
+pub fn sensors(keys: Vec<Resolved>, profile: &Profile) -> Vec<Sensor> {
+    keys.into_iter()
+        .filter(|k| k.name.starts_with('T') && matches!(k.info.data_type.as_str(), "sp78" | "flt " | "ioft"))
+        .map(|key| Sensor { category: profile.classify(&key.name), weight: profile.weight(&key.name), key })
+        .collect()
+}
+
+impl Temps {
+    // `noise` tracks per-sensor jitter across calls; pass None for one-shot reads.
+    pub fn collect(sensors: &[Sensor], noise: Option<&mut Noise>) -> Temps {
+        let mut t = Temps::default();
+        t.refill(sensors, noise);
+        t
+    }
+
+    // `collect` into the buffers of the previous sweep (the stream loop keeps one Temps for the
+    // whole run): vectors keep their capacity and the sensor names their Strings, so once the set
+    // of readable sensors is steady a sweep allocates nothing. The keys are read in one batched
+    // pass (smcraw::Raw::read_batch).
+    pub fn refill(&mut self, sensors: &[Sensor], mut noise: Option<&mut Noise>) {
+        for v in [&mut self.cpu, &mut self.cpu_p, &mut self.cpu_e, &mut self.gpu, &mut self.mem, &mut self.ssd, &mut self.bat] { v.clear(); }
+        let (mut gpu_n, mut n) = (0, 0);
+        let raw = if sensors.is_empty() { None } else { crate::smcraw::shared().ok() };
+        raw.into_iter().for_each(|raw| raw.read_batch(sensors.iter().map(|s| &s.key), |i, bytes| {
+            let Sensor { key, category, weight } = &sensors[i];
+            let (key_str, category) = (key.name.as_str(), *category);
+            if let Some(temp) = bytes.ok().and_then(|b| decode(key, category, b)) {
+                let mut weight = *weight;
+                if let Some(n) = noise.as_deref_mut() { weight *= n.observe(key_str, temp); }
+                match category {
+                    Some(Category::CpuP) => { self.cpu.push((temp, weight)); self.cpu_p.push((temp, weight)); }
+                    Some(Category::CpuE) => { self.cpu.push((temp, weight)); self.cpu_e.push((temp, weight)); }
+                    Some(Category::Gpu) => { self.gpu.push((temp, weight)); self.set_gpu_sensor(gpu_n, key_str, temp); gpu_n += 1; }
+                    Some(Category::Mem) => self.mem.push((temp, weight)),
+                    Some(Category::Ssd) => self.ssd.push((temp, weight)),
+                    Some(Category::Battery) => self.bat.push((temp, weight)),
+                    None => {}
+                }
+                self.set_sensor(n, key_str, temp, weight, category.map(Category::group));
+                n += 1;
+            }
+        }));
+        self.gpu_sensors.truncate(gpu_n);
+        self.sensors.truncate(n);
+    }
+
+    // Entry `n` of `sensors` (and of `gpu_sensors` below) set to a reading, reusing the name's
+    // String when the entry exists. For backends filling a Temps themselves; they truncate the
+    // two vectors to the entries set once a sweep is done, as `refill` does.
+    pub fn set_sensor(&mut self, n: usize, key: &str, temp: f64, weight: f64, group: Option<&'static str>) {
+        let s = slot(&mut self.sensors, n);
+        s.0.clear(); s.0.push_str(key); (s.1, s.2, s.3) = (temp, weight, group);
+    }
+
+    pub fn set_gpu_sensor(&mut self, n: usize, key: &str, temp: f64) {
+        let s = slot(&mut self.gpu_sensors, n);
+        s.0.clear(); s.0.push_str(key); s.1 = temp;
+    }
+}
+
+// Entry `n` of a buffer being refilled: the old one when there is one, else a new default.
+fn slot<T: Default>(v: &mut Vec<T>, n: usize) -> &mut T {
+    if n == v.len() { v.push(T::default()); }
+    &mut v[n]
+}
+
+// Plausible range (°C, exclusive) for a sensor kind; batteries, memory and SSDs never get near
+// die temperatures, so a reading there is a misread rather than a hot part.
+fn range(category: Option<Category>) -> (f64, f64) {
+    match category {
+        Some(Category::Battery) => (0.0, 80.0),
+        Some(Category::Mem | Category::Ssd) => (0.0, 100.0),
+        _ => (0.0, 130.0),
+    }
+}
+
+// A raw temperature key value as °C, judged by the key's SMC type:
+//   sp78  fixed point that can only hold Celsius (-128..128); 0 means "no reading"
+//   flt   usually Celsius, but some keys hold Kelvin (converted) or raw ADC counts (rejected)
+//   ioft  IOFixed 48.16, Celsius after decoding
+// Anything else (ui16 counters, fpe2 fan values under a T prefix, ...) is not a temperature.
+pub fn celsius(data_type: &str, category: Option<Category>, v: f64) -> Option<f64> {
+    let (lo, hi) = range(category);
+    let ok = |c: f64| c.is_finite() && c > lo && c < hi;
+    match data_type {
+        "sp78" | "ioft" => ok(v).then_some(v),
+        // 200 K is far below anything a Mac sees, so Celsius and Kelvin readings can't overlap
+        "flt " if ok(v) => Some(v),
+        "flt " if v > 200.0 && ok(v - 273.15) => Some(v - 273.15),
+        _ => None,
+    }
+}
+
+// Reads one temperature key and validates it for its type and sensor kind.
+// One driver call: the key's type was looked up when the sensor list was built.
+pub fn read(key: &Resolved, category: Option<Category>) -> Option<f64> {
+    if !matches!(key.info.data_type.as_str(), "sp78" | "flt " | "ioft") { return None; }
+    let raw = crate::smcraw::shared().ok()?;
+    crate::smcraw::guarded(&key.name, || raw.read_resolved(key, |b| decode(key, category, b))).ok()?
+}
+
+// A temperature key's bytes as validated °C.
+fn decode(key: &Resolved, category: Option<Category>, bytes: &[u8]) -> Option<f64> {
+    let data_type = key.info.data_type.as_str();
+    if !matches!(data_type, "sp78" | "flt " | "ioft") { return None; }
+    let v = SmcValue::decode(data_type, bytes).as_f64()?;
+    celsius(data_type, category, crate::diag::finite(v, &key.name)?)
+}
+
+// How a category's readings become its one temperature (--agg, `agg` in the config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Method {
+    // Weighted by the profile in json/stream, plain in the one-value modes
+    #[default]
+    Mean,
+    Median,
+    // The mean without the highest and lowest fifth (at least one each way from three readings)
+    Trimmed,
+    Max,
+}
+
+impl Method {
+    pub fn parse(s: &str) -> Option<Method> {
+        match s {
+            "mean" => Some(Method::Mean),
+            "median" => Some(Method::Median),
+            "trimmed" => Some(Method::Trimmed),
+            "max" => Some(Method::Max),
+            _ => None,
+        }
+    }
+}
+
+// The sigma a reading is measured in is never taken below this (°C), so sensors that agree to
+// the tenth don't reject a neighbour a degree off.
+const MIN_SIGMA: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Agg {
+    pub method: Method,
+    // --reject-sigma: readings further than this many standard deviations from the rest of
+    // their category are left out before `method` (a stuck or shorted sensor)
+    pub reject_sigma: Option<f64>,
+}
+
+static AGG: OnceLock<Agg> = OnceLock::new();
+
+// Once, from main, before anything is aggregated.
+pub fn set_aggregation(agg: Agg) {
+    let _ = AGG.set(agg);
+}
+
+pub fn aggregation() -> Agg {
+    AGG.get().copied().unwrap_or_default()
+}
+
+impl Agg {
+    // None when nothing usable is left. `weighted`: the mean uses each reading's weight (median,
+    // trimmed and max never do).
+    pub fn apply(&self, v: &[(f64, f64)], weighted: bool) -> Option<f64> {
+        SCRATCH.with(|scratch| {
+            let Scratch { kept, values } = &mut *scratch.borrow_mut();
+            kept.clear();
+            kept.extend(usable(v));
+            if let Some(n) = self.reject_sigma { reject(kept, n); }
+            if kept.is_empty() { return None; }
+            values.clear();
+            values.extend(kept.iter().map(|(t, _)| *t));
+            values.sort_by(f64::total_cmp);
+            Some(self.of_sorted(kept, values, weighted))
+        })
+    }
+
+    // `kept`: the readings left after rejection; `values`: their temperatures, sorted.
+    fn of_sorted(&self, kept: &[(f64, f64)], values: &[f64], weighted: bool) -> f64 {
+        let plain_mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+        let n = values.len();
+        match self.method {
+            Method::Mean if weighted => {
+                let total_weight: f64 = kept.iter().map(|(_, w)| w).sum();
+                if total_weight <= 0.0 { 0.0 } else { kept.iter().map(|(t, w)| t * w).sum::<f64>() / total_weight }
+            }
+            Method::Mean => plain_mean(values),
+            Method::Median => if n % 2 == 1 { values[n / 2] } else { (values[n / 2 - 1] + values[n / 2]) / 2.0 },
+            Method::Trimmed => {
+                let cut = if n >= 3 { (n / 5).max(1) } else { 0 };
+                plain_mean(&values[cut..n - cut])
+            }
+            Method::Max => values[n - 1],
+        }
+    }
+}
+
+// A category's usable readings and their sorted temperatures, kept between calls: the stream
+// aggregates every category every sample, and only the first sweep should allocate
+#[derive(Default)]
+struct Scratch {
+    kept: Vec<(f64, f64)>,
+    values: Vec<f64>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+}
+
+// Leave-one-out: each reading against the mean and sigma of the others. Needs three readings,
+// as two can't say which of them is wrong.
+fn reject(v: &mut Vec<(f64, f64)>, sigmas: f64) {
+    if v.len() < 3 { return; }
+    let (sum, sq) = v.iter().fold((0.0, 0.0), |(s, q), (t, _)| (s + t, q + t * t));
+    let others = (v.len() - 1) as f64;
+    v.retain(|(t, _)| {
+        let mean = (sum - t) / others;
+        let sigma = ((sq - t * t) / others - mean * mean).max(0.0).sqrt().max(MIN_SIGMA);
+        let off = (t - mean).abs() / sigma;
+        if off > sigmas { crate::diag::debug!("rejected a {:.1} °C reading, {:.1} sigma from the rest of its category ({:.1} °C)", t, off, mean); }
+        off <= sigmas
+    });
+}
+
+// The readings the aggregates below use. Every backend already drops non-finite readings (and
+// counts them, see diag::finite); this keeps one that slips through from poisoning a mean.
+fn usable(v: &[(f64, f64)]) -> impl Iterator<Item = (f64, f64)> + '_ {
+    v.iter().copied().filter(|(t, w)| t.is_finite() && w.is_finite())
+}
+
+// Hottest reading of a category (after --reject-sigma); None when it has no sensors.
+pub fn max(v: &[(f64, f64)]) -> Option<f64> {
+    Agg { method: Method::Max, ..aggregation() }.apply(v, false)
+}
+
+// A category's temperature by --agg: by default the weighted mean (a plain mean when the profile
+// has no quality data for its keys). 0.0 when it has no sensors (matches the historical JSON
+// output).
+pub fn avg(v: &[(f64, f64)]) -> f64 {
+    avg_opt(v).unwrap_or(0.0)
+}
+
+// Like `avg`, but None when the category has no sensors (for fields that are null rather than 0).
+pub fn avg_opt(v: &[(f64, f64)]) -> Option<f64> {
+    aggregation().apply(v, true)
+}
+
+// The one-value modes (`cpu`, `gpu`, ...): --agg over plain readings, the mean unweighted.
+pub fn of(v: &[f64]) -> Option<f64> {
+    aggregation().apply(&v.iter().map(|t| (*t, 1.0)).collect::<Vec<_>>(), false)
+}
+
+// The hottest classified sensor: its key, reading and category group. Throttling follows the
+// hottest die, not the average. With --reject-sigma a reading its category rejects can't be it.
+pub fn hottest(t: &Temps) -> Option<(&str, f64, &'static str)> {
+    let limits = [("cpu", max(&t.cpu)), ("gpu", max(&t.gpu)), ("mem", max(&t.mem)), ("ssd", max(&t.ssd)), ("bat", max(&t.bat))];
+    let limit = |group: &str| limits.iter().find(|(g, _)| *g == group).and_then(|(_, m)| *m);
+    t.sensors.iter().filter_map(|(k, temp, _, group)| Some((k.as_str(), *temp, (*group)?)))
+        .filter(|(_, temp, group)| limit(group).is_some_and(|m| *temp <= m))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+// Unweighted, for `serve` answering the one-value modes as they print locally.
+pub fn plain(v: &[(f64, f64)]) -> Option<f64> {
+    aggregation().apply(v, false)
+}
+
+// Unweighted mean of every reading, with neither --agg nor --reject-sigma, for showing what the
+// profile weights (and the aggregation) changed.
+pub fn plain_avg(v: &[(f64, f64)]) -> f64 {
+    Agg::default().apply(v, false).unwrap_or(0.0)
+}
+
+// Per-sensor jitter as an EWMA of the absolute change between samples.
+#[derive(Debug, Default)]
+pub struct Noise {
+    last: HashMap<String, (f64, f64)>,
+}
+
+impl Noise {
+    // Records a reading and returns a weight multiplier in (0, 1]: 1.0 for a steady sensor,
+    // 0.5 for one that moves ~1°C per sample.
+    pub fn observe(&mut self, key: &str, temp: f64) -> f64 {
+        // Look up before inserting: `entry` would allocate the key every sample
+        if !self.last.contains_key(key) { self.last.insert(key.to_string(), (temp, 0.0)); }
+        let Some(entry) = self.last.get_mut(key) else { return 1.0 };
+        let jitter = 0.8 * entry.1 + 0.2 * (temp - entry.0).abs();
+        *entry = (temp, jitter);
+        1.0 / (1.0 + jitter)
+    }
+}
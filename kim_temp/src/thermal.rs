@@ -0,0 +1,59 @@
+// macOS thermal pressure, the same signal NSProcessInfo.thermalState is derived from: the
+// kernel posts the level to the `com.apple.system.thermalpressurelevel` notification, whose
+// state can be read at any time. It tells "hot but fine" apart from "being throttled".
+
+use std::os::raw::{c_char, c_int};
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn notify_register_check(name: *const c_char, out_token: *mut c_int) -> u32;
+    fn notify_get_state(token: c_int, state: *mut u64) -> u32;
+}
+
+// No notify(3) elsewhere: registering fails, so there is never a Pressure to read.
+#[cfg(not(target_os = "macos"))]
+const NOTIFY_STATUS_FAILED: u32 = 1_000_000;
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn notify_register_check(_name: *const c_char, _out_token: *mut c_int) -> u32 {
+    NOTIFY_STATUS_FAILED
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn notify_get_state(_token: c_int, _state: *mut u64) -> u32 {
+    NOTIFY_STATUS_FAILED
+}
+
+pub struct Pressure {
+    token: c_int,
+}
+
+impl Pressure {
+    // None when the notification can't be registered (very old macOS).
+    pub fn open() -> Option<Pressure> {
+        let mut token = 0;
+        let status = unsafe { notify_register_check(c"com.apple.system.thermalpressurelevel".as_ptr(), &mut token) };
+        if status == 0 { Some(Pressure { token }) } else { None }
+    }
+
+    // OSThermalPressureLevel: 0 nominal, 1 moderate, 2 heavy, 3 trapping, 4 sleeping.
+    pub fn raw_level(&self) -> Option<u64> {
+        let mut state = 0;
+        if unsafe { notify_get_state(self.token, &mut state) } == 0 { Some(state) } else { None }
+    }
+
+    // The level as NSProcessInfo.ThermalState names it, with its 0..3 rank for thresholds.
+    pub fn level(&self) -> Option<(&'static str, u8)> {
+        self.raw_level().map(level)
+    }
+}
+
+// A raw notify state (or a recorded rank) as (name, rank).
+pub fn level(raw: u64) -> (&'static str, u8) {
+    match raw {
+        0 => ("nominal", 0),
+        1 => ("fair", 1),
+        2 => ("serious", 2),
+        _ => ("critical", 3),
+    }
+}
@@ -0,0 +1,81 @@
+// Throttle onset for `stream`: a throttle episode starts when any of these holds and ends
+// when none does, giving one `throttle_start` / `throttle_end` event pair per episode.
+//   thermal_pressure    macOS reports serious or critical pressure (see thermal.rs)
+//   frequency_collapse  a busy P-cluster runs below 60% of the highest clock it reached busy
+//   ecore_migration     work that was on the P-clusters is now on saturated E-cores with the
+//                       P-clusters idle
+// The last two come from powermetrics' cpu_power sampler, so they need powermetrics running
+// and update at its pace (every 5th sample).
+
+use crate::sample::Cluster;
+use std::time::Instant;
+
+// Residency (%) that counts as busy / idle / saturated
+const BUSY_PCT: f64 = 50.0;
+const IDLE_PCT: f64 = 10.0;
+const SATURATED_PCT: f64 = 90.0;
+const COLLAPSE_RATIO: f64 = 0.6;
+
+#[derive(Default)]
+pub struct Detector {
+    peak_p_mhz: f64,
+    p_was_busy: bool,
+    migrated: bool,
+    cluster_reasons: Vec<&'static str>,
+    // This sample's reasons, kept so an episode lasting hours doesn't allocate them every sample
+    reasons: Vec<&'static str>,
+    // Start of the current episode and every reason seen during it
+    active: Option<(Instant, Vec<&'static str>)>,
+}
+
+impl Detector {
+    // `clusters` is Some only when powermetrics produced a fresh reading this sample.
+    // `context` builds extra JSON members (readings, top processes) for the start event; it only
+    // runs when an episode starts.
+    pub fn update(&mut self, pressure: Option<(&str, u8)>, clusters: Option<&[Cluster]>, context: impl FnOnce() -> String) -> Option<String> {
+        if let Some(clusters) = clusters.filter(|c| !c.is_empty()) { self.observe(clusters); }
+        let reasons = &mut self.reasons;
+        reasons.clear();
+        if pressure.is_some_and(|(_, rank)| rank >= 2) { reasons.push("thermal_pressure"); }
+        reasons.extend(self.cluster_reasons.iter().copied());
+        match (&mut self.active, reasons.is_empty()) {
+            (None, false) => {
+                let context = context();
+                let event = format!("{{\"type\":\"throttle_start\",\"reasons\":[{}],\"thermal_pressure\":{},\"clusters\":[{}]{}{}}}",
+                    quoted(reasons), pressure.map(|(n, _)| format!("\"{}\"", n)).unwrap_or_else(|| "null".to_string()),
+                    clusters.unwrap_or_default().iter().map(|c| format!("{{\"name\":\"{}\",\"freq_mhz\":{:.0},\"active_pct\":{:.1}}}", c.name, c.freq_mhz, c.active_pct)).collect::<Vec<_>>().join(","),
+                    if context.is_empty() { "" } else { "," }, context);
+                self.active = Some((Instant::now(), reasons.clone()));
+                Some(event)
+            }
+            (Some((_, seen)), false) => {
+                for r in reasons.iter() { if !seen.contains(r) { seen.push(r); } }
+                None
+            }
+            (Some((started, seen)), true) => {
+                let event = format!("{{\"type\":\"throttle_end\",\"duration_s\":{:.1},\"reasons\":[{}]}}", started.elapsed().as_secs_f64(), quoted(seen));
+                self.active = None;
+                Some(event)
+            }
+            (None, true) => None,
+        }
+    }
+
+    fn observe(&mut self, clusters: &[Cluster]) {
+        let (p, e): (Vec<&Cluster>, Vec<&Cluster>) = clusters.iter().partition(|c| c.is_performance());
+        let busy_p: Vec<&&Cluster> = p.iter().filter(|c| c.active_pct >= BUSY_PCT).collect();
+        let collapsed = busy_p.iter().any(|c| self.peak_p_mhz > 0.0 && c.freq_mhz < self.peak_p_mhz * COLLAPSE_RATIO);
+        self.peak_p_mhz = busy_p.iter().map(|c| c.freq_mhz).fold(self.peak_p_mhz, f64::max);
+        let e_saturated = !e.is_empty() && e.iter().all(|c| c.active_pct >= SATURATED_PCT);
+        let p_idle = p.iter().all(|c| c.active_pct <= IDLE_PCT);
+        self.migrated = e_saturated && p_idle && (self.migrated || self.p_was_busy);
+        self.p_was_busy = !busy_p.is_empty();
+        self.cluster_reasons.clear();
+        if collapsed { self.cluster_reasons.push("frequency_collapse"); }
+        if self.migrated { self.cluster_reasons.push("ecore_migration"); }
+    }
+}
+
+fn quoted(reasons: &[&str]) -> String {
+    reasons.iter().map(|r| format!("\"{}\"", r)).collect::<Vec<_>>().join(",")
+}
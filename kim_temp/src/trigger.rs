@@ -0,0 +1,80 @@
+// `stream --trigger 'power_w > 20'`: record only around what matters. Every sample is still taken
+// at the full rate, but a line is written only while the condition holds (same syntax as
+// --alert, `for DURATION` included) and for --post-trigger seconds after it stops. The
+// --pre-trigger seconds before it started wait in a ring buffer and are written first, so a
+// spike comes with its run-up; hours of idle write nothing.
+
+use crate::alerts::Rule;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PRE: Duration = Duration::from_secs(5);
+pub const DEFAULT_POST: Duration = Duration::from_secs(10);
+
+pub struct Trigger {
+    rule: Rule,
+    post: Duration,
+    // The condition has held since
+    since: Option<Instant>,
+    // Last sample it held for long enough
+    last_met: Option<Instant>,
+    recording: bool,
+}
+
+impl Trigger {
+    pub fn parse(when: &str, post: Duration) -> Result<Trigger, String> {
+        let rule = Rule::parse(when).map_err(|e| e.replace("invalid alert", "invalid --trigger"))?;
+        Ok(Trigger { rule, post, since: None, last_met: None, recording: false })
+    }
+
+    pub fn metric(&self) -> &str {
+        &self.rule.metric
+    }
+
+    // Whether this sample is written, plus a `trigger` event when recording starts or stops.
+    pub fn update(&mut self, now: Instant, metrics: &[(&str, Option<f64>)]) -> (bool, Option<String>) {
+        if self.rule.holds(metrics) {
+            let since = *self.since.get_or_insert(now);
+            if now.duration_since(since) >= self.rule.hold { self.last_met = Some(now); }
+        } else {
+            self.since = None;
+        }
+        let recording = self.last_met.is_some_and(|t| now.duration_since(t) <= self.post);
+        if recording == self.recording { return (recording, None); }
+        self.recording = recording;
+        let value = metrics.iter().find(|(m, _)| *m == self.rule.metric).and_then(|(_, v)| *v);
+        (recording, Some(format!("{{\"type\":\"trigger\",\"state\":\"{}\",\"condition\":{},\"value\":{}}}",
+            if recording { "started" } else { "stopped" }, crate::json_str(&self.rule.name), crate::json_num(value, 2))))
+    }
+}
+
+// The lines of the last `pre` seconds that weren't written.
+pub struct Ring {
+    pre: Duration,
+    lines: VecDeque<(Instant, String)>,
+}
+
+impl Ring {
+    pub fn new(pre: Duration) -> Ring {
+        Ring { pre, lines: VecDeque::new() }
+    }
+
+    // Keeps a line back; the oldest give their Strings to new ones, so a long idle stretch
+    // allocates nothing once the ring is full.
+    pub fn hold(&mut self, now: Instant, line: &str) {
+        if self.pre.is_zero() { return; }
+        let mut slot = match self.lines.front() {
+            Some((t, _)) if now.duration_since(*t) > self.pre => self.lines.pop_front().map(|(_, s)| s).unwrap_or_default(),
+            _ => String::new(),
+        };
+        while self.lines.front().is_some_and(|(t, _)| now.duration_since(*t) > self.pre) { self.lines.pop_front(); }
+        slot.clear();
+        slot.push_str(line);
+        self.lines.push_back((now, slot));
+    }
+
+    // The held lines, oldest first, emptying the ring.
+    pub fn drain(&mut self) -> Vec<String> {
+        self.lines.drain(..).map(|(_, s)| s).collect()
+    }
+}
@@ -0,0 +1,57 @@
+// ULIDs for stream samples, so at-least-once pipelines (webhook retries, MQTT, log shippers)
+// can deduplicate. 48-bit millisecond timestamp + 80 random bits, Crockford base32; IDs
+// generated within the same millisecond increment the random part, so they stay sortable.
+
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+// One ID, kept as its 26 characters so a sample's ID is formatted without a heap allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ulid([u8; 26]);
+
+impl std::fmt::Display for Ulid {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(std::str::from_utf8(&self.0).unwrap_or_default())
+    }
+}
+
+pub struct Generator {
+    urandom: Option<std::fs::File>,
+    last_ms: u64,
+    last_rand: u128,
+}
+
+impl Default for Generator {
+    fn default() -> Self { Generator::new() }
+}
+
+impl Generator {
+    pub fn new() -> Generator {
+        Generator { urandom: std::fs::File::open("/dev/urandom").ok(), last_ms: 0, last_rand: 0 }
+    }
+
+    fn random80(&mut self) -> u128 {
+        let mut buf = [0u8; 16];
+        let filled = self.urandom.as_mut().is_some_and(|f| f.read_exact(&mut buf[6..]).is_ok());
+        if !filled {
+            // No /dev/urandom: fall back to clock nanos mixed with the pid
+            let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+            buf[6..].copy_from_slice(&(nanos ^ ((std::process::id() as u128) << 64)).to_be_bytes()[6..]);
+        }
+        u128::from_be_bytes(buf)
+    }
+
+    pub fn next(&mut self) -> Ulid {
+        let ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64 & ((1 << 48) - 1);
+        let rand = if ms == self.last_ms { (self.last_rand + 1) & ((1 << 80) - 1) } else { self.random80() };
+        self.last_ms = ms;
+        self.last_rand = rand;
+        let value = ((ms as u128) << 80) | rand;
+        // 26 chars x 5 bits = 130 bits; the top 2 are always zero
+        let mut id = [0u8; 26];
+        for (n, c) in id.iter_mut().enumerate() { *c = ALPHABET[((value >> ((25 - n) * 5)) & 0x1f) as usize]; }
+        Ulid(id)
+    }
+}
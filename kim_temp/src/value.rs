@@ -0,0 +1,167 @@
+// Typed SMC values. Every key is decoded by the data type the SMC declares for it, so fan
+// speeds (fpe2), voltages (sp4b), flags and counters read as what they are; a fixed-type float
+// read only knows flt/sp78/fpe2 and fails (or panics) on anything else. All reads by key name
+// go through `read` (one shared connection, each key's type looked up once, panics contained).
+
+use crate::smcraw::{self, KeyInfo};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmcValue {
+    // flt, ioft, and the 16-bit fixed-point fpXY/spXY types
+    Float(f64),
+    // ui8–ui64 and si8–si64 (ui64 values above i64::MAX don't occur in practice)
+    Int(i64),
+    Flag(bool),
+    // ch8* (NULs trimmed) and char
+    Text(String),
+    // hex_
+    Hex(Vec<u8>),
+    // Structs such as {fds, and anything not listed above
+    Bytes(Vec<u8>),
+}
+
+impl SmcValue {
+    // `data_type` as the SMC reports it, e.g. "flt ", "sp78", "ui16". Bytes shorter than the
+    // type needs decode as Bytes rather than failing.
+    pub fn decode(data_type: &str, b: &[u8]) -> SmcValue {
+        let int = |n: usize, signed: bool| -> Option<i64> {
+            let bytes = b.get(..n)?;
+            let u = bytes.iter().fold(0u64, |acc, x| acc << 8 | *x as u64);
+            Some(if signed { (u << (64 - 8 * n)) as i64 >> (64 - 8 * n) } else { u as i64 })
+        };
+        // 16-bit fixed point named by its integer and fraction bit counts in hex: fp88 is
+        // unsigned 8.8, fp2e unsigned 2.14, fpe2 unsigned 14.2, sp78 signed (sign + 7).8
+        let frac_bits = data_type.get(3..4).and_then(|d| u32::from_str_radix(d, 16).ok());
+        let decoded = match (data_type.get(..2), frac_bits) {
+            (Some("fp"), Some(f)) => int(2, false).map(|v| SmcValue::Float(v as f64 / (1u32 << f) as f64)),
+            (Some("sp"), Some(f)) => int(2, true).map(|v| SmcValue::Float(v as f64 / (1u32 << f) as f64)),
+            _ => match data_type {
+                // Apple Silicon floats are little-endian
+                "flt " => b.get(..4).and_then(|f| f.try_into().ok()).map(|f| SmcValue::Float(f32::from_le_bytes(f) as f64)),
+                // IOFixed 48.16, little-endian like flt
+                "ioft" => b.get(..8).and_then(|f| f.try_into().ok()).map(|f| SmcValue::Float(u64::from_le_bytes(f) as f64 / 65536.0)),
+                "ui8 " => int(1, false).map(SmcValue::Int),
+                "ui16" => int(2, false).map(SmcValue::Int),
+                "ui32" => int(4, false).map(SmcValue::Int),
+                "ui64" => int(8, false).map(SmcValue::Int),
+                "si8 " => int(1, true).map(SmcValue::Int),
+                "si16" => int(2, true).map(SmcValue::Int),
+                "si32" => int(4, true).map(SmcValue::Int),
+                "si64" => int(8, true).map(SmcValue::Int),
+                "flag" => b.first().map(|v| SmcValue::Flag(*v != 0)),
+                "ch8*" | "char" => Some(SmcValue::Text(String::from_utf8_lossy(b).trim_end_matches('\0').to_string())),
+                "hex_" => Some(SmcValue::Hex(b.to_vec())),
+                _ => None,
+            },
+        };
+        decoded.unwrap_or_else(|| SmcValue::Bytes(b.to_vec()))
+    }
+
+    pub fn from_info(info: &KeyInfo, b: &[u8]) -> SmcValue {
+        SmcValue::decode(&info.data_type, b)
+    }
+
+    // Numbers and flags (as 0/1); None for text and bytes.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            SmcValue::Float(v) => Some(*v),
+            SmcValue::Int(v) => Some(*v as f64),
+            SmcValue::Flag(v) => Some(*v as u8 as f64),
+            SmcValue::Text(_) | SmcValue::Hex(_) | SmcValue::Bytes(_) => None,
+        }
+    }
+
+    // For JSON: numbers (null when not finite) and flags as themselves, text as a string, hex_
+    // and other bytes as one hex string.
+    pub fn to_json(&self) -> String {
+        let hex = |b: &[u8]| b.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+        match self {
+            SmcValue::Float(v) if v.is_finite() => v.to_string(),
+            SmcValue::Float(_) => "null".to_string(),
+            SmcValue::Int(v) => v.to_string(),
+            SmcValue::Flag(v) => v.to_string(),
+            SmcValue::Text(s) => crate::json_str(s),
+            SmcValue::Hex(b) | SmcValue::Bytes(b) => crate::json_str(&hex(b)),
+        }
+    }
+}
+
+// For people: whole floats without a fraction, text quoted, hex_ as 0x…, other bytes spaced.
+impl fmt::Display for SmcValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |b: &[u8], sep: &str| b.iter().map(|x| format!("{:02x}", x)).collect::<Vec<_>>().join(sep);
+        match self {
+            SmcValue::Float(v) if v.fract() == 0.0 && v.abs() < 1e15 => write!(f, "{}", *v as i64),
+            SmcValue::Float(v) => write!(f, "{}", v),
+            SmcValue::Int(v) => write!(f, "{}", v),
+            SmcValue::Flag(v) => write!(f, "{}", v),
+            SmcValue::Text(s) => write!(f, "{:?}", s),
+            SmcValue::Hex(b) => write!(f, "0x{}", hex(b, "")),
+            SmcValue::Bytes(b) => write!(f, "{}", hex(b, " ")),
+        }
+    }
+}
+
+// The inverse of `decode`: `text` as the bytes of `data_type`, for `write`. Numbers must fit
+// the type exactly (no silent wrap or clamp); ch8* is text, hex_ and other types take hex bytes
+// ("0x0a1b", "0a 1b"). The caller checks the result against the key's size.
+pub fn encode(data_type: &str, text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    let bad = || format!("{:?} is not a valid {} value", text, data_type.trim());
+    let int = |n: usize, signed: bool| -> Result<Vec<u8>, String> {
+        let v: i128 = match text.strip_prefix("0x") { Some(h) => i128::from_str_radix(h, 16), None => text.parse() }.map_err(|_| bad())?;
+        let (lo, hi) = if signed { (-(1i128 << (8 * n - 1)), (1i128 << (8 * n - 1)) - 1) } else { (0, (1i128 << (8 * n)) - 1) };
+        if v < lo || v > hi { return Err(format!("{} is out of range for {} ({}..={})", v, data_type.trim(), lo, hi)); }
+        Ok(v.to_be_bytes()[16 - n..].to_vec())
+    };
+    let float = || text.parse::<f64>().ok().filter(|v| v.is_finite()).ok_or_else(bad);
+    let frac_bits = data_type.get(3..4).and_then(|d| u32::from_str_radix(d, 16).ok());
+    match (data_type.get(..2), frac_bits) {
+        (Some(p @ ("fp" | "sp")), Some(f)) => {
+            let scaled = (float()? * (1u32 << f) as f64).round();
+            let (lo, hi) = if p == "sp" { (i16::MIN as f64, i16::MAX as f64) } else { (0.0, u16::MAX as f64) };
+            if scaled < lo || scaled > hi { return Err(format!("{} is out of range for {}", text, data_type)); }
+            Ok(if p == "sp" { (scaled as i16).to_be_bytes().to_vec() } else { (scaled as u16).to_be_bytes().to_vec() })
+        }
+        _ => match data_type {
+            "flt " => Ok((float()? as f32).to_le_bytes().to_vec()),
+            "ioft" => {
+                let v = float()?;
+                if v < 0.0 || v * 65536.0 > u64::MAX as f64 { return Err(format!("{} is out of range for ioft", text)); }
+                Ok(((v * 65536.0).round() as u64).to_le_bytes().to_vec())
+            }
+            "ui8 " => int(1, false),
+            "ui16" => int(2, false),
+            "ui32" => int(4, false),
+            "ui64" => int(8, false),
+            "si8 " => int(1, true),
+            "si16" => int(2, true),
+            "si32" => int(4, true),
+            "si64" => int(8, true),
+            "flag" => match text { "1" | "true" => Ok(vec![1]), "0" | "false" => Ok(vec![0]), _ => Err(bad()) },
+            "ch8*" | "char" => Ok(text.as_bytes().to_vec()),
+            _ => {
+                let hex: String = text.trim_start_matches("0x").chars().filter(|c| !c.is_whitespace()).collect();
+                if hex.is_empty() || !hex.is_ascii() || !hex.len().is_multiple_of(2) { return Err(bad()); }
+                (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| bad())).collect()
+            }
+        },
+    }
+}
+
+pub fn read(key: &str) -> Result<SmcValue, String> {
+    let raw = smcraw::shared()?;
+    smcraw::guarded(key, || raw.read(key).map(|(info, bytes)| SmcValue::from_info(&info, &bytes)))
+}
+
+// A numeric key's value; None when it is missing, not numeric, or not finite. Allocation-free
+// for numeric types, so the per-sample power reads cost only the driver call.
+pub fn read_f64(key: &str) -> Option<f64> {
+    let raw = smcraw::shared().ok()?;
+    match smcraw::guarded(key, || raw.read_with(key, |t, b| SmcValue::decode(t, b).as_f64())) {
+        Ok(Some(v)) => crate::diag::finite(v, key),
+        Ok(None) => { crate::diag::trace!("{} is not a number", key); None }
+        Err(e) => { crate::diag::trace!("{}", e); None }
+    }
+}
@@ -0,0 +1,106 @@
+// Alert webhooks: POSTs a JSON payload to a URL via curl, retrying with exponential backoff
+// on a background thread so a slow or down endpoint never stalls sampling.
+
+#[cfg(feature = "config")]
+use serde::Deserialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(deny_unknown_fields))]
+pub struct Webhook {
+    pub url: String,
+    // minijinja template over the alert event (name, state, metric, value, threshold);
+    // the event JSON itself when unset
+    pub body: Option<String>,
+    #[cfg_attr(feature = "config", serde(default = "default_retries"))]
+    pub retries: u32,
+    #[cfg_attr(feature = "config", serde(default = "default_backoff_ms"))]
+    pub backoff_ms: u64,
+    #[cfg_attr(feature = "config", serde(default))]
+    pub headers: Vec<String>,
+}
+
+#[cfg(feature = "config")]
+fn default_retries() -> u32 { 3 }
+#[cfg(feature = "config")]
+fn default_backoff_ms() -> u64 { 1000 }
+
+impl Webhook {
+    // Checks the body template up front so a typo fails at startup rather than mid-incident.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(body) = &self.body {
+            #[cfg(feature = "templates")]
+            minijinja::Environment::new().template_from_str(body).map_err(|e| format!("invalid webhook body template: {}", e))?;
+            #[cfg(not(feature = "templates"))]
+            return Err(format!("webhook body template {:?} needs a build with --features templates", body));
+        }
+        Ok(())
+    }
+
+    // Renders the body for `event_json` and delivers it in the background.
+    pub fn send(&self, event_json: &str) {
+        let body = match &self.body {
+            None => event_json.to_string(),
+            #[cfg(feature = "templates")]
+            Some(tmpl) => {
+                let ctx: serde_json::Value = serde_json::from_str(event_json).unwrap_or_default();
+                match minijinja::Environment::new().render_str(tmpl, ctx) {
+                    Ok(b) => b,
+                    Err(e) => { crate::diag::warn!("webhook body render failed: {}", e); return; }
+                }
+            }
+            // Rejected by validate()
+            #[cfg(not(feature = "templates"))]
+            Some(_) => event_json.to_string(),
+        };
+        let hook = self.clone();
+        std::thread::spawn(move || {
+            let mut delay = Duration::from_millis(hook.backoff_ms);
+            for attempt in 0..=hook.retries {
+                match post(&hook, &body) {
+                    Ok(()) => return,
+                    Err(e) if attempt == hook.retries => crate::diag::warn!("webhook {} failed after {} attempts: {}", hook.url, attempt + 1, e),
+                    Err(e) => { crate::diag::info!("webhook {} attempt {} failed, retrying in {:?}: {}", hook.url, attempt + 1, delay, e); std::thread::sleep(delay); delay *= 2; }
+                }
+            }
+        });
+    }
+}
+
+// One delivery attempt; any non-2xx response counts as failure (curl -f).
+fn post(hook: &Webhook, body: &str) -> Result<(), String> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["-fsS", "--max-time", "10", "-X", "POST", "-H", "Content-Type: application/json", "--data-binary", "@-"]);
+    let headers = if hook.headers.is_empty() { None } else { Some(HeaderFile::write(&hook.headers)?) };
+    if let Some(file) = &headers { cmd.arg("-H").arg(format!("@{}", file.0.display())); }
+    let mut child = cmd.arg(&hook.url).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() { let _ = stdin.write_all(body.as_bytes()); }
+    let out = child.wait_with_output().map_err(|e| e.to_string())?;
+    if out.status.success() { Ok(()) } else { Err(String::from_utf8_lossy(&out.stderr).trim().to_string()) }
+}
+
+// The headers (often an `Authorization:` token) for `curl -H @FILE`, readable only by this user,
+// since curl's arguments are visible to everyone in `ps`. Removed when dropped.
+struct HeaderFile(std::path::PathBuf);
+
+impl HeaderFile {
+    fn write(headers: &[String]) -> Result<HeaderFile, String> {
+        use std::os::unix::fs::OpenOptionsExt;
+        static NEXT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!("kim_temp-webhook-{}-{}", std::process::id(), NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)));
+        let _ = std::fs::remove_file(&path);
+        let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+        let written = HeaderFile(path);
+        file.write_all((headers.join("\n") + "\n").as_bytes()).map_err(|e| format!("cannot write {}: {}", written.0.display(), e))?;
+        Ok(written)
+    }
+}
+
+impl Drop for HeaderFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
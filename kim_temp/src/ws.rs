@@ -0,0 +1,174 @@
+// `serve --ws ADDR`: the server's samples pushed over WebSocket (RFC 6455) to browser dashboards
+// and Electron/Tauri widgets, which can't reach the unix socket. A client subscribes by
+// connecting, naming a mode and its flags in the URL the way a socket request would:
+//   ws://localhost:8765/json?fields=cpu_temp,power_w&units=fahrenheit
+// and from then on gets one text frame per TTL with what that request would print (`/` alone is
+// `json`). Each client's frames come from the server's shared cache, so any number of them cost
+// one sample per TTL, and nothing is sampled while nobody is connected.
+// Any web page the user opens may connect to localhost, so a browser's Origin must be local
+// (localhost, 127.0.0.1, [::1] or a file) or listed with --ws-origin, and its Host must name this
+// server (against DNS rebinding); other handshakes get 403. Clients without an Origin (scripts,
+// native widgets) are always let in. With a token (--ws-token, KIM_TEMP_WS_TOKEN) every client
+// needs it too, as a bearer header or `?access_token=` (a browser's WebSocket can't send headers),
+// or gets 401. Frames from the client are read only for ping and close.
+
+use crate::http::{self, Request};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const TIMEOUT: Duration = Duration::from_secs(2);
+// A client's frames are pings and closes; anything that leaves more unread than this is dropped
+const MAX_PENDING: usize = 64 * 1024;
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+pub struct Options {
+    pub addr: SocketAddr,
+    // --ws-origin: browser origins let in besides the local ones; "*" for any
+    pub origins: Vec<String>,
+    // Some: every handshake needs it, as a bearer header or `?access_token=`
+    pub token: Option<String>,
+}
+
+// Binds ADDR and pushes to clients from a thread of its own. `answer` turns a client's request
+// (`/json?fields=cpu_temp`) into the text of one frame; an error refuses the handshake (400) or,
+// later, closes the connection.
+pub fn spawn<F>(opts: Options, every: Duration, answer: F) -> Result<(), String>
+where F: Fn(&Request) -> Result<String, String> + Send + Sync + 'static {
+    let (addr, open) = (opts.addr, opts.token.is_none());
+    http::listen(addr, move |conn| client(conn, every, &opts, &answer))?;
+    eprintln!("serve: WebSocket clients on ws://{}/{}", addr, if open { "" } else { " (token required)" });
+    Ok(())
+}
+
+fn client(mut conn: TcpStream, every: Duration, opts: &Options, answer: &dyn Fn(&Request) -> Result<String, String>) {
+    let _ = conn.set_write_timeout(Some(TIMEOUT));
+    let _ = conn.set_nodelay(true);
+    let Some(mut request) = Request::read(&conn) else { return };
+    if !handshake(&mut conn, &request, opts, answer) { return; }
+    let (mut frame, mut chunk, mut due, mut pending) = (Vec::new(), [0; 1024], Instant::now(), std::mem::take(&mut request.rest));
+    loop {
+        let now = Instant::now();
+        if now >= due {
+            let sent = match answer(&request) {
+                Ok(text) => send(&mut conn, &mut frame, TEXT, text.trim_end().as_bytes()),
+                // 1011: the server couldn't answer
+                Err(e) => { let _ = send(&mut conn, &mut frame, CLOSE, &[&1011u16.to_be_bytes()[..], &e.as_bytes()[..e.len().min(120)]].concat()); return; }
+            };
+            if sent.is_err() { return; }
+            due = now + every;
+            continue;
+        }
+        // Waiting for the next frame is reading the client's
+        let _ = conn.set_read_timeout(Some(due - now));
+        match conn.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(n) => pending.extend_from_slice(&chunk[..n]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(_) => return,
+        }
+        while let Some((opcode, payload)) = take_frame(&mut pending) {
+            match opcode {
+                // Echoing the status code back, as the close handshake asks
+                CLOSE => { let _ = send(&mut conn, &mut frame, CLOSE, &payload[..payload.len().min(2)]); return; }
+                PING if send(&mut conn, &mut frame, PONG, &payload).is_err() => return,
+                _ => {}
+            }
+        }
+        if pending.len() > MAX_PENDING { return; }
+    }
+}
+
+// Answers the upgrade request; false once the client has been refused.
+fn handshake(conn: &mut TcpStream, request: &Request, opts: &Options, answer: &dyn Fn(&Request) -> Result<String, String>) -> bool {
+    let upgrade = request.header("upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+    let refused = match (request.header("sec-websocket-key"), request.header("origin")) {
+        _ if request.method != "GET" => Some(("405 Method Not Allowed", "only GET".to_string())),
+        (None, _) if !upgrade => Some(("426 Upgrade Required", "this is a WebSocket endpoint".to_string())),
+        (None, _) => Some(("400 Bad Request", "no Sec-WebSocket-Key".to_string())),
+        _ if !http::allowed_host(request.header("host"), opts.addr) => Some(("403 Forbidden", format!("host {} isn't this server", request.header("host").unwrap_or("")))),
+        _ if !http::bearer(request, &mut request.params(), opts.token.as_deref()) => Some(("401 Unauthorized", "missing or wrong bearer token".to_string())),
+        (_, Some(o)) if !http::allowed_origin(o, &opts.origins) => Some(("403 Forbidden", format!("origin {} isn't allowed (serve --ws-origin)", o))),
+        (Some(key), _) => match answer(request) {
+            Err(e) => Some(("400 Bad Request", e)),
+            Ok(_) => {
+                let accept = base64(&sha1(format!("{}{}", key, GUID).as_bytes()));
+                return write!(conn, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept).is_ok();
+            }
+        },
+    };
+    if let Some((status, why)) = refused { http::reply(conn, status, "", "text/plain", &(why + "\n")); }
+    false
+}
+
+// One unfragmented frame; the server's are never masked. `frame` is the buffer, reused.
+fn send(conn: &mut TcpStream, frame: &mut Vec<u8>, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    frame.clear();
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= 0xffff => { frame.push(126); frame.extend_from_slice(&(n as u16).to_be_bytes()); }
+        n => { frame.push(127); frame.extend_from_slice(&(n as u64).to_be_bytes()); }
+    }
+    frame.extend_from_slice(payload);
+    conn.write_all(frame)
+}
+
+// The first whole frame in `buf`, removed from it: its opcode and unmasked payload. None until
+// all of it has arrived.
+fn take_frame(buf: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    let (opcode, masked) = (buf.first()? & 0x0f, buf.get(1)? & 0x80 != 0);
+    let (len, mut at) = match buf[1] & 0x7f {
+        126 => (u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as usize, 4),
+        127 => (usize::try_from(u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?)).ok()?, 10),
+        n => (n as usize, 2),
+    };
+    let mask: [u8; 4] = if masked { at += 4; buf.get(at - 4..at)?.try_into().ok()? } else { [0; 4] };
+    let end = at.checked_add(len)?;
+    let payload = buf.get(at..end)?.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m).collect();
+    buf.drain(..end);
+    Some((opcode, payload))
+}
+
+// For Sec-WebSocket-Accept, the only hashing the protocol needs
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 { msg.push(0); }
+    msg.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (w, b) in w.iter_mut().zip(block.chunks(4)) { *w = u32::from_be_bytes([b[0], b[1], b[2], b[3]]); }
+        for i in 16..80 { w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1); }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*w);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) { *h = h.wrapping_add(v); }
+    }
+    let mut out = [0; 20];
+    for (o, v) in out.chunks_mut(4).zip(h) { o.copy_from_slice(&v.to_be_bytes()); }
+    out
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 { out.push(if i <= chunk.len() { ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char } else { '=' }); }
+    }
+    out
+}